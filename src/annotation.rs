@@ -0,0 +1,89 @@
+//! Per-position annotation tracks, e.g. conservation or accessibility, written as extra columns
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use flate2::read::MultiGzDecoder;
+
+/// Magic number at the start of a bigWig file (little-endian)
+const BIGWIG_MAGIC: [u8; 4] = [0x26, 0xfc, 0x8f, 0x88];
+
+/// Values of a bedGraph track
+#[derive(Debug, Clone)]
+pub(crate) struct AnnotationTrack {
+    pub(crate) name: String,
+    /// chromosome -> (0-based start, end, value) sorted by start
+    intervals: HashMap<String, Vec<(i64, i64, f32)>>,
+}
+
+impl AnnotationTrack {
+    /// Parse `name=path` given to --annotate-track
+    pub(crate) fn from_arg(arg: &str) -> Result<Self, Box<dyn Error>> {
+        let (name, path) = arg.split_once('=').ok_or_else(|| format!("Expected name=path for an annotation track: {}", arg))?;
+        if name.is_empty() {
+            return Err(format!("Empty name of an annotation track: {}", arg).into());
+        }
+        Self::from_path(name, path)
+    }
+
+    /// Read a bedGraph file, gzip-compressed if the path ends with .gz.
+    /// bigWig files are detected and rejected, since they have to be converted by `bigWigToBedGraph` first.
+    pub(crate) fn from_path(name: &str, path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(path)?;
+        let mut magic = [0; 4];
+        if file.read(&mut magic)? == 4 && magic == BIGWIG_MAGIC {
+            return Err(format!("{}: bigWig is not supported; convert it into bedGraph with bigWigToBedGraph", path).into());
+        }
+        let file = File::open(path)?;
+        let input: Box<dyn Read> = if path.ends_with(".gz") { Box::new(MultiGzDecoder::new(file)) } else { Box::new(file) };
+        let mut intervals: HashMap<String, Vec<(i64, i64, f32)>> = HashMap::new();
+        for (i, line) in BufReader::new(input).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 4 {
+                return Err(format!("{}:{}: expected chromosome, start, end, and value", path, i + 1).into());
+            }
+            let parse_error = |e: &dyn std::fmt::Display| format!("{}:{}: {}", path, i + 1, e);
+            let start = fields[1].parse::<i64>().map_err(|e| parse_error(&e))?;
+            let end = fields[2].parse::<i64>().map_err(|e| parse_error(&e))?;
+            let value = fields[3].parse::<f32>().map_err(|e| parse_error(&e))?;
+            intervals.entry(fields[0].to_string()).or_default().push((start, end, value));
+        }
+        for chr_intervals in intervals.values_mut() {
+            chr_intervals.sort_by_key(|&(start, _, _)| start);
+        }
+        Ok(Self { name: name.to_string(), intervals })
+    }
+
+    /// Value at a 1-based position, or None if no interval covers it
+    pub(crate) fn value_at(&self, chr: &str, position: i64) -> Option<f32> {
+        let chr_intervals = self.intervals.get(chr)?;
+        let start = position - 1;
+        // the last interval starting at or before the position
+        let n = chr_intervals.partition_point(|&(s, _, _)| s <= start);
+        let &(_, end, value) = chr_intervals.get(n.checked_sub(1)?)?;
+        if start < end { Some(value) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_at1() {
+        let track = AnnotationTrack {
+            name: "phyloP".to_string(),
+            intervals: [("chrI".to_string(), vec![(0, 2, 1.5), (5, 6, -0.5)])].into_iter().collect(),
+        };
+        assert_eq!(track.value_at("chrI", 1), Some(1.5));
+        assert_eq!(track.value_at("chrI", 2), Some(1.5));
+        assert_eq!(track.value_at("chrI", 3), None);
+        assert_eq!(track.value_at("chrI", 6), Some(-0.5));
+        assert_eq!(track.value_at("chrII", 1), None);
+    }
+}
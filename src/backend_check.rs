@@ -0,0 +1,101 @@
+//! Consistency check between the CSV and HDF5 kinetics backends
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use crate::{collect_hdf5_ipd_summary_in_merged_occ, collect_ipd_summary_in_merged_occ, InputOptions, OutputOptions};
+use crate::manifest::Manifest;
+use crate::run_report::RunReport;
+
+/// Check if two output fields are the same, allowing a relative difference of numbers up to `tolerance`
+fn fields_match(a: &str, b: &str, tolerance: f64) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => (x - y).abs() <= tolerance * x.abs().max(y.abs()).max(1.0),
+        _ => false,
+    }
+}
+
+fn temporary_output_path(backend: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("collect_regional_kinetics.{}.{}.csv", std::process::id(), backend))
+}
+
+/// Compare two output CSV files record by record, and return the number of differing records.
+/// At most `max_reported` differences are printed to stderr.
+fn compare_outputs<P: AsRef<Path>>(csv_output: P, hdf5_output: P, tolerance: f64, max_reported: usize) -> Result<u64, Box<dyn Error>> {
+    let mut csv_reader = csv::Reader::from_path(csv_output)?;
+    let mut hdf5_reader = csv::Reader::from_path(hdf5_output)?;
+    if csv_reader.headers()? != hdf5_reader.headers()? {
+        eprintln!("[WARNING] Headers differ: CSV: {:?}, HDF5: {:?}", csv_reader.headers()?, hdf5_reader.headers()?);
+        return Ok(1);
+    }
+    let header = csv_reader.headers()?.clone();
+    let mut csv_records = csv_reader.records();
+    let mut hdf5_records = hdf5_reader.records();
+    let mut n_diff: u64 = 0;
+    // line 1 is the header
+    let mut line: u64 = 1;
+    loop {
+        line += 1;
+        let (csv_record, hdf5_record) = match (csv_records.next().transpose()?, hdf5_records.next().transpose()?) {
+            (None, None) => break,
+            (Some(_), None) | (None, Some(_)) => {
+                eprintln!("[WARNING] Outputs have different numbers of records (from line {})", line);
+                return Ok(n_diff + 1);
+            },
+            (Some(c), Some(h)) => (c, h),
+        };
+        let differing_fields = header.iter().zip(csv_record.iter().zip(hdf5_record.iter()))
+            .filter(|(_, (c, h))| !fields_match(c, h, tolerance))
+            .map(|(name, (c, h))| format!("{}: {} vs {}", name, c, h))
+            .collect::<Vec<_>>();
+        if !differing_fields.is_empty() {
+            n_diff += 1;
+            if n_diff <= max_reported as u64 {
+                eprintln!("[WARNING] Line {} differs (CSV vs HDF5): {}", line, differing_fields.join(", "));
+            }
+        }
+    }
+    Ok(n_diff)
+}
+
+/// Run both backends on the same inputs and compare their outputs.
+/// Return an error if any difference is found.
+pub(crate) fn compare_backends(kinetics_path: &str, kinetics_hdf5_path: &str, occ_path: &str, occ_width: i64, occ_extension: i64,
+    tolerance: f64, max_reported: usize) -> Result<(), Box<dyn Error>>
+{
+    let output_options = OutputOptions::default();
+    let mut manifest = Manifest::new();
+    let csv_output = temporary_output_path("csv");
+    let hdf5_output = temporary_output_path("hdf5");
+    let csv_output_str = csv_output.to_string_lossy().into_owned();
+    let hdf5_output_str = hdf5_output.to_string_lossy().into_owned();
+    let input_options = InputOptions::new(occ_path, occ_width, occ_extension);
+    let n_csv = collect_ipd_summary_in_merged_occ(&[kinetics_path], &input_options, &output_options, csv_output_str.as_str(), &mut manifest, &mut RunReport::new())?;
+    let n_hdf5 = collect_hdf5_ipd_summary_in_merged_occ(&[kinetics_hdf5_path], &input_options, &output_options, hdf5_output_str.as_str(), &mut manifest, &mut RunReport::new())?;
+    eprintln!("[INFO] Rows: CSV backend: {}, HDF5 backend: {}", n_csv, n_hdf5);
+    let result = compare_outputs(&csv_output, &hdf5_output, tolerance, max_reported);
+    std::fs::remove_file(&csv_output)?;
+    std::fs::remove_file(&hdf5_output)?;
+    match result? {
+        0 => {
+            eprintln!("[INFO] Outputs of the CSV and HDF5 backends are equivalent");
+            Ok(())
+        },
+        n => Err(format!("Outputs of the CSV and HDF5 backends differ in {} records", n).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_match_numbers() {
+        assert!(fields_match("chrI", "chrI", 0.0));
+        assert!(!fields_match("chrI", "chrII", 0.0));
+        assert!(fields_match("0.822", "0.82200003", 1e-6));
+        assert!(!fields_match("0.822", "0.823", 1e-6));
+    }
+}
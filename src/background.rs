@@ -0,0 +1,223 @@
+//! Background distribution of a kinetics field at random genomic positions, for standardizing collected values
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::Write;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::{IpdSummaryKey, IpdSummaryValue, TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::stats::{mean_and_variance, NA};
+
+/// How to sample the background
+#[derive(Debug, Clone)]
+pub(crate) struct BackgroundOptions {
+    /// Number of random positions with kinetics data
+    pub(crate) n: usize,
+    pub(crate) value_field: ValueField,
+    /// Standardize values with the background of the same base rather than all bases
+    pub(crate) by_base: bool,
+    pub(crate) seed: u64,
+}
+
+/// Chromosomes sorted by name with their lengths, the largest 1-based position of each chromosome in `positions`
+pub(crate) fn chromosome_lengths<'a, I: Iterator<Item = (&'a str, i64)>>(positions: I) -> Vec<(String, i64)> {
+    let mut lengths: BTreeMap<&str, i64> = BTreeMap::new();
+    for (chr, position) in positions {
+        let length = lengths.entry(chr).or_insert(0);
+        *length = (*length).max(position);
+    }
+    lengths.into_iter().map(|(chr, length)| (chr.to_string(), length)).collect()
+}
+
+/// Draw positions (and strands) uniformly over `chromosomes` until `n` of them have data by `value_of`,
+/// giving up after 100 draws per requested position. Positions can be drawn more than once.
+pub(crate) fn sample_positions<F>(chromosomes: &[(String, i64)], n: usize, rng: &mut StdRng, value_of: F) -> Vec<(IpdSummaryKey, IpdSummaryValue)>
+where
+    F: Fn(&IpdSummaryKey) -> Option<IpdSummaryValue>,
+{
+    let ends = chromosomes.iter().scan(0, |end, (_, length)| { *end += (*length).max(0); Some(*end) }).collect::<Vec<_>>();
+    let total = ends.last().copied().unwrap_or(0);
+    let mut sampled = Vec::with_capacity(n);
+    if total == 0 {
+        return sampled;
+    }
+    for _ in 0..n.saturating_mul(100) {
+        if sampled.len() >= n {
+            break;
+        }
+        let offset = rng.gen_range(0..total);
+        let i = ends.partition_point(|&end| end <= offset);
+        let start = if i == 0 { 0 } else { ends[i - 1] };
+        let key = IpdSummaryKey { refName: chromosomes[i].0.clone(), tpl: offset - start + 1, strand: rng.gen_range(0..2) };
+        if let Some(value) = value_of(&key) {
+            sampled.push((key, value));
+        }
+    }
+    sampled
+}
+
+fn mean_sd(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < 2 {
+        return None;
+    }
+    let (mean, variance) = mean_and_variance(values);
+    let sd = variance.sqrt();
+    if sd > 0.0 { Some((mean, sd)) } else { None }
+}
+
+/// Numbers of positions drawn from each chromosome in proportion to the numbers of occurrences on them
+fn matched_counts(chromosomes: &[(String, i64)], occ_chromosomes: &[&str], n: usize) -> Vec<usize> {
+    let mut occ_counts: HashMap<&str, usize> = HashMap::new();
+    for chr in occ_chromosomes {
+        *occ_counts.entry(chr).or_insert(0) += 1;
+    }
+    let total = occ_chromosomes.len().max(1) as f64;
+    chromosomes.iter().map(|(chr, _)| {
+        let count = occ_counts.get(chr.as_str()).copied().unwrap_or(0);
+        (n as f64 * count as f64 / total).round() as usize
+    }).collect()
+}
+
+/// Random positions written into a separate table
+#[derive(Debug, Clone)]
+pub(crate) struct BackgroundTable {
+    pub(crate) path: String,
+    pub(crate) n: usize,
+    /// Draw positions from each chromosome in proportion to occurrences on it rather than uniformly over the genome
+    pub(crate) per_chromosome: bool,
+    pub(crate) seed: u64,
+}
+
+impl BackgroundTable {
+    /// Write a CSV of kinetics at random positions with data of each sample (with a sample column for `sample_names`),
+    /// sampled with `value_of` a sample at a position, and return the number of rows
+    pub(crate) fn write<F>(&self, chromosomes: &[(String, i64)], occ_chromosomes: &[&str], sample_names: &[String], n_samples: usize,
+        value_of: F, manifest: &mut Manifest) -> Result<u64, Box<dyn Error>>
+    where
+        F: Fn(usize, &IpdSummaryKey) -> Option<IpdSummaryValue>,
+    {
+        let counts = if self.per_chromosome { Some(matched_counts(chromosomes, occ_chromosomes, self.n)) } else { None };
+        let mut n_rows = 0;
+        write_atomically(&self.path, |output| {
+            let sample_header = if sample_names.is_empty() { "" } else { "sample," };
+            writeln!(output, "{}ref_chr,ref_position,ref_strand,base,score,tMean,tErr,modelPrediction,ipdRatio,coverage,frac", sample_header)?;
+            for sample in 0..n_samples {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                let sampled = match counts.as_ref() {
+                    Some(counts) => chromosomes.iter().zip(counts.iter()).flat_map(|(chromosome, &n)| {
+                        sample_positions(std::slice::from_ref(chromosome), n, &mut rng, |key| value_of(sample, key))
+                    }).collect(),
+                    None => sample_positions(chromosomes, self.n, &mut rng, |key| value_of(sample, key)),
+                };
+                let sample_field = sample_names.get(sample).map_or_else(String::new, |name| format!("{},", name));
+                for (key, value) in sampled.iter() {
+                    writeln!(output, "{}{},{},{},{},{},{},{},{},{},{},{}", sample_field, key.refName, key.tpl, key.strand,
+                        value.base.map_or_else(|| "N".to_string(), |base| base.to_string()), value.score, value.tMean, value.tErr,
+                        value.modelPrediction, value.ipdRatio, value.coverage, value.frac.map_or_else(|| NA.to_string(), |frac| frac.to_string()))?;
+                }
+                n_rows += sampled.len() as u64;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(&self.path, "background", n_rows);
+        eprintln!("[INFO] Wrote {} background positions into {}", n_rows, self.path);
+        Ok(n_rows)
+    }
+}
+
+/// Background of each of `n_samples` samples, sampled with `value_of` a sample at a position
+pub(crate) fn sample_backgrounds<F>(options: &BackgroundOptions, chromosomes: &[(String, i64)], n_samples: usize, value_of: F) -> Vec<Background>
+where
+    F: Fn(usize, &IpdSummaryKey) -> Option<IpdSummaryValue>,
+{
+    (0..n_samples).map(|sample| {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let sampled = sample_positions(chromosomes, options.n, &mut rng, |key| value_of(sample, key));
+        if sampled.len() < options.n {
+            eprintln!("[WARNING] Only {} of {} background positions have kinetics data", sampled.len(), options.n);
+        }
+        let background = Background::new(options, &sampled);
+        match background.overall() {
+            Some((mean, sd)) => eprintln!("[INFO] Background of {} positions: mean {}, sd {}", sampled.len(), mean, sd),
+            None => eprintln!("[WARNING] Background values do not vary; zscore_background is NA"),
+        }
+        background
+    }).collect()
+}
+
+/// Mean and standard deviation of background values, overall and per base
+#[derive(Debug)]
+pub(crate) struct Background {
+    value_field: ValueField,
+    overall: Option<(f64, f64)>,
+    by_base: Option<HashMap<char, (f64, f64)>>,
+}
+
+impl Background {
+    pub(crate) fn new(options: &BackgroundOptions, sampled: &[(IpdSummaryKey, IpdSummaryValue)]) -> Self {
+        let values = sampled.iter()
+            .filter_map(|(_, value)| value.value_of(options.value_field).filter(|v| v.is_finite()).map(|v| (value.base, v as f64)))
+            .collect::<Vec<_>>();
+        let overall = mean_sd(&values.iter().map(|&(_, v)| v).collect::<Vec<_>>());
+        let by_base = if options.by_base {
+            let mut base_values: HashMap<char, Vec<f64>> = HashMap::new();
+            for &(base, v) in values.iter() {
+                if let Some(base) = base {
+                    base_values.entry(base.to_ascii_uppercase()).or_default().push(v);
+                }
+            }
+            Some(base_values.into_iter().filter_map(|(base, values)| mean_sd(&values).map(|stats| (base, stats))).collect())
+        } else {
+            None
+        };
+        Self { value_field: options.value_field, overall, by_base }
+    }
+
+    /// (mean, sd) of all sampled values, if at least two of them vary
+    pub(crate) fn overall(&self) -> Option<(f64, f64)> {
+        self.overall
+    }
+
+    /// z-score of the value of a row against the background (of its base, by ipdSummary's base on the strand of the data);
+    /// None for rows without kinetics data or bases without enough background values
+    pub(crate) fn zscore(&self, row: &TargetIpdRich) -> Option<f32> {
+        if row.missing {
+            return None;
+        }
+        let value = row.value_of(self.value_field).filter(|v| v.is_finite())?;
+        let (mean, sd) = match self.by_base.as_ref() {
+            Some(by_base) => *by_base.get(&row.base?.to_ascii_uppercase())?,
+            None => self.overall?,
+        };
+        Some(((value as f64 - mean) / sd) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_positions1() {
+        let chromosomes = chromosome_lengths([("chr2", 5), ("chr1", 3), ("chr2", 10)].into_iter());
+        assert_eq!(chromosomes, [("chr1".to_string(), 3), ("chr2".to_string(), 10)]);
+        let mut rng = StdRng::seed_from_u64(1);
+        // data only on the plus strand of chr1
+        let sampled = sample_positions(&chromosomes, 20, &mut rng, |key| {
+            if key.refName == "chr1" && key.strand == 0 { Some(IpdSummaryValue { tMean: key.tpl as f32, ..IpdSummaryValue::default() }) } else { None }
+        });
+        assert_eq!(sampled.len(), 20);
+        assert!(sampled.iter().all(|(key, _)| (1..=3).contains(&key.tpl)));
+        let options = BackgroundOptions { n: 20, value_field: ValueField::TMean, by_base: false, seed: 1 };
+        let (mean, sd) = Background::new(&options, &sampled).overall().unwrap();
+        assert!((1.0..=3.0).contains(&mean) && sd > 0.0);
+    }
+
+    #[test]
+    fn matched_counts1() {
+        let chromosomes = [("chr1".to_string(), 100), ("chr2".to_string(), 100), ("chr3".to_string(), 100)];
+        assert_eq!(matched_counts(&chromosomes, &["chr1", "chr3", "chr3", "chr3"], 100), [25, 0, 75]);
+    }
+}
@@ -0,0 +1,78 @@
+//! Averaging of rows within fixed-size bins of relative positions, for profiles of wide regions
+
+use crate::TargetIpdRich;
+
+/// Mean of present values; None if no value is present
+fn mean<I: Iterator<Item = Option<f32>>>(values: I) -> Option<f32> {
+    let (sum, n) = values.flatten().fold((0.0, 0), |(sum, n), v| (sum + v as f64, n + 1));
+    if n == 0 { None } else { Some((sum / n as f64) as f32) }
+}
+
+/// Average rows of a bin on one relative strand into the row of its first base.
+/// Kinetics fields are means over bases with data (missing if no base has data); base and context are dropped.
+fn merge_bin(bin: &[&TargetIpdRich]) -> TargetIpdRich {
+    let first = bin[0];
+    let present = bin.iter().filter(|row| !row.missing).copied().collect::<Vec<_>>();
+    if present.is_empty() {
+        return TargetIpdRich { base: None, context: None, ..first.clone() };
+    }
+    let mean_of = |field: fn(&TargetIpdRich) -> f32| mean(present.iter().map(|row| Some(field(row)))).expect("present rows");
+    let mean_opt = |field: fn(&TargetIpdRich) -> Option<f32>| mean(present.iter().map(|row| field(row)));
+    TargetIpdRich {
+        value: mean_of(|row| row.value),
+        base: None,
+        score: mean_of(|row| row.score as f32).round() as u32,
+        tErr: mean_of(|row| row.tErr),
+        modelPrediction: mean_of(|row| row.modelPrediction),
+        ipdRatio: mean_of(|row| row.ipdRatio),
+        coverage: mean_of(|row| row.coverage as f32).round() as u32,
+        frac: mean_opt(|row| row.frac),
+        fracLow: mean_opt(|row| row.fracLow),
+        fracUp: mean_opt(|row| row.fracUp),
+        pwMean: mean_opt(|row| row.pwMean),
+        pwErr: mean_opt(|row| row.pwErr),
+        context: None,
+        ipdRatio_control: mean_opt(|row| row.ipdRatio_control),
+        zscore_background: mean_opt(|row| row.zscore_background),
+        missing: false,
+        annotations: (0..first.annotations.len()).map(|k| mean(bin.iter().map(|row| row.annotations[k]))).collect(),
+        ..first.clone()
+    }
+}
+
+/// Rows of bins of `bin_size` consecutive relative positions on each relative strand, in the order of `rows`.
+/// The row of a bin has the position, label, region, and reference position of the first base of the bin;
+/// the last bin can be shorter.
+pub(crate) fn bin_rows(rows: Vec<TargetIpdRich>, bin_size: i64) -> Vec<TargetIpdRich> {
+    let mut bins: Vec<(i64, char, Vec<&TargetIpdRich>)> = Vec::new();
+    for row in rows.iter() {
+        let bin = (row.position - 1) / bin_size;
+        match bins.iter_mut().rev().find(|(b, strand, _)| *b == bin && *strand == row.strand) {
+            Some((_, _, members)) => members.push(row),
+            None => bins.push((bin, row.strand, vec![row])),
+        }
+    }
+    bins.iter().map(|(_, _, members)| merge_bin(members)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpdSummaryValue, RegionSpec};
+    use crate::tests::alternating_strand_rows;
+
+    #[test]
+    fn bin_rows1() {
+        let rows = alternating_strand_rows(10, &RegionSpec::new(1, 2), |j| {
+            let position = j / 2 + 1;
+            if position == 2 { None } else { Some(IpdSummaryValue { tMean: position as f32, coverage: 10, ..IpdSummaryValue::default() }) }
+        });
+        let binned = bin_rows(rows, 2);
+        assert_eq!(binned.len(), 6);
+        assert_eq!(binned.iter().map(|row| (row.position, row.strand)).collect::<Vec<_>>(), [(1, '+'), (1, '-'), (3, '+'), (3, '-'), (5, '+'), (5, '-')]);
+        // position 2 has no data
+        assert_eq!(binned[0].value, 1.0);
+        assert_eq!(binned[2].value, 3.5);
+        assert_eq!(binned[4].ref_position, 105);
+    }
+}
@@ -0,0 +1,80 @@
+//! Blacklisted intervals (e.g. the ENCODE blacklist) to drop occurrences in repeat or artifact regions
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Disjoint 0-based half-open intervals sorted by start on each chromosome
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Blacklist {
+    intervals: HashMap<String, Vec<(i64, i64)>>,
+}
+
+impl Blacklist {
+    /// Read intervals from the first three columns of BED, merging overlapping ones
+    pub(crate) fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut intervals: HashMap<String, Vec<(i64, i64)>> = HashMap::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 3 {
+                return Err(format!("{}:{}: expected at least 3 tab-separated BED columns", path, i + 1).into());
+            }
+            let parse = |field: &str| field.trim().parse::<i64>().map_err(|e| format!("{}:{}: invalid coordinate: {}", path, i + 1, e));
+            intervals.entry(fields[0].to_string()).or_default().push((parse(fields[1])?, parse(fields[2])?));
+        }
+        Ok(Self::new(intervals))
+    }
+
+    fn new(mut intervals: HashMap<String, Vec<(i64, i64)>>) -> Self {
+        for chr_intervals in intervals.values_mut() {
+            chr_intervals.sort_unstable();
+            let mut merged: Vec<(i64, i64)> = Vec::with_capacity(chr_intervals.len());
+            for &(start, end) in chr_intervals.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *chr_intervals = merged;
+        }
+        Self { intervals }
+    }
+
+    /// Whether [start, end) on `chr` overlaps any interval
+    pub(crate) fn overlaps(&self, chr: &str, start: i64, end: i64) -> bool {
+        let intervals = match self.intervals.get(chr) {
+            Some(intervals) => intervals,
+            None => return false,
+        };
+        // the first interval ending after `start`
+        let i = intervals.partition_point(|&(_, interval_end)| interval_end <= start);
+        i < intervals.len() && intervals[i].0 < end
+    }
+
+    /// Number of merged intervals
+    pub(crate) fn n_intervals(&self) -> usize {
+        self.intervals.values().map(|intervals| intervals.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlaps1() {
+        let blacklist = Blacklist::new(HashMap::from([("chr1".to_string(), vec![(30, 40), (10, 20), (15, 25)])]));
+        assert_eq!(blacklist.n_intervals(), 2);
+        assert!(blacklist.overlaps("chr1", 0, 11));
+        assert!(!blacklist.overlaps("chr1", 0, 10));
+        assert!(!blacklist.overlaps("chr1", 25, 30));
+        assert!(blacklist.overlaps("chr1", 24, 26));
+        assert!(blacklist.overlaps("chr1", 0, 100));
+        assert!(!blacklist.overlaps("chr2", 0, 100));
+    }
+}
@@ -0,0 +1,93 @@
+//! BED export of putatively modified positions within target regions
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use crate::TargetIpdRich;
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+
+/// Thresholds for a position to be reported as a modification call
+#[derive(Debug, Clone)]
+pub(crate) struct CallThresholds {
+    pub(crate) min_score: u32,
+    pub(crate) min_ipd_ratio: f32,
+}
+
+#[derive(Debug)]
+struct Call {
+    label: String,
+    src: i64,
+    score: u32,
+    ipd_ratio: f32,
+    coverage: u32,
+}
+
+/// Positions passing thresholds, keyed by (chromosome, 1-based position, reference strand).
+/// A position shared by overlapping target regions is reported once, with the first region.
+pub(crate) struct CallsBed {
+    thresholds: CallThresholds,
+    calls: BTreeMap<(String, i64, u8), Call>,
+}
+
+impl CallsBed {
+    pub(crate) fn new(thresholds: CallThresholds) -> Self {
+        Self { thresholds, calls: BTreeMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, row: &TargetIpdRich) {
+        if row.coverage == 0 || row.score < self.thresholds.min_score || row.ipdRatio < self.thresholds.min_ipd_ratio {
+            return;
+        }
+        self.calls.entry((row.ref_chr.clone(), row.ref_position, row.ref_strand)).or_insert_with(|| Call {
+            label: row.label.clone(),
+            src: row.src,
+            score: row.score,
+            ipd_ratio: row.ipdRatio,
+            coverage: row.coverage,
+        });
+    }
+
+    /// Write calls in BED6 sorted by chromosome and position.
+    /// The BED score is the ipdSummary score capped at 1000, and the name holds the source region,
+    /// the position label, ipdRatio, and coverage.
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        write_atomically(path, |output| {
+            for ((chr, position, strand), call) in &self.calls {
+                let name = format!("src={};label={};ipdRatio={};coverage={}", call.src, call.label, call.ipd_ratio, call.coverage);
+                let strand = if *strand == 0 { '+' } else { '-' };
+                writeln!(output, "{}\t{}\t{}\t{}\t{}\t{}", chr, position - 1, position, name, call.score.min(1000), strand)?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(path, "calls_bed", self.calls.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpdSummaryValue, RegionSpec};
+    use crate::tests::{alternating_strand_rows, temporary_file};
+
+    #[test]
+    fn calls_bed1() {
+        let file = temporary_file("calls.bed");
+        // (score, ipdRatio) of rows at s1p, s1m, m1p, and m1m
+        let values = [(30, 1.5), (2000, 2.5), (10, 3.0), (20, 2.0)];
+        let rows = alternating_strand_rows(4, &RegionSpec::new(1, 1), |j| {
+            let (score, ipd_ratio) = values[j as usize];
+            Some(IpdSummaryValue { score, ipdRatio: ipd_ratio, tMean: 1.0, coverage: 10, ..Default::default() })
+        });
+        let mut calls = CallsBed::new(CallThresholds { min_score: 20, min_ipd_ratio: 2.0 });
+        rows.iter().for_each(|row| calls.add(row));
+        let path = file.path().to_str().unwrap();
+        calls.write(path, &mut Manifest::new()).unwrap();
+        // thresholds are inclusive, and the BED score is capped at 1000
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().collect::<Vec<_>>(), [
+            "chr\t100\t101\tsrc=1;label=s1m;ipdRatio=2.5;coverage=10\t1000\t-",
+            "chr\t101\t102\tsrc=1;label=m1m;ipdRatio=2;coverage=10\t20\t-",
+        ]);
+    }
+}
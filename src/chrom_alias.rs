@@ -0,0 +1,78 @@
+//! Mapping of chromosome names of occurrences to those of kinetics, e.g. `1` to `chr1` or RefSeq accessions to UCSC names
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use clap::ArgEnum;
+
+/// Conversion of the "chr" prefix of chromosome names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum ChrPrefix {
+    /// Prepend "chr" to names without it, e.g. 1 to chr1
+    Add,
+    /// Remove "chr" from names with it, e.g. chr1 to 1
+    Remove,
+}
+
+/// Explicit aliases, then the prefix conversion for names without an alias
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChromAlias {
+    aliases: HashMap<String, String>,
+    chr_prefix: Option<ChrPrefix>,
+}
+
+impl ChromAlias {
+    pub(crate) fn new(aliases: HashMap<String, String>, chr_prefix: Option<ChrPrefix>) -> Self {
+        Self { aliases, chr_prefix }
+    }
+
+    /// Read a tab-separated file whose first two columns are names in occ files and names in kinetics
+    pub(crate) fn read_aliases(path: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let mut aliases = HashMap::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(alias)) => aliases.insert(name.to_string(), alias.trim().to_string()),
+                _ => return Err(format!("{}:{}: expected an occ chromosome name and its kinetics name separated by a tab", path, i + 1).into()),
+            };
+        }
+        Ok(aliases)
+    }
+
+    /// Whether names are mapped at all
+    pub(crate) fn is_identity(&self) -> bool {
+        self.aliases.is_empty() && self.chr_prefix.is_none()
+    }
+
+    /// Kinetics chromosome name of an occ chromosome name
+    pub(crate) fn map(&self, name: &str) -> String {
+        if let Some(alias) = self.aliases.get(name) {
+            return alias.clone();
+        }
+        match self.chr_prefix {
+            Some(ChrPrefix::Add) if !name.starts_with("chr") => format!("chr{}", name),
+            Some(ChrPrefix::Remove) => name.strip_prefix("chr").unwrap_or(name).to_string(),
+            _ => name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map1() {
+        let alias = ChromAlias::new(HashMap::from([("NC_000001.11".to_string(), "chr1".to_string())]), Some(ChrPrefix::Add));
+        assert_eq!(alias.map("NC_000001.11"), "chr1");
+        assert_eq!(alias.map("2"), "chr2");
+        assert_eq!(alias.map("chrX"), "chrX");
+        assert_eq!(ChromAlias::new(HashMap::new(), Some(ChrPrefix::Remove)).map("chrM"), "M");
+        assert!(ChromAlias::default().is_identity());
+    }
+}
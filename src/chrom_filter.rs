@@ -0,0 +1,67 @@
+//! Selection of chromosomes by names or simple glob patterns, applied to occurrences and kinetics inputs
+
+/// Chromosomes matching any `include` pattern (all if none) and no `exclude` pattern
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChromFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+/// Whether `name` matches `pattern`, where '*' matches any string and '?' any single character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // position of the last '*' in the pattern and of the name where it started to match
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // let the last '*' match one more character
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+impl ChromFilter {
+    pub(crate) fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub(crate) fn includes(&self, chr: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, chr)))
+            && !self.exclude.iter().any(|pattern| glob_match(pattern, chr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match1() {
+        assert!(glob_match("chrUn*", "chrUn_KI270302v1"));
+        assert!(glob_match("chr?", "chrM"));
+        assert!(!glob_match("chr?", "chr10"));
+        assert!(glob_match("*_random", "chr1_KI270706v1_random"));
+        assert!(glob_match("chr*1*", "chr21"));
+        assert!(!glob_match("chrX", "chrX_alt"));
+        let filter = ChromFilter::new(vec!["chr*".to_string()], vec!["chrM".to_string(), "chrUn*".to_string()]);
+        assert!(filter.includes("chr1") && !filter.includes("chrM") && !filter.includes("chrUn_1") && !filter.includes("scaffold1"));
+        assert!(ChromFilter::default().includes("anything"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Chromosome lengths to reject motif occurrences beyond chromosome ends, and to clip extended regions at them
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use clap::ArgEnum;
+
+/// How extended target regions running past chromosome ends are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum ChromEndPolicy {
+    /// Keep the occurrence and write NA for positions beyond the chromosome ends
+    Clip,
+    /// Skip the occurrence as a rejected record
+    Skip,
+    /// Stop with an error
+    Error,
+}
+
+/// How extended target regions running before the first base of chromosomes are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum EdgePolicy {
+    /// Keep the occurrence without rows of positions before the first base
+    Clip,
+    /// Skip the occurrence as a rejected record
+    Skip,
+    /// Keep the occurrence and write NA for positions before the first base, flagged in an off_contig column
+    PadNa,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ChromSizes {
+    lengths: HashMap<String, i64>,
+}
+
+impl ChromSizes {
+    /// Read a tab-separated file whose first two columns are chromosome names and lengths,
+    /// e.g. UCSC chrom.sizes or a FASTA index (.fai)
+    pub(crate) fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lengths = HashMap::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (name, length) = match (fields.next(), fields.next()) {
+                (Some(name), Some(length)) => (name, length),
+                _ => return Err(format!("{}:{}: expected a chromosome name and its length separated by a tab", path, i + 1).into()),
+            };
+            let length = length.trim().parse::<i64>().map_err(|e| format!("{}:{}: invalid length: {}", path, i + 1, e))?;
+            lengths.insert(name.to_string(), length);
+        }
+        Ok(Self { lengths })
+    }
+
+    /// Length of a chromosome, if listed
+    pub(crate) fn length(&self, chr: &str) -> Option<i64> {
+        self.lengths.get(chr).copied()
+    }
+
+    /// Length of the longest chromosome
+    pub(crate) fn max_length(&self) -> Option<i64> {
+        self.lengths.values().copied().max()
+    }
+
+    /// Reason to reject an occurrence at a 0-based `start`, or None if it starts within the chromosome
+    pub(crate) fn rejection_reason(&self, chr: &str, start: i64) -> Option<String> {
+        match self.lengths.get(chr) {
+            None => Some("unknown_chromosome".to_string()),
+            Some(length) if start >= *length => Some(format!("start_beyond_chromosome_end(length={})", length)),
+            Some(_) if start < 0 => Some("negative_start".to_string()),
+            Some(_) => None,
+        }
+    }
+
+    /// Reason to reject a 0-based half-open extended region running past the ends of its chromosome, if it does
+    pub(crate) fn region_reason(&self, chr: &str, start: i64, end: i64) -> Option<String> {
+        self.lengths.get(chr).filter(|&&length| start < 0 || end > length)
+            .map(|length| format!("region_beyond_chromosome_end(length={})", length))
+    }
+
+    /// Whether a 1-based position is beyond the ends of a listed chromosome
+    pub(crate) fn is_off_contig(&self, chr: &str, position: i64) -> bool {
+        self.lengths.get(chr).is_some_and(|&length| position < 1 || position > length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejection_reason1() {
+        let sizes = ChromSizes { lengths: [("chrI".to_string(), 100)].into_iter().collect() };
+        assert_eq!(sizes.rejection_reason("chrI", 99), None);
+        assert_eq!(sizes.rejection_reason("chrI", 100).as_deref(), Some("start_beyond_chromosome_end(length=100)"));
+        assert_eq!(sizes.rejection_reason("chrII", 0).as_deref(), Some("unknown_chromosome"));
+        assert_eq!(sizes.region_reason("chrI", 0, 100), None);
+        assert_eq!(sizes.region_reason("chrI", -1, 10).as_deref(), Some("region_beyond_chromosome_end(length=100)"));
+        assert!(sizes.is_off_contig("chrI", 101) && sizes.is_off_contig("chrI", 0) && !sizes.is_off_contig("chrI", 100));
+    }
+}
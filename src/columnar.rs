@@ -0,0 +1,345 @@
+//! Columnar outputs built on Arrow record batches: Feather v2 (Arrow IPC file) and Parquet
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use arrow::array::{ArrayRef, BooleanArray, DictionaryArray, Float32Array, Int32Array, Int64Array, StringArray, UInt32Array, UInt8Array};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use crate::{Column, Layout, OutputOptions, TargetIpdRich, ValueField, ValueFormat};
+use crate::output::{Compression, OutputFormat};
+use crate::sink::{OutputSink, TargetKinetics};
+
+const STRANDS: [&str; 2] = ["+", "-"];
+/// Any other base character is stored as "N"
+const BASES: [&str; 5] = ["A", "C", "G", "T", "N"];
+
+fn dictionary_field(name: &str, nullable: bool, dict_id: i64) -> Field {
+    let data_type = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    Field::new_dict(name, data_type, nullable, dict_id, false)
+}
+
+/// Build a dictionary array over a fixed set of values.
+/// The Arrow IPC file format does not allow a dictionary to change between batches,
+/// so dictionaries must not depend on the rows of each batch.
+fn fixed_dictionary_array<S: AsRef<str>>(keys: Vec<Option<i32>>, values: &[S]) -> Result<ArrayRef, Box<dyn Error>> {
+    let keys = Int32Array::from(keys);
+    let values = StringArray::from(values.iter().map(|v| v.as_ref()).collect::<Vec<_>>());
+    Ok(Arc::new(DictionaryArray::<Int32Type>::try_new(&keys, &values)?))
+}
+
+fn strand_index(strand: char) -> i32 {
+    if strand == '+' { 0 } else { 1 }
+}
+
+fn base_index(base: char) -> i32 {
+    match base {
+        'A' => 0,
+        'C' => 1,
+        'G' => 2,
+        'T' => 3,
+        _ => 4,
+    }
+}
+
+/// Index of a region in the dictionary of `regions`, or an error for an unknown region
+fn region_index(regions: &[String], region: &str) -> Result<i32, String> {
+    regions.iter().position(|r| r == region).map(|i| i as i32).ok_or_else(|| format!("Unknown region: {}", region))
+}
+
+/// Field of a kinetics value, which is null where CSV writes NA, or for absent frac
+fn value_field(name: &str) -> Field {
+    Field::new(name, DataType::Float32, true)
+}
+
+/// Fields of a column of the long layout, named after it; the value column is expanded into `value_fields`
+fn long_fields(column: Column, value_fields: &[ValueField]) -> Vec<Field> {
+    let name = column.name();
+    let field = |data_type: DataType, nullable: bool| vec![Field::new(name, data_type, nullable)];
+    let dictionary = |nullable: bool, dict_id: i64| vec![dictionary_field(name, nullable, dict_id)];
+    match column {
+        Column::Position => field(DataType::Int64, false),
+        Column::Strand => dictionary(false, 0),
+        Column::Value => ValueField::column_names(name, value_fields).iter().map(|name| value_field(name.as_str())).collect(),
+        Column::Label => dictionary(false, 1),
+        Column::Src => field(DataType::Int64, false),
+        Column::Base => dictionary(true, 2),
+        Column::Score | Column::Coverage => field(DataType::UInt32, true),
+        Column::TErr | Column::ModelPrediction | Column::IpdRatio => vec![value_field(name)],
+        Column::RefChr => field(DataType::Utf8, false),
+        Column::RefPosition => field(DataType::Int64, false),
+        Column::RefStrand => field(DataType::UInt8, false),
+        Column::Region => dictionary(false, 3),
+        Column::Frac | Column::FracLow | Column::FracUp | Column::Log2ObsOverModel | Column::PwMean | Column::PwErr
+            | Column::GcTarget | Column::GcWindow | Column::IpdRatioControl | Column::ZscoreBackground => field(DataType::Float32, true),
+        Column::Distance => field(DataType::Int64, false),
+        Column::Context => field(DataType::Utf8, true),
+        Column::OccId => field(DataType::Utf8, false),
+        Column::StrandAssumed | Column::OffContig | Column::IsMissing => field(DataType::Boolean, false),
+        Column::OverlapCount => field(DataType::UInt32, false),
+    }
+}
+
+/// Fields of the schema in the order of `OutputOptions::header`
+fn schema_fields(output_options: &OutputOptions, labels: &[String]) -> Vec<Field> {
+    // a constant column is a dictionary of its values of samples
+    let mut fields = output_options.constant_columns().iter().enumerate().map(|(i, (name, _))| dictionary_field(name, false, 4 + i as i64)).collect::<Vec<_>>();
+    match output_options.layout {
+        Layout::Long => {
+            fields.extend(output_options.columns.iter().flat_map(|column| long_fields(*column, &output_options.value_fields)));
+            fields.extend(output_options.annotation_names.iter().map(|name| Field::new(name, DataType::Float32, true)));
+        },
+        Layout::Wide => {
+            fields.extend([
+                long_fields(Column::Src, &[]),
+                long_fields(Column::RefChr, &[]),
+                long_fields(Column::RefPosition, &[]),
+                long_fields(Column::RefStrand, &[]),
+            ].concat());
+            for label in labels.iter() {
+                fields.extend(ValueField::column_names(label, &output_options.value_fields).iter().map(|name| value_field(name.as_str())));
+            }
+        },
+    }
+    fields
+}
+
+/// Underlying writer of record batches
+enum ColumnarFile {
+    Ipc(FileWriter<File>),
+    Parquet(ArrowWriter<File>),
+}
+
+fn parquet_compression(compression: Compression) -> parquet::basic::Compression {
+    match compression {
+        Compression::None => parquet::basic::Compression::UNCOMPRESSED,
+        Compression::Gzip => parquet::basic::Compression::GZIP,
+        Compression::Zstd => parquet::basic::Compression::ZSTD,
+    }
+}
+
+/// Sink writing each batch of target regions as a record batch of a Feather or Parquet file
+pub(crate) struct ColumnarSink {
+    file: ColumnarFile,
+    schema: Arc<Schema>,
+    layout: Layout,
+    /// Column order of the long layout
+    columns: Vec<Column>,
+    value_fields: Vec<ValueField>,
+    /// Decides which kinetics fields are null, as they are NA in CSV
+    value_format: ValueFormat,
+    /// Position labels in the order of rows in a target region
+    labels: Vec<String>,
+    /// Number of rows at each position in a target region
+    n_strands: i32,
+    /// Region names in the order of positions
+    regions: Vec<String>,
+    /// Values of constant columns written first, e.g. `sample`, indexed by column and then by sample
+    constant_values: Vec<Vec<String>>,
+}
+
+impl ColumnarSink {
+    /// Provenance in `output_options` is embedded into the schema metadata of the file.
+    /// Parquet columns are compressed with the codec of `output_options.compression`.
+    pub(crate) fn create<P: AsRef<Path>>(path: P, occ_width: i64, occ_extension: i64, output_options: &OutputOptions) -> Result<Self, Box<dyn Error>> {
+        let layout = output_options.layout;
+        let region_spec = output_options.region_spec(occ_width, occ_extension);
+        let labels = region_spec.labels();
+        let constant_columns = output_options.constant_columns();
+        let fields = schema_fields(output_options, &labels);
+        let metadata = output_options.provenance.as_ref().map(|p| p.to_metadata()).unwrap_or_default();
+        let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+        let output = File::create(path)?;
+        let file = match output_options.format {
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder().set_compression(parquet_compression(output_options.compression)).build();
+                ColumnarFile::Parquet(ArrowWriter::try_new(output, schema.clone(), Some(properties))?)
+            },
+            _ => ColumnarFile::Ipc(FileWriter::try_new(output, &schema)?),
+        };
+        Ok(Self {
+            file,
+            schema,
+            layout,
+            columns: output_options.columns.clone(),
+            value_fields: output_options.value_fields.clone(),
+            value_format: output_options.value_format.clone(),
+            labels,
+            n_strands: region_spec.strands().len() as i32,
+            regions: region_spec.regions(),
+            constant_values: constant_columns.into_iter().map(|(_, value)| value).collect(),
+        })
+    }
+
+    /// A kinetics field of a row, or None if it is NA
+    fn value_of(&self, t: &TargetIpdRich, field: ValueField) -> Option<f32> {
+        t.value_of(field).filter(|_| !t.is_na(field.column(), &self.value_format))
+    }
+
+    /// Arrays of a column of the long layout, matching `long_fields`
+    fn long_column(&self, column: Column, rows: &[&TargetIpdRich]) -> Result<Vec<ArrayRef>, Box<dyn Error>> {
+        let unless_na = |t: &TargetIpdRich| !t.is_na(column, &self.value_format);
+        let array: ArrayRef = match column {
+            Column::Value => return Ok(self.value_fields.iter().map(|field| -> ArrayRef {
+                Arc::new(Float32Array::from(rows.iter().map(|t| self.value_of(t, *field)).collect::<Vec<_>>()))
+            }).collect()),
+            Column::Position => Arc::new(Int64Array::from(rows.iter().map(|t| t.position).collect::<Vec<_>>())),
+            Column::Strand => fixed_dictionary_array(rows.iter().map(|t| Some(strand_index(t.strand))).collect(), &STRANDS)?,
+            Column::Label => fixed_dictionary_array(rows.iter().map(|t| Some((t.position - 1) as i32 * self.n_strands + strand_index(t.strand))).collect(), &self.labels)?,
+            Column::Src => Arc::new(Int64Array::from(rows.iter().map(|t| t.src).collect::<Vec<_>>())),
+            Column::Base => fixed_dictionary_array(rows.iter().map(|t| t.base.map(base_index)).collect(), &BASES)?,
+            Column::Score => Arc::new(UInt32Array::from(rows.iter().map(|t| Some(t.score).filter(|_| unless_na(t))).collect::<Vec<_>>())),
+            Column::TErr => Arc::new(Float32Array::from(rows.iter().map(|t| Some(t.tErr).filter(|_| unless_na(t))).collect::<Vec<_>>())),
+            Column::ModelPrediction => Arc::new(Float32Array::from(rows.iter().map(|t| Some(t.modelPrediction).filter(|_| unless_na(t))).collect::<Vec<_>>())),
+            Column::IpdRatio => Arc::new(Float32Array::from(rows.iter().map(|t| Some(t.ipdRatio).filter(|_| unless_na(t))).collect::<Vec<_>>())),
+            Column::Coverage => Arc::new(UInt32Array::from(rows.iter().map(|t| Some(t.coverage).filter(|_| unless_na(t))).collect::<Vec<_>>())),
+            Column::RefChr => Arc::new(StringArray::from(rows.iter().map(|t| t.ref_chr.as_str()).collect::<Vec<_>>())),
+            Column::RefPosition => Arc::new(Int64Array::from(rows.iter().map(|t| t.ref_position).collect::<Vec<_>>())),
+            Column::RefStrand => Arc::new(UInt8Array::from(rows.iter().map(|t| t.ref_strand).collect::<Vec<_>>())),
+            Column::Region => {
+                let keys = rows.iter().map(|t| region_index(&self.regions, &t.region).map(Some)).collect::<Result<Vec<_>, _>>()?;
+                fixed_dictionary_array(keys, &self.regions)?
+            },
+            Column::Frac => Arc::new(Float32Array::from(rows.iter().map(|t| t.frac).collect::<Vec<_>>())),
+            Column::FracLow => Arc::new(Float32Array::from(rows.iter().map(|t| t.fracLow).collect::<Vec<_>>())),
+            Column::FracUp => Arc::new(Float32Array::from(rows.iter().map(|t| t.fracUp).collect::<Vec<_>>())),
+            Column::Log2ObsOverModel => Arc::new(Float32Array::from(rows.iter().map(|t| t.log2_obs_over_model()).collect::<Vec<_>>())),
+            Column::PwMean => Arc::new(Float32Array::from(rows.iter().map(|t| t.pwMean).collect::<Vec<_>>())),
+            Column::PwErr => Arc::new(Float32Array::from(rows.iter().map(|t| t.pwErr).collect::<Vec<_>>())),
+            Column::Distance => Arc::new(Int64Array::from(rows.iter().map(|t| t.distance).collect::<Vec<_>>())),
+            Column::Context => Arc::new(StringArray::from(rows.iter().map(|t| t.context.as_deref()).collect::<Vec<_>>())),
+            Column::OccId => Arc::new(StringArray::from(rows.iter().map(|t| t.format_occ_id()).collect::<Vec<_>>())),
+            Column::GcTarget => Arc::new(Float32Array::from(rows.iter().map(|t| t.gc_target).collect::<Vec<_>>())),
+            Column::GcWindow => Arc::new(Float32Array::from(rows.iter().map(|t| t.gc_window).collect::<Vec<_>>())),
+            Column::IpdRatioControl => Arc::new(Float32Array::from(rows.iter().map(|t| t.ipdRatio_control).collect::<Vec<_>>())),
+            Column::ZscoreBackground => Arc::new(Float32Array::from(rows.iter().map(|t| t.zscore_background).collect::<Vec<_>>())),
+            Column::StrandAssumed => Arc::new(BooleanArray::from(rows.iter().map(|t| t.strand_assumed).collect::<Vec<_>>())),
+            Column::OverlapCount => Arc::new(UInt32Array::from(rows.iter().map(|t| t.overlap_count).collect::<Vec<_>>())),
+            Column::OffContig => Arc::new(BooleanArray::from(rows.iter().map(|t| t.off_contig).collect::<Vec<_>>())),
+            Column::IsMissing => Arc::new(BooleanArray::from(rows.iter().map(|t| t.missing).collect::<Vec<_>>())),
+        };
+        Ok(vec![array])
+    }
+
+    fn long_columns(&self, batch: &[TargetKinetics]) -> Result<Vec<ArrayRef>, Box<dyn Error>> {
+        let rows = batch.iter().flat_map(|(_, target_vals)| target_vals.iter()).collect::<Vec<&TargetIpdRich>>();
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        for column in self.columns.iter() {
+            columns.extend(self.long_column(*column, &rows)?);
+        }
+        // annotation columns follow the others
+        let n_annotations = rows.first().map_or(0, |t| t.annotations.len());
+        for k in 0..n_annotations {
+            columns.push(Arc::new(Float32Array::from(rows.iter().map(|t| t.annotations[k]).collect::<Vec<_>>())) as ArrayRef);
+        }
+        Ok(columns)
+    }
+
+    fn wide_columns(&self, batch: &[TargetKinetics]) -> Vec<ArrayRef> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(batch.iter().map(|(_, vals)| vals.first().map_or(0, |t| t.src)).collect::<Vec<_>>())),
+            Arc::new(StringArray::from(batch.iter().map(|(key, _)| key.refName.as_str()).collect::<Vec<_>>())),
+            Arc::new(Int64Array::from(batch.iter().map(|(key, _)| key.tpl).collect::<Vec<_>>())),
+            Arc::new(UInt8Array::from(batch.iter().map(|(key, _)| key.strand).collect::<Vec<_>>())),
+        ];
+        for j in 0..self.labels.len() {
+            for field in self.value_fields.iter() {
+                columns.push(Arc::new(Float32Array::from(batch.iter().map(|(_, vals)| self.value_of(&vals[j], *field)).collect::<Vec<_>>())));
+            }
+        }
+        columns
+    }
+}
+
+impl OutputSink for ColumnarSink {
+    fn write_batch(&mut self, batch: Vec<TargetKinetics>) -> Result<u64, Box<dyn Error>> {
+        let n_rows = self.layout.count_rows(&batch);
+        if n_rows == 0 {
+            return Ok(0);
+        }
+        let samples = match self.layout {
+            Layout::Long => batch.iter().flat_map(|(_, target_vals)| target_vals.iter().map(|t| Some(t.sample as i32))).collect::<Vec<_>>(),
+            Layout::Wide => batch.iter().map(|(_, target_vals)| Some(target_vals.first().map_or(0, |t| t.sample) as i32)).collect(),
+        };
+        let mut columns = self.constant_values.iter()
+            .map(|values| fixed_dictionary_array(samples.clone(), values))
+            .collect::<Result<Vec<_>, _>>()?;
+        columns.extend(match self.layout {
+            Layout::Long => self.long_columns(&batch)?,
+            Layout::Wide => self.wide_columns(&batch),
+        });
+        let record_batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        match &mut self.file {
+            ColumnarFile::Ipc(writer) => writer.write(&record_batch)?,
+            ColumnarFile::Parquet(writer) => writer.write(&record_batch)?,
+        }
+        Ok(n_rows)
+    }
+
+    /// Write the file footer
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        match self.file {
+            ColumnarFile::Ipc(mut writer) => writer.finish()?,
+            ColumnarFile::Parquet(writer) => {
+                writer.close()?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ArgEnum;
+    use arrow::array::Array;
+    use arrow::ipc::reader::FileReader;
+    use crate::{IpdSummaryKey, RegionSpec};
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn schema_names_match_header() {
+        let long = OutputOptions {
+            columns: Column::value_variants().to_vec(),
+            value_fields: vec![ValueField::TMean, ValueField::Frac],
+            sample_names: vec!["s1".to_string()],
+            annotation_names: vec!["track".to_string()],
+            ..OutputOptions::default()
+        };
+        let wide = OutputOptions { layout: Layout::Wide, annotation_names: Vec::new(), ..long.clone() };
+        for output_options in [long, wide] {
+            let labels = output_options.region_spec(2, 1).labels();
+            let names = schema_fields(&output_options, &labels).iter().map(|field| field.name().to_string()).collect::<Vec<_>>();
+            assert_eq!(names, output_options.header(2, 1));
+        }
+    }
+
+    #[test]
+    fn missing_row_is_null() {
+        let file = crate::tests::temporary_file("missing.feather");
+        let path = file.path();
+        let output_options = OutputOptions {
+            format: OutputFormat::Feather,
+            value_format: ValueFormat { na_string: Some("NA".to_string()), float_precision: None },
+            ..OutputOptions::default()
+        };
+        let key = IpdSummaryKey::new("chr1".to_string(), 10, 0);
+        let row = TargetIpdRich::new(1, '+', 1, &RegionSpec::new(1, 0), key.clone(), None).unwrap();
+        let mut sink: Box<dyn OutputSink> = Box::new(ColumnarSink::create(path, 1, 0, &output_options).unwrap());
+        sink.write_batch(vec![(key, vec![row])]).unwrap();
+        sink.finish().unwrap();
+
+        let mut reader = FileReader::try_new(File::open(path).unwrap(), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        for name in ["value", "score", "tErr", "modelPrediction", "ipdRatio"] {
+            let column = batch.column(batch.schema().index_of(name).unwrap());
+            assert!(column.is_null(0), "{} is not null", name);
+        }
+        // coverage is 0 rather than NA at a base without kinetics data, as in CSV
+        assert!(!batch.column(batch.schema().index_of("coverage").unwrap()).is_null(0));
+    }
+}
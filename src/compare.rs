@@ -0,0 +1,108 @@
+//! Per-position comparison of native kinetics with control (e.g. WGA or PCR-amplified) kinetics
+
+use std::error::Error;
+use std::io::{self, Write};
+use crate::{Column, InputOptions, IpdSummaryKey, IpdSummaryValue, RegionSpec, TargetIpdRich, ValueFormat};
+use crate::output::write_atomically;
+use crate::rejects::Rejects;
+use crate::simulate::normal_upper_tail;
+use crate::stats::{format_stat, NA};
+
+/// Columns of the long layout locating each row, written first
+const ROW_COLUMNS: [Column; 8] = [Column::Src, Column::Position, Column::Strand, Column::Label, Column::Region, Column::RefChr, Column::RefPosition, Column::RefStrand];
+
+/// Columns of native and control values and their differences, written after `ROW_COLUMNS` in this order
+const COMPARISON_COLUMNS: [&str; 8] = ["tMean_native", "tMean_control", "delta_tMean", "ipdRatio_native", "ipdRatio_control", "ipdRatio_ratio", "z", "p"];
+
+fn header() -> String {
+    ROW_COLUMNS.iter().map(|c| c.name()).chain(COMPARISON_COLUMNS).collect::<Vec<_>>().join(",")
+}
+
+/// Difference statistics of a base: delta tMean, the ratio of ipdRatios (None for a non-positive control ipdRatio),
+/// and the z score of delta tMean with its two-sided p-value (None without a positive standard error)
+fn compare_values(native: &IpdSummaryValue, control: &IpdSummaryValue) -> (f64, Option<f64>, Option<(f64, f64)>) {
+    let delta = native.tMean as f64 - control.tMean as f64;
+    let ratio = if control.ipdRatio > 0.0 { Some(native.ipdRatio as f64 / control.ipdRatio as f64) } else { None };
+    let standard_error = ((native.tErr as f64).powi(2) + (control.tErr as f64).powi(2)).sqrt();
+    let test = if standard_error > 0.0 && standard_error.is_finite() {
+        let z = delta / standard_error;
+        Some((z, (2.0 * normal_upper_tail(z.abs())).min(1.0)))
+    } else {
+        None
+    };
+    (delta, ratio, test)
+}
+
+/// Write a CSV of native and control kinetics of bases in target regions of occurrences in the long layout,
+/// with their differences and significance, and return the number of rows.
+/// Values of a base without kinetics data in either input are NA.
+pub(crate) fn compare_kinetics(native_path: &str, control_path: &str, occ_path: &str, occ_width: i64, occ_extension: i64, output_path: &str) -> Result<u64, Box<dyn Error>> {
+    let input_options = InputOptions::new(occ_path, occ_width, occ_extension);
+    let region_spec = RegionSpec::new(occ_width, occ_extension);
+    let mut rejects = Rejects::default();
+    let occs = input_options.read_occ(&mut rejects)?.into_iter().map(|(i, occ)| {
+        input_options.check_occ(&occ)?;
+        IpdSummaryKey::try_from(occ).map(|key| (i, key))
+    }).collect::<Result<Vec<_>, _>>()?;
+    let native = input_options.read_kinetics_csv(native_path, &mut rejects)?;
+    let control = input_options.read_kinetics_csv(control_path, &mut rejects)?;
+    let mut n_rows = 0;
+    write_atomically(output_path, |output| {
+        writeln!(output, "{}", header())?;
+        for (i, target_key) in occs {
+            let target_keys = target_key.target_keys(occ_width, occ_extension, occ_extension).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for (j, key) in target_keys.enumerate() {
+                let (native_value, control_value) = (native.get(&key), control.get(&key));
+                let strand = if j % 2 == 0 { '+' } else { '-' };
+                let row = TargetIpdRich::new((j / 2 + 1) as i64, strand, (i + 1) as i64, &region_spec, key, native_value)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let na = || NA.to_string();
+                let (native_fields, control_fields, stats) = match (native_value, control_value) {
+                    (Some(n), Some(c)) => {
+                        let (delta, ratio, test) = compare_values(n, c);
+                        ((n.tMean.to_string(), n.ipdRatio.to_string()), (c.tMean.to_string(), c.ipdRatio.to_string()),
+                            [Some(delta), ratio, test.map(|t| t.0), test.map(|t| t.1)])
+                    },
+                    (n, c) => {
+                        let fields = |v: Option<&IpdSummaryValue>| v.map_or_else(|| (na(), na()), |v| (v.tMean.to_string(), v.ipdRatio.to_string()));
+                        (fields(n), fields(c), [None; 4])
+                    },
+                };
+                let [delta, ratio, z, p] = stats.map(format_stat);
+                let mut record = ROW_COLUMNS.iter().map(|c| row.format_field(*c, &ValueFormat::default())).collect::<Vec<_>>();
+                record.extend([native_fields.0, control_fields.0, delta, native_fields.1, control_fields.1, ratio, z, p]);
+                writeln!(output, "{}", record.join(","))?;
+                n_rows += 1;
+            }
+        }
+        Ok(())
+    })?;
+    rejects.report();
+    eprintln!("[INFO] Wrote {} rows comparing native and control kinetics", n_rows);
+    Ok(n_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header1() {
+        assert_eq!(header(), "src,position,strand,label,region,ref_chr,ref_position,ref_strand,\
+tMean_native,tMean_control,delta_tMean,ipdRatio_native,ipdRatio_control,ipdRatio_ratio,z,p");
+    }
+
+    #[test]
+    fn compare_values1() {
+        let native = IpdSummaryValue { tMean: 2.0, tErr: 0.3, ipdRatio: 2.0, ..IpdSummaryValue::default() };
+        let control = IpdSummaryValue { tMean: 1.0, tErr: 0.4, ipdRatio: 0.5, ..IpdSummaryValue::default() };
+        let (delta, ratio, test) = compare_values(&native, &control);
+        assert_eq!(delta, 1.0);
+        assert_eq!(ratio, Some(4.0));
+        let (z, p) = test.unwrap();
+        assert!((z - 2.0).abs() < 1e-6);
+        assert!((p - 0.0455).abs() < 1e-3);
+        assert!(compare_values(&native, &IpdSummaryValue::default()).2.is_some());
+        assert!(compare_values(&IpdSummaryValue::default(), &IpdSummaryValue::default()).2.is_none());
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-position statistical tests of collected values between occurrence sets
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use crate::{TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::simulate::normal_upper_tail;
+use crate::stats::{format_stat, mean, mean_and_variance};
+
+/// Natural logarithm of the gamma function (Lanczos approximation, g = 7)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.9999999999998099, 676.5203681218851, -1259.1392167224028, 771.3234287776531, -176.6150291621406,
+        12.507343278686905, -0.13857109526572012, 9.984369578019572e-6, 1.5056327351493116e-7,
+    ];
+    if x < 0.5 {
+        // reflection formula
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..].iter().enumerate().fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Continued fraction of the regularized incomplete beta function (Numerical Recipes `betacf`)
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    d = if d.abs() < TINY { TINY } else { d };
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        for aa in [m * (b - m) * x / ((qam + m2) * (a + m2)), -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2))] {
+            d = 1.0 + aa * d;
+            d = if d.abs() < TINY { TINY } else { d };
+            c = 1.0 + aa / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Two-sided p-value of a t statistic with `df` degrees of freedom
+fn t_two_sided_p(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// Welch's t-test of the difference of means: (t, degrees of freedom, two-sided p-value).
+/// None if either group has fewer than 2 values or both have no variance.
+pub(crate) fn welch_t_test(case: &[f64], control: &[f64]) -> Option<(f64, f64, f64)> {
+    if case.len() < 2 || control.len() < 2 {
+        return None;
+    }
+    let (mean1, var1) = mean_and_variance(case);
+    let (mean2, var2) = mean_and_variance(control);
+    let (se1, se2) = (var1 / case.len() as f64, var2 / control.len() as f64);
+    if se1 + se2 <= 0.0 {
+        return None;
+    }
+    let t = (mean1 - mean2) / (se1 + se2).sqrt();
+    let df = (se1 + se2).powi(2) / (se1 * se1 / (case.len() - 1) as f64 + se2 * se2 / (control.len() - 1) as f64);
+    Some((t, df, t_two_sided_p(t, df)))
+}
+
+/// Wilcoxon rank-sum (Mann-Whitney) test: (U of `case`, two-sided p-value by the normal approximation
+/// with tie and continuity corrections). None if either group is empty or all values are tied.
+pub(crate) fn rank_sum_test(case: &[f64], control: &[f64]) -> Option<(f64, f64)> {
+    let (n1, n2) = (case.len() as f64, control.len() as f64);
+    if case.is_empty() || control.is_empty() {
+        return None;
+    }
+    let mut pooled = case.iter().map(|&v| (v, true)).chain(control.iter().map(|&v| (v, false))).collect::<Vec<_>>();
+    pooled.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("finite values"));
+    let (mut rank_sum, mut tie_term) = (0.0, 0.0);
+    let mut i = 0;
+    while i < pooled.len() {
+        let j = i + pooled[i..].iter().take_while(|(v, _)| *v == pooled[i].0).count();
+        // average of ranks i + 1 to j
+        let rank = (i + 1 + j) as f64 / 2.0;
+        rank_sum += rank * pooled[i..j].iter().filter(|(_, is_case)| *is_case).count() as f64;
+        let ties = (j - i) as f64;
+        tie_term += ties * ties * ties - ties;
+        i = j;
+    }
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let variance = n1 * n2 / 12.0 * (n + 1.0 - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return None;
+    }
+    let deviation = (u - n1 * n2 / 2.0).abs();
+    let z = (deviation - 0.5).max(0.0) / variance.sqrt();
+    Some((u, (2.0 * normal_upper_tail(z)).min(1.0)))
+}
+
+/// Label and values of case and control occurrences
+type CaseControlValues = (String, Vec<f64>, Vec<f64>);
+
+/// Values of each position, keyed by (relative position, relative strand), in case and control occurrences
+pub(crate) struct Comparison {
+    value_field: ValueField,
+    values: BTreeMap<(i64, char), CaseControlValues>,
+}
+
+impl Comparison {
+    pub(crate) fn new(value_field: ValueField) -> Self {
+        Self { value_field, values: BTreeMap::new() }
+    }
+
+    /// Add a row of a case (`is_case`) or control occurrence
+    pub(crate) fn add(&mut self, row: &TargetIpdRich, is_case: bool) {
+        let (_, case, control) = self.values.entry((row.position, row.strand)).or_insert_with(|| (row.label.clone(), Vec::new(), Vec::new()));
+        match row.value_of(self.value_field) {
+            Some(value) if !row.missing && value.is_finite() => if is_case { case.push(value as f64) } else { control.push(value as f64) },
+            _ => {},
+        }
+    }
+
+    /// Write a CSV with columns label, strand, numbers and means of case and control values, their difference,
+    /// Welch's t statistic, its degrees of freedom and p-value, and the Mann-Whitney U of case values and its p-value
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        write_atomically(path, |output| {
+            writeln!(output, "label,strand,n_case,n_control,mean_case,mean_control,mean_diff,t,t_df,t_p,u,u_p")?;
+            for ((_, strand), (label, case, control)) in self.values.iter() {
+                let (mean_case, mean_control) = (mean(case), mean(control));
+                let diff = mean_case.zip(mean_control).map(|(a, b)| a - b);
+                let t_test = welch_t_test(case, control);
+                let rank_sum = rank_sum_test(case, control);
+                writeln!(output, "{},{},{},{},{},{},{},{},{},{},{},{}", label, strand, case.len(), control.len(),
+                    format_stat(mean_case), format_stat(mean_control), format_stat(diff),
+                    format_stat(t_test.map(|t| t.0)), format_stat(t_test.map(|t| t.1)), format_stat(t_test.map(|t| t.2)),
+                    format_stat(rank_sum.map(|r| r.0)), format_stat(rank_sum.map(|r| r.1)))?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(path, "comparison", self.values.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_two_sided_p1() {
+        // qt(0.975, 10) = 2.228139
+        assert!((t_two_sided_p(2.228139, 10.0) - 0.05).abs() < 1e-6);
+        assert!((t_two_sided_p(0.0, 5.0) - 1.0).abs() < 1e-9);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn rank_sum_test1() {
+        let (u, p) = rank_sum_test(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(u, 0.0);
+        assert!(p < 0.1);
+        let (u, _) = rank_sum_test(&[1.0, 2.0], &[2.0, 3.0]).unwrap();
+        assert_eq!(u, 0.5);
+        assert!(rank_sum_test(&[1.0], &[1.0]).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Estimates of the size of a run from the occ file and the shapes of kinetics inputs, without collecting kinetics,
+//! e.g. to choose the memory request of a cluster job
+
+use std::error::Error;
+use std::mem::size_of;
+use crate::{open_without_bom, InputOptions, IpdSummaryKey, IpdSummaryValue, Layout, MergedOcc, OutputOptions, Rejects, TargetIpdRich};
+use crate::chrom_filter::ChromFilter;
+use crate::sink::BATCH_ROWS;
+
+/// Number of rows of kinetics CSV read to estimate the length of rows and chromosome names
+const SAMPLE_ROWS: u64 = 10000;
+
+/// Heap bytes of a string of `len` bytes, rounded up to allocation units
+fn heap_bytes(len: f64) -> f64 {
+    if len == 0.0 { 0.0 } else { (len / 16.0).ceil() * 16.0 }
+}
+
+/// Estimated number of rows of kinetics CSV from the mean length of its first rows, and the mean length of chromosome names
+fn csv_shape(path: &str) -> Result<(u64, f64), Box<dyn Error>> {
+    let mut reader = csv::Reader::from_reader(open_without_bom(path)?);
+    let chr_index = reader.headers()?.iter().position(|h| h == "refName").ok_or_else(|| format!("{}: no refName column", path))?;
+    let mut record = csv::StringRecord::new();
+    let (mut n, mut name_bytes, mut first_byte) = (0, 0, None);
+    while n < SAMPLE_ROWS && reader.read_record(&mut record)? {
+        first_byte.get_or_insert_with(|| record.position().map_or(0, |p| p.byte()));
+        name_bytes += record.get(chr_index).map_or(0, str::len);
+        n += 1;
+    }
+    let first_byte = match first_byte {
+        Some(byte) => byte,
+        None => return Ok((0, 0.0)),
+    };
+    if n < SAMPLE_ROWS {
+        return Ok((n, name_bytes as f64 / n as f64));
+    }
+    let row_bytes = (reader.position().byte() - first_byte) as f64 / n as f64;
+    let size = std::fs::metadata(path)?.len().saturating_sub(first_byte);
+    Ok(((size as f64 / row_bytes).round() as u64, name_bytes as f64 / n as f64))
+}
+
+/// Number of rows of chromosomes of kinetics HDF5 included by `chrom_filter`, from the shapes of datasets
+fn hdf5_rows(path: &str, chrom_filter: &ChromFilter) -> Result<u64, Box<dyn Error>> {
+    let file = hdf5::File::open(path)?;
+    let mut n = 0;
+    for chr in file.member_names()?.into_iter().filter(|chr| chrom_filter.includes(chr)) {
+        n += file.group(&chr)?.dataset("tpl")?.size() as u64;
+    }
+    Ok(n)
+}
+
+/// Bytes of a hash map of `n` kinetics rows of the CSV backend, whose capacity is a power of two at most 7/8 full,
+/// with chromosome names of `name_len` bytes on average
+fn csv_kinetics_bytes(n: u64, name_len: f64) -> f64 {
+    if n == 0 {
+        return 0.0;
+    }
+    let buckets = (n * 8 / 7).next_power_of_two();
+    let entry = size_of::<IpdSummaryKey>() + size_of::<IpdSummaryValue>() + 1;
+    buckets as f64 * entry as f64 + n as f64 * heap_bytes(name_len)
+}
+
+/// Bytes of `n` kinetics rows of the HDF5 backend, which are read into one vector per dataset
+fn hdf5_kinetics_bytes(n: u64) -> f64 {
+    // tpl, score, coverage, strand, a one-letter base, and nine floats with pulse widths
+    let row = 3 * size_of::<u32>() + size_of::<u8>() + size_of::<String>() + 9 * size_of::<f32>();
+    n as f64 * (row as f64 + heap_bytes(1.0))
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Print the numbers of target regions and output rows, and estimates of the peak memory and the output size, to stderr.
+/// Memory for --reference, --control-kinetics, annotation tracks, and profiles is not included.
+pub(crate) fn dry_run(kinetics_paths: &[String], hdf5: bool, input_options: &InputOptions, output_options: &OutputOptions) -> Result<(), Box<dyn Error>> {
+    let occs = input_options.read_occ(&mut Rejects::default())?;
+    let n_samples = input_options.n_samples(kinetics_paths.len());
+    let n_regions = occs.len() as u64 * n_samples as u64;
+    let region = output_options.region_spec(input_options.occ_width, input_options.occ_extension);
+    let rows_per_region = match (output_options.layout, output_options.bin_size) {
+        (Layout::Wide, _) => 1,
+        (Layout::Long, Some(bin_size)) => ((region.length() + bin_size - 1) / bin_size) as u64 * region.strands().len() as u64,
+        (Layout::Long, None) => region.n_rows() as u64,
+    };
+    let n_rows = n_regions * rows_per_region;
+    let n_columns = output_options.header(input_options.occ_width, input_options.occ_extension).len() as u64;
+    eprintln!("[INFO] Dry run: {} occurrences, {} target regions in {} samples", occs.len(), n_regions, n_samples);
+    eprintln!("[INFO] Dry run: at most {} output rows of {} columns", n_rows, n_columns);
+
+    let mut kinetics_bytes = 0.0;
+    for path in kinetics_paths {
+        let size = std::fs::metadata(path)?.len();
+        let (n_kinetics_rows, bytes) = if hdf5 {
+            let n = hdf5_rows(path, &input_options.chrom_filter)?;
+            (n, hdf5_kinetics_bytes(n))
+        } else {
+            let (n, name_len) = csv_shape(path)?;
+            (n, csv_kinetics_bytes(n, name_len))
+        };
+        eprintln!("[INFO] Dry run: {} ({}) with about {} kinetics rows taking about {} in memory", path, format_bytes(size as f64), n_kinetics_rows, format_bytes(bytes));
+        kinetics_bytes += bytes;
+    }
+    let mean_name_len = occs.iter().map(|(_, occ)| occ.refName.len() as f64).sum::<f64>() / occs.len().max(1) as f64;
+    let occ_bytes = occs.len() as f64 * (size_of::<(usize, MergedOcc)>() as f64 + heap_bytes(mean_name_len));
+    // rows of a batch with their labels and region names
+    let batch_bytes = BATCH_ROWS.min(n_rows) as f64 * (size_of::<TargetIpdRich>() as f64 + 2.0 * heap_bytes(16.0));
+    eprintln!("[INFO] Dry run: estimated peak memory {} (kinetics {}, occurrences {}, output batches {})",
+        format_bytes(kinetics_bytes + occ_bytes + batch_bytes), format_bytes(kinetics_bytes), format_bytes(occ_bytes), format_bytes(batch_bytes));
+    // about 8 characters per field of uncompressed CSV
+    eprintln!("[INFO] Dry run: estimated uncompressed CSV output of {}", format_bytes((n_rows * n_columns * 8) as f64));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_kinetics_bytes1() {
+        assert_eq!(csv_kinetics_bytes(0, 4.0), 0.0);
+        let entry = (size_of::<IpdSummaryKey>() + size_of::<IpdSummaryValue>() + 1) as f64;
+        // 112 rows fill 128 buckets up to 7/8, and one more row needs 256 buckets
+        assert_eq!(csv_kinetics_bytes(112, 4.0), 128.0 * entry + 112.0 * 16.0);
+        assert_eq!(csv_kinetics_bytes(113, 4.0), 256.0 * entry + 113.0 * 16.0);
+        assert_eq!(format_bytes(1536.0), "1.5 KiB");
+    }
+}
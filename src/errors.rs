@@ -0,0 +1,60 @@
+//! Errors of occurrences and of their target regions, with exit codes distinct from other failures
+
+use std::error::Error;
+use std::fmt;
+
+/// An occurrence that cannot be collected, with its coordinates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OccError {
+    /// A strand other than '+' or '-'
+    UnexpectedStrand { chr: String, start: i64, strand: char },
+    /// Coordinates of the extended target region overflow
+    Overflow { chr: String, start: i64, extension: i64 },
+    /// A 1-based position or a relative strand outside the extended target region of `length` bp, found when labeling rows
+    OutOfRegion { position: i64, strand: char, length: i64 },
+    /// A target region with a number of rows other than that of the extended target region
+    RowCount { chr: String, start: i64, rows: usize, expected: i64 },
+}
+
+impl OccError {
+    /// Exit status of the process stopped by this error; 1 is for other errors and 2 for usage errors
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            OccError::UnexpectedStrand { .. } => 3,
+            OccError::Overflow { .. } => 4,
+            OccError::OutOfRegion { .. } => 5,
+            OccError::RowCount { .. } => 6,
+        }
+    }
+}
+
+impl fmt::Display for OccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OccError::UnexpectedStrand { chr, start, strand } =>
+                write!(f, "Unexpected strand '{}' of the occurrence at {}:{} (0-based start)", strand, chr, start),
+            OccError::Overflow { chr, start, extension } =>
+                write!(f, "The extended region of the occurrence at {}:{} (0-based start) overflows with the extension of {} bp", chr, start, extension),
+            OccError::OutOfRegion { position, strand, length } =>
+                write!(f, "Position {} of strand '{}' is outside the extended target region of {} bp", position, strand, length),
+            OccError::RowCount { chr, start, rows, expected } =>
+                write!(f, "The target region of the occurrence at {}:{} (0-based start) has {} rows instead of {}", chr, start, rows, expected),
+        }
+    }
+}
+
+impl Error for OccError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code1() {
+        let error = OccError::UnexpectedStrand { chr: "chr1".to_string(), start: 10, strand: '.' };
+        assert_eq!(error.exit_code(), 3);
+        assert_eq!(error.to_string(), "Unexpected strand '.' of the occurrence at chr1:10 (0-based start)");
+        assert_eq!(OccError::Overflow { chr: "chr1".to_string(), start: i64::MAX, extension: 1 }.exit_code(), 4);
+        assert_eq!(OccError::OutOfRegion { position: 0, strand: '+', length: 3 }.exit_code(), 5);
+    }
+}
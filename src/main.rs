@@ -1,14 +1,87 @@
 use std::error::Error;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize,Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::From;
-use clap::{Parser, ArgGroup};
+use clap::{Parser, Subcommand, ArgGroup, ArgEnum};
 use hdf5::dataset::Dataset;
 use hdf5::types::{TypeDescriptor, FloatSize, IntSize, FixedAscii};
 
+mod output;
+use output::{Compression, OutputFormat, OutputWriter, TemporaryFile, write_atomically};
+mod sink;
+use sink::{CsvSink, OutputSink, TargetKinetics, BATCH_ROWS};
+mod columnar;
+use columnar::ColumnarSink;
+mod tabix;
+use tabix::TabixSink;
+mod backend_check;
+mod track;
+use track::{BedGraphTracks, TrackValue};
+mod calls;
+use calls::{CallsBed, CallThresholds};
+mod profile;
+use profile::{Bootstrap, Profile, ProfileWeighting, RobustOptions};
+mod report;
+use report::Report;
+mod multiqc;
+use multiqc::MultiQc;
+mod summary;
+use summary::RegionSummary;
+mod comparison;
+use comparison::Comparison;
+mod compare;
+mod merge;
+use merge::merge_values;
+mod background;
+mod binning;
+use binning::bin_rows;
+mod strands;
+use strands::{combine_strands, CombineStrands};
+mod windows;
+use windows::Windows;
+use background::{chromosome_lengths, sample_backgrounds, BackgroundOptions, BackgroundTable};
+mod manifest;
+use manifest::Manifest;
+mod provenance;
+use provenance::Provenance;
+mod simulate;
+mod power;
+mod stats;
+mod chrom_sizes;
+use chrom_sizes::{ChromEndPolicy, ChromSizes, EdgePolicy};
+mod chrom_filter;
+use chrom_filter::ChromFilter;
+mod blacklist;
+use blacklist::Blacklist;
+mod overlaps;
+use overlaps::OverlappingOcc;
+mod chrom_alias;
+use chrom_alias::{ChrPrefix, ChromAlias};
+mod errors;
+use errors::OccError;
+mod rejects;
+use rejects::Rejects;
+mod reference;
+use reference::{complement, Reference};
+mod annotation;
+use annotation::AnnotationTrack;
+mod preset;
+use preset::Preset;
+mod self_test;
+mod shuffle;
+mod scale;
+mod numbers;
+mod validate;
+mod dry_run;
+mod run_report;
+use run_report::RunReport;
+mod progress;
+use progress::{Progress, ProgressUnit, PROGRESS_ROWS};
+
 /// a record for PacBio ipdSummary with in-silico model
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(non_snake_case)]
 struct IpdSummary {
     /// Chromosome name
@@ -18,15 +91,29 @@ struct IpdSummary {
     /// Strand: 0 = plus, 1 = minus
     strand: u8,
     base: Option<char>,
+    #[serde(deserialize_with = "numbers::u32_lenient")]
     score: u32,
+    #[serde(deserialize_with = "numbers::f32_lenient")]
     tMean: f32,
+    #[serde(deserialize_with = "numbers::f32_lenient")]
     tErr: f32,
+    #[serde(deserialize_with = "numbers::f32_lenient")]
     modelPrediction: f32,
+    #[serde(deserialize_with = "numbers::f32_lenient")]
     ipdRatio: f32,
+    #[serde(deserialize_with = "numbers::u32_lenient")]
     coverage: u32,
+    #[serde(deserialize_with = "numbers::opt_f32_lenient")]
     frac: Option<f32>,
+    #[serde(deserialize_with = "numbers::opt_f32_lenient")]
     fracLow: Option<f32>,
+    #[serde(deserialize_with = "numbers::opt_f32_lenient")]
     fracUp: Option<f32>,
+    /// Pulse width statistics, only in extended ipdSummary outputs
+    #[serde(default, deserialize_with = "numbers::opt_f32_lenient", skip_serializing_if = "Option::is_none")]
+    pwMean: Option<f32>,
+    #[serde(default, deserialize_with = "numbers::opt_f32_lenient", skip_serializing_if = "Option::is_none")]
+    pwErr: Option<f32>,
 }
 
 impl IpdSummary {
@@ -46,11 +133,13 @@ impl IpdSummary {
             frac: self.frac,
             fracLow: self.fracLow,
             fracUp: self.fracUp,
+            pwMean: self.pwMean,
+            pwErr: self.pwErr,
         })
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
 #[allow(non_snake_case)]
 struct IpdSummaryKey {
     /// Chromosome name
@@ -69,67 +158,123 @@ impl IpdSummaryKey {
 
     /// return a new instance with an opposite strand
     #[allow(dead_code)]
-    fn opposite(&self) -> Self {
-        Self {
+    fn opposite(&self) -> Result<Self, OccError> {
+        Ok(Self {
             refName: self.refName.clone(),
             tpl: self.tpl,
             strand: match self.strand {
                 0 => 1,
                 1 => 0,
-                n => panic!("Unexpected strand number: {}", n),
+                _ => return Err(self.unexpected_strand()),
             }
-        }
+        })
+    }
+
+    /// Error of a key with a strand other than 0 or 1
+    fn unexpected_strand(&self) -> OccError {
+        OccError::UnexpectedStrand { chr: self.refName.clone(), start: self.tpl.saturating_sub(1), strand: char::from_digit(self.strand as u32, 10).unwrap_or('?') }
+    }
+
+    /// A checked coordinate of the extended region of this key, or an error of its overflow by `extension`
+    fn checked(&self, coordinate: Option<i64>, extension: i64) -> Result<i64, OccError> {
+        coordinate.ok_or_else(|| OccError::Overflow { chr: self.refName.clone(), start: self.tpl.saturating_sub(1), extension })
     }
 
     /// Extend IpdSummaryKey respecting its strand
     /// For a negative strand key, extension length `up` and `down` are swapped
     /// and keys in the reversed order are returned
-    #[allow(dead_code)]
-    fn extend(&self, up: i64, down: i64) -> Box<dyn Iterator<Item = Self> + '_> {
-        let position_left: i64;
-        let position_right: i64;
-        match self.strand {
-            0 => {
-                position_left = self.tpl.checked_sub(up)
-                    .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, up));
-                position_right = self.tpl.checked_add(down)
-                    .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, down));
-            },
-            1 => {
-                position_left = self.tpl.checked_sub(down)
-                    .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, down));
-                position_right = self.tpl.checked_add(up)
-                    .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, up));
-            },
-            n => panic!("Unexpected strand: {}", n),
+    fn extend(&self, up: i64, down: i64) -> Result<Box<dyn Iterator<Item = Self>>, OccError> {
+        let (left, right) = match self.strand {
+            0 => (up, down),
+            1 => (down, up),
+            _ => return Err(self.unexpected_strand()),
         };
+        let position_left = self.checked(self.tpl.checked_sub(left), left)?;
+        let position_right = self.checked(self.tpl.checked_add(right), right)?;
         let range = position_left..=position_right;
-        let keys = range.flat_map(|p| {
-            [Self::new(self.refName.clone(), p, 0), Self::new(self.refName.clone(), p, 1)]
+        let ref_name = self.refName.clone();
+        let keys = range.flat_map(move |p| {
+            [Self::new(ref_name.clone(), p, 0), Self::new(ref_name.clone(), p, 1)]
         });
-        if self.strand == 0 { Box::new(keys) } else { Box::new(keys.rev()) }
+        let keys: Box<dyn Iterator<Item = Self>> = if self.strand == 0 { Box::new(keys) } else { Box::new(keys.rev()) };
+        Ok(keys)
+    }
+
+    /// Keys of the target region extended by `up` to the left and `down` to the right of this key in reference coordinates,
+    /// in the order of rows: key(-up)..key(+width+down) for each strand, reversed for a minus-strand target
+    fn target_keys(&self, occ_width: i64, up: i64, down: i64) -> Result<Box<dyn Iterator<Item = IpdSummaryKey> + '_>, OccError> {
+        let right = self.checked(down.checked_add(occ_width - 1), down)?;
+        let pre_target_keys = self.extend_without_strand(up, right)?;
+        let keys: Box<dyn Iterator<Item = IpdSummaryKey> + '_> = match self.strand {
+            0 => Box::new(pre_target_keys),
+            1 => Box::new(pre_target_keys.rev()),
+            _ => return Err(self.unexpected_strand()),
+        };
+        Ok(keys)
+    }
+
+    /// Keys of the target region extended by `up` upstream and `down` downstream of this key on its strand, in the order of rows:
+    /// the same as `target_keys` with `up` and `down` swapped for a minus-strand target
+    fn target_keys_on_strand(&self, occ_width: i64, up: i64, down: i64) -> Result<Box<dyn Iterator<Item = IpdSummaryKey>>, OccError> {
+        // 5' end of the target on its strand
+        let tpl = if self.strand == 1 { self.checked(self.tpl.checked_add(occ_width - 1), occ_width)? } else { self.tpl };
+        let down = self.checked(down.checked_add(occ_width - 1), down)?;
+        Self { tpl, ..self.clone() }.extend(up, down)
     }
 
     /// Extend IpdSummaryKey ignoring its strand
-    fn extend_without_strand(&self, up: i64, down: i64) -> impl Iterator<Item = IpdSummaryKey> + DoubleEndedIterator + '_ {
-        let position_left = self.tpl.checked_sub(up)
-            .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, up));
-        let position_right = self.tpl.checked_add(down)
-            .unwrap_or_else(||panic!("[ERROR] Target position overflowed. IpdSummary tpl: {}, extension length: {}", self.tpl, down));
+    fn extend_without_strand(&self, up: i64, down: i64) -> Result<impl Iterator<Item = IpdSummaryKey> + DoubleEndedIterator + '_, OccError> {
+        let position_left = self.checked(self.tpl.checked_sub(up), up)?;
+        let position_right = self.checked(self.tpl.checked_add(down), down)?;
         let range = position_left..=position_right;
-        range.flat_map(|p| {
+        Ok(range.flat_map(|p| {
             [Self::new(self.refName.clone(), p, 0), Self::new(self.refName.clone(), p, 1)]
-        })
+        }))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    /// A file named `name` in the temporary directory, removed when dropped even if the test fails
+    pub(crate) fn temporary_file(name: &str) -> TemporaryFile {
+        TemporaryFile::new(std::env::temp_dir().join(format!("collect_regional_kinetics.{}.{}", std::process::id(), name)))
+    }
+
+    /// `n` rows of a target region of `region` alternating between the relative plus and minus strands
+    /// (position j / 2 + 1 of row j at chr:100 + position), with the values of row j by `value`
+    pub(crate) fn alternating_strand_rows<F: Fn(i64) -> Option<IpdSummaryValue>>(n: i64, region: &RegionSpec, value: F) -> Vec<TargetIpdRich> {
+        (0..n).map(|j| {
+            let position = j / 2 + 1;
+            let strand = if j % 2 == 0 { '+' } else { '-' };
+            let key = IpdSummaryKey { refName: "chr".to_string(), tpl: 100 + position, strand: if strand == '+' { 0 } else { 1 } };
+            TargetIpdRich::new(position, strand, 1, region, key, value(j).as_ref()).unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn null_nonfinite_frac1() {
+        let value = IpdSummaryValue { frac: Some(f32::NAN), fracLow: Some(f32::NAN), fracUp: Some(0.5), ..Default::default() };
+        let value = value.null_nonfinite_frac();
+        assert_eq!((value.frac, value.fracLow, value.fracUp), (None, None, None));
+        let value = IpdSummaryValue { frac: Some(0.2), ..Default::default() }.null_nonfinite_frac();
+        assert_eq!(value.frac, Some(0.2));
+    }
+
+    #[test]
+    fn frames_to_milliseconds1() {
+        let value = IpdSummaryValue { tMean: 0.8, tErr: 0.08, modelPrediction: 0.4, ipdRatio: 2.0, ..Default::default() };
+        let value = value.frames_to_milliseconds(80.0);
+        assert!((value.tMean - 10.0).abs() < 1e-5);
+        assert!((value.tErr - 1.0).abs() < 1e-5);
+        assert!((value.modelPrediction - 5.0).abs() < 1e-5);
+        assert_eq!(value.ipdRatio, 2.0);
+    }
     #[test]
     fn key_extend1() {
         let k = IpdSummaryKey::new("chrX".to_string(), 100, 0);
-        let result = k.extend(1, 2).collect::<Vec<_>>();
+        let result = k.extend(1, 2).unwrap().collect::<Vec<_>>();
         let expected = vec![
             IpdSummaryKey::new("chrX".to_string(), 99, 0),
             IpdSummaryKey::new("chrX".to_string(), 99, 1),
@@ -146,7 +291,7 @@ mod tests {
     #[test]
     fn key_extend1neg() {
         let k = IpdSummaryKey::new("chrX".to_string(), 100, 1);
-        let result = k.extend(1, 2).collect::<Vec<_>>();
+        let result = k.extend(1, 2).unwrap().collect::<Vec<_>>();
         let expected = vec![
             IpdSummaryKey::new("chrX".to_string(), 101, 1),
             IpdSummaryKey::new("chrX".to_string(), 101, 0),
@@ -163,7 +308,7 @@ mod tests {
     #[test]
     fn key_extend_without_strand1() {
         let k = IpdSummaryKey::new("chrX".to_string(), 100, 0);
-        let result = k.extend_without_strand(1, 2).collect::<Vec<_>>();
+        let result = k.extend_without_strand(1, 2).unwrap().collect::<Vec<_>>();
         let expected = vec![
             IpdSummaryKey::new("chrX".to_string(), 99, 0),
             IpdSummaryKey::new("chrX".to_string(), 99, 1),
@@ -180,7 +325,7 @@ mod tests {
     #[test]
     fn key_extend_without_strand1neg() {
         let k = IpdSummaryKey::new("chrX".to_string(), 100, 1);
-        let result = k.extend_without_strand(1, 2).collect::<Vec<_>>();
+        let result = k.extend_without_strand(1, 2).unwrap().collect::<Vec<_>>();
         let expected = vec![
             IpdSummaryKey::new("chrX".to_string(), 99, 0),
             IpdSummaryKey::new("chrX".to_string(), 99, 1),
@@ -193,24 +338,500 @@ mod tests {
         ];
         assert_eq!(result, expected);
     }
-}
 
-impl From<MergedOcc> for IpdSummaryKey {
-    fn from(merged_occ: MergedOcc) -> Self {
-        Self {
-            refName: merged_occ.refName,
-            // MergedOcc: 0-based, IpdSummary: 1-based
-            tpl: merged_occ.start + 1,
-            strand: match merged_occ.strand {
-                '+' => 0,
-                '-' => 1,
-                c => panic!("Unexpected strand char: {}", c),
-            },
+    #[test]
+    fn key_errors1() {
+        let k = IpdSummaryKey::new("chrX".to_string(), i64::MAX, 0);
+        assert_eq!(k.target_keys(2, 0, 1).err().map(|e| e.exit_code()), Some(4));
+        assert_eq!(k.extend(0, 1).err().map(|e| e.exit_code()), Some(4));
+        let k = IpdSummaryKey::new("chrX".to_string(), 100, 2);
+        assert_eq!(k.target_keys(1, 0, 0).err().map(|e| e.exit_code()), Some(3));
+        assert_eq!(k.opposite().err().map(|e| e.exit_code()), Some(3));
+    }
+
+    #[test]
+    fn value_format1() {
+        let raw = ValueFormat::default();
+        assert_eq!(raw.float(1.0), "1.0");
+        assert_eq!(raw.float(0.822), "0.822");
+        assert_eq!(raw.missing_or(true, raw.float(0.0)), "0.0");
+        let rounded = ValueFormat { na_string: Some("NA".to_string()), float_precision: Some(2) };
+        assert_eq!(rounded.float(0.826), "0.83");
+        assert_eq!(rounded.missing_or(true, rounded.float(0.0)), "NA");
+        assert_eq!(rounded.missing_or(false, rounded.float(0.0)), "0.00");
+    }
+
+    #[test]
+    fn anchor1() {
+        let occ = |strand: char| MergedOcc { refName: "chr".to_string(), start: 10, strand, strand_assumed: false, overlap_count: 0 };
+        assert_eq!(Anchor::Start.anchor(occ('+'), 4).unwrap().start, 10);
+        assert_eq!(Anchor::Center.anchor(occ('+'), 4).unwrap().start, 11);
+        assert_eq!(Anchor::End.anchor(occ('+'), 4).unwrap().start, 13);
+        assert_eq!(Anchor::Start.anchor(occ('-'), 4).unwrap().start, 13);
+        assert_eq!(Anchor::Center.anchor(occ('-'), 4).unwrap().start, 12);
+        assert_eq!(Anchor::End.anchor(occ('-'), 4).unwrap().start, 10);
+    }
+
+    #[test]
+    fn column_reorder1() {
+        let columns = Column::reorder(&[Column::Region, Column::Strand], &[]).unwrap();
+        assert_eq!(columns.len(), Column::value_variants().iter().filter(|c| !c.is_optional()).count());
+        assert_eq!(&columns[..3], &[Column::Region, Column::Strand, Column::Position]);
+        assert_eq!(Column::TErr.name(), "tErr");
+        assert!(Column::reorder(&[Column::Src, Column::Src], &[]).is_err());
+        assert_eq!(Column::reorder(&[], &Column::FRAC).unwrap().last(), Some(&Column::FracUp));
+    }
+
+    #[test]
+    fn signed_labels() {
+        let region = RegionSpec { label_style: LabelStyle::Signed, ..RegionSpec::new(2, 1) };
+        assert_eq!(region.labels(), vec!["-1p", "-1m", "1p", "1m", "2p", "2m", "+1p", "+1m"]);
+        let region = RegionSpec { target_strand_only: true, ..region };
+        assert_eq!(region.labels(), vec!["-1p", "1p", "2p", "+1p"]);
+        assert_eq!(region.n_rows(), 4);
+    }
+
+    #[test]
+    fn asymmetric_extension1() {
+        let region = RegionSpec { label_style: LabelStyle::Signed, downstream: 0, ..RegionSpec::new(1, 2) };
+        assert_eq!(region.length(), 3);
+        assert_eq!(region.labels(), vec!["-2p", "-2m", "-1p", "-1m", "1p", "1m"]);
+        let key = IpdSummaryKey::new("chr".to_string(), 10, 0);
+        assert_eq!(key.target_keys(1, 2, 0).unwrap().map(|k| k.tpl).collect::<Vec<_>>(), vec![8, 8, 9, 9, 10, 10]);
+    }
+
+    #[test]
+    fn key_target_keys_on_strand1() {
+        let plus = IpdSummaryKey::new("chr".to_string(), 10, 0);
+        assert_eq!(plus.target_keys_on_strand(2, 2, 1).unwrap().collect::<Vec<_>>(), plus.target_keys(2, 2, 1).unwrap().collect::<Vec<_>>());
+        // upstream of a minus-strand target is to its right
+        let minus = IpdSummaryKey::new("chr".to_string(), 10, 1);
+        assert_eq!(minus.target_keys_on_strand(2, 2, 1).unwrap().collect::<Vec<_>>(), minus.target_keys(2, 1, 2).unwrap().collect::<Vec<_>>());
+        assert_eq!(minus.target_keys_on_strand(2, 2, 1).unwrap().map(|k| k.tpl).step_by(2).collect::<Vec<_>>(), vec![13, 12, 11, 10, 9]);
+    }
+
+    #[test]
+    fn region_parts1() {
+        let region = RegionSpec { parts: vec![1, 2], ..RegionSpec::new(3, 1) };
+        assert!(region.check_parts().is_ok());
+        assert_eq!((1..=5).map(|p| region.region(p).unwrap()).collect::<Vec<_>>(), vec!["Upstream", "Target1", "Target2", "Target2", "Downstream"]);
+        assert_eq!((1..=5).map(|p| region.label(p, '+').unwrap()).collect::<Vec<_>>(), vec!["s1p", "m1_1p", "m2_1p", "m2_2p", "e1p"]);
+        assert_eq!(region.regions(), vec!["Upstream", "Target1", "Target2", "Downstream"]);
+        assert!(RegionSpec { parts: vec![1, 1], ..RegionSpec::new(3, 1) }.check_parts().is_err());
+    }
+
+    #[test]
+    fn occ_coords1() {
+        let occ = MergedOcc { refName: "chr".to_string(), start: 10, strand: '+', strand_assumed: false, overlap_count: 0 };
+        assert_eq!(IpdSummaryKey::try_from(OccCoords::ZeroBased.to_zero_based(occ.clone()).unwrap()).unwrap().tpl, 11);
+        assert_eq!(IpdSummaryKey::try_from(OccCoords::OneBased.to_zero_based(occ).unwrap()).unwrap().tpl, 10);
+    }
+
+    #[test]
+    fn unknown_strand_policy1() {
+        let occ_file = temporary_file("unstranded.occ");
+        let occ_path = occ_file.path();
+        std::fs::write(&occ_path, "chr 10 .\nchr 20 +\n").unwrap();
+        let read = |unknown_strand| {
+            let input_options = InputOptions { unknown_strand, ..InputOptions::new(occ_path.to_str().unwrap(), 2, 1) };
+            let mut rejects = Rejects::default();
+            input_options.read_occ(&mut rejects).map(|occs| occs.into_iter().map(|(i, occ)| (i, occ.strand, occ.strand_assumed)).collect::<Vec<_>>())
+        };
+        assert_eq!(read(UnknownStrand::Both).unwrap(), [(0, '+', true), (0, '-', true), (1, '+', false)]);
+        assert_eq!(read(UnknownStrand::Skip).unwrap(), [(1, '+', false)]);
+        let error = read(UnknownStrand::Error).unwrap_err();
+        assert_eq!(error.downcast_ref::<OccError>().map(OccError::exit_code), Some(3));
+    }
+
+    #[test]
+    fn malformed_occ_skipped1() {
+        let (occ_file, rejects_file) = (temporary_file("malformed.occ"), temporary_file("malformed.rejects.tsv"));
+        std::fs::write(occ_file.path(), "chr 10 +\nchr ten +\nchr 20 x\nchr 30 -\n").unwrap();
+        let mut rejects = Rejects::default();
+        let occs = InputOptions::new(occ_file.path().to_str().unwrap(), 2, 1).read_occ(&mut rejects).unwrap();
+        assert_eq!(occs.iter().map(|(i, occ)| (*i, occ.start)).collect::<Vec<_>>(), [(0, 10), (3, 30)]);
+        // counts reported at the end of a run
+        assert_eq!(rejects.counts().into_iter().collect::<Vec<_>>(), [(("occ", "malformed_record"), 2)]);
+        // line numbers of skipped records
+        let rejects_path = rejects_file.path().to_str().unwrap();
+        rejects.write(rejects_path, &mut Manifest::new()).unwrap();
+        let lines = std::fs::read_to_string(rejects_path).unwrap().lines().skip(1)
+            .map(|line| line.split('\t').take(2).collect::<Vec<_>>().join(":")).collect::<Vec<_>>();
+        assert_eq!(lines, ["occ:2", "occ:3"]);
+    }
+
+    #[test]
+    fn dedup_occ1() {
+        let occ_file = temporary_file("duplicate.occ");
+        std::fs::write(occ_file.path(), "chr 10 +\nchr 20 -\nchr 10 +\nchr 10 -\nchr 30 +\n").unwrap();
+        let read = |dedup_occ| {
+            let mut rejects = Rejects::default();
+            let occs = InputOptions { dedup_occ, ..InputOptions::new(occ_file.path().to_str().unwrap(), 2, 1) }.read_occ(&mut rejects).unwrap();
+            (occs.iter().map(|(i, occ)| (*i, occ.start, occ.strand)).collect::<Vec<_>>(), rejects.counts().into_iter().collect::<Vec<_>>())
+        };
+        let (occs, counts) = read(false);
+        assert_eq!(occs, [(0, 10, '+'), (1, 20, '-'), (2, 10, '+'), (3, 10, '-'), (4, 30, '+')]);
+        assert!(counts.is_empty());
+        // the same position on the other strand is not a duplicate;
+        // kept occurrences are numbered by their records as without --dedup-occ
+        let (occs, counts) = read(true);
+        assert_eq!(occs, [(0, 10, '+'), (1, 20, '-'), (3, 10, '-'), (4, 30, '+')]);
+        assert_eq!(counts, [(("occ", "duplicate_occ"), 1)]);
+        assert_eq!(read(true).0, occs);
+    }
+
+    #[test]
+    fn malformed_kinetics_record1() {
+        let kinetics_file = temporary_file("malformed.kinetics.csv");
+        std::fs::write(kinetics_file.path(), "refName,tpl,strand,base,score,tMean,tErr,modelPrediction,ipdRatio,coverage,frac,fracLow,fracUp\n\
+            chr1,1,0,A,10,1.5,0.1,1.0,1.5,20,,,\n\
+            chr1,x2,0,C,10,1.5,0.1,1.0,1.5,20,,,\n\
+            chr1,3,0,G,10,1.5,0.1,1.0,1.5,20,,,\n").unwrap();
+        // the error names the file, the line, and the field
+        let error = InputOptions::new("", 1, 0).read_kinetics_csv(kinetics_file.path(), &mut Rejects::default()).unwrap_err().to_string();
+        assert!(error.starts_with(&format!("Malformed kinetics record at {}:3: ", kinetics_file.path().display())), "{}", error);
+        assert!(error.contains("(field tpl)"), "{}", error);
+        assert!(error.ends_with("(see --skip-bad-kinetics-rows)"), "{}", error);
+        // the record is rejected with --skip-bad-kinetics-rows
+        let mut rejects = Rejects::default();
+        let kinetics = InputOptions { skip_bad_kinetics: true, ..InputOptions::new("", 1, 0) }.read_kinetics_csv(kinetics_file.path(), &mut rejects).unwrap();
+        let mut positions = kinetics.keys().map(|key| key.tpl).collect::<Vec<_>>();
+        positions.sort_unstable();
+        assert_eq!(positions, [1, 3]);
+        assert_eq!(rejects.counts().into_iter().collect::<Vec<_>>(), [(("kinetics", "malformed_record"), 1)]);
+    }
+
+    #[test]
+    fn resolve_missing_chroms1() {
+        let occ = |chr: &str| MergedOcc { refName: chr.to_string(), start: 10, strand: '+', strand_assumed: false, overlap_count: 0 };
+        let occs = vec![(0, occ("chr1")), (1, occ("chrX")), (2, occ("chr2"))];
+        let kinetics_chroms = ["chr2", "chr1"].into_iter().collect::<HashSet<_>>();
+        let resolve = |missing_chrom, kinetics_chroms: &HashSet<&str>| InputOptions { missing_chrom, ..InputOptions::new("", 1, 0) }
+            .resolve_missing_chroms(occs.clone(), kinetics_chroms).map(|occs| occs.into_iter().map(|(i, _)| i).collect::<Vec<_>>());
+        // the error lists the missing and available chromosomes
+        let error = resolve(MissingChrom::Error, &kinetics_chroms).unwrap_err().to_string();
+        assert!(error.starts_with("Chromosomes of occurrences are not in the kinetics input: chrX; its chromosomes are: chr1, chr2 "), "{}", error);
+        assert_eq!(resolve(MissingChrom::Skip, &kinetics_chroms).unwrap(), [0, 2]);
+        assert_eq!(resolve(MissingChrom::Zero, &kinetics_chroms).unwrap(), [0, 1, 2]);
+        // long lists are cut
+        let names = (0..25).map(|i| format!("chr{:02}", i + 10)).collect::<Vec<_>>();
+        let error = resolve(MissingChrom::Error, &names.iter().map(|name| name.as_str()).collect()).unwrap_err().to_string();
+        assert!(error.contains("chr29, ... (25 in total)"), "{}", error);
+        assert!(!error.contains("chr30"), "{}", error);
+    }
+
+    #[test]
+    fn occ_exit_codes() {
+        let occ_file = temporary_file("exit_codes.occ");
+        let occ_path = occ_file.path();
+        let exit_code = |content: String, occ_coords: OccCoords| {
+            std::fs::write(&occ_path, content).unwrap();
+            let input_options = InputOptions { occ_coords, strict: true, ..InputOptions::new(occ_path.to_str().unwrap(), 2, 1) };
+            let error = input_options.read_occ_path(occ_path.to_str().unwrap(), "occ", &mut Rejects::default()).unwrap_err();
+            error.downcast_ref::<OccError>().map(OccError::exit_code)
+        };
+        assert_eq!(exit_code("chr 10 x\n".to_string(), OccCoords::ZeroBased), Some(3));
+        assert_eq!(exit_code(format!("chr {} +\n", i64::MAX), OccCoords::ZeroBased), Some(4));
+        assert_eq!(exit_code(format!("chr {} +\n", i64::MIN), OccCoords::OneBased), Some(4));
+    }
+
+    #[test]
+    fn occ_header1() {
+        let occ_file = temporary_file("header.occ");
+        let occ_path = occ_file.path();
+        std::fs::write(&occ_path, "\u{feff}# exported\r\nrefName start strand\r\nchr 10 +\r\nchr 20 -\r\n").unwrap();
+        let mut rejects = Rejects::default();
+        let occs = InputOptions::new(occ_path.to_str().unwrap(), 2, 1).read_occ(&mut rejects).unwrap();
+        assert_eq!(occs.iter().map(|(i, occ)| (*i, occ.refName.as_str(), occ.start, occ.strand)).collect::<Vec<_>>(), [(0, "chr", 10, '+'), (1, "chr", 20, '-')]);
+    }
+
+    #[test]
+    fn occ_stable_id1() {
+        let occ = |start, strand| MergedOcc { refName: "chrI".to_string(), start, strand, strand_assumed: false, overlap_count: 0 };
+        assert_eq!(occ(10, '+').stable_id(), occ(10, '+').stable_id());
+        assert_ne!(occ(10, '+').stable_id(), occ(10, '-').stable_id());
+        assert_ne!(occ(10, '+').stable_id(), occ(11, '+').stable_id());
+    }
+
+    #[test]
+    fn distance1() {
+        let distances = (1..=6).map(|p| TargetIpdRich::new(p, '+', 1, &RegionSpec::new(2, 2), IpdSummaryKey::new("chrX".to_string(), p, 0), None).unwrap().distance).collect::<Vec<_>>();
+        assert_eq!(distances, vec![-2, -1, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn column_names_match_serialized_fields() {
+        let key = IpdSummaryKey::new("chrX".to_string(), 100, 0);
+        let target = TargetIpdRich::new(1, '+', 0, &RegionSpec::new(1, 0), key, None).unwrap();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(&target).unwrap();
+        let serialized = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let names = Column::value_variants().iter().filter(|c| !c.is_computed()).map(|c| c.name()).collect::<Vec<_>>();
+        assert_eq!(serialized.lines().next(), Some(names.join(",").as_str()));
+    }
+
+    #[test]
+    fn empty_output_has_requested_columns() {
+        let output_file = temporary_file("empty.csv");
+        let output_path = output_file.path();
+        let output_options = OutputOptions {
+            columns: Column::reorder(&[Column::Region], &Column::FRAC).unwrap(),
+            sample_names: vec!["s1".to_string()],
+            ..OutputOptions::default()
+        };
+        write_empty_target_kinetics(2, 1, &output_options, output_path, &mut Manifest::new()).unwrap();
+        let written = std::fs::read_to_string(output_path).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), vec![output_options.header(2, 1).join(",")]);
+        assert!(written.starts_with("sample,region,position,"));
+    }
+
+    #[test]
+    fn append_samples1() {
+        let output_file = temporary_file("append.csv");
+        let output_path = output_file.path();
+        let rows = alternating_strand_rows(2, &RegionSpec::new(1, 0), |_| Some(IpdSummaryValue { tMean: 1.5, coverage: 10, ..Default::default() }));
+        let append = |sample_name: &str, value_fields: Vec<ValueField>| {
+            let output_options = OutputOptions { append: true, sample_names: vec![sample_name.to_string()], value_fields, ..OutputOptions::default() };
+            let target = Ok((IpdSummaryKey::new("chr".to_string(), 101, 0), rows.clone()));
+            write_target_kinetics_table(std::iter::once(target), 1, 0, &output_options, output_path, &mut Manifest::new())
+        };
+        assert_eq!(append("a", vec![ValueField::TMean]).unwrap(), 2);
+        assert_eq!(append("b", vec![ValueField::TMean]).unwrap(), 2);
+        let written = std::fs::read_to_string(output_path).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        // a single header, followed by rows of both samples
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("sample,"));
+        assert_eq!(lines[1..].iter().map(|line| line.split(',').next().unwrap()).collect::<Vec<_>>(), ["a", "a", "b", "b"]);
+        // different columns are refused and the output is left as it is
+        let error = append("c", vec![ValueField::TMean, ValueField::IpdRatio]).unwrap_err().to_string();
+        assert!(error.contains("its header differs from the current output columns"), "{}", error);
+        assert_eq!(std::fs::read_to_string(output_path).unwrap(), written);
+    }
+
+    /// Rows of two target regions (src 1 and 2) of a one-base target, where the minus-strand row of the second has no data
+    fn regions_with_missing_row() -> [Vec<TargetIpdRich>; 2] {
+        let value = IpdSummaryValue { tMean: 1.0, coverage: 10, ..IpdSummaryValue::default() };
+        let region = RegionSpec::new(1, 0);
+        let first = alternating_strand_rows(2, &region, |_| Some(value.clone()));
+        let mut second = alternating_strand_rows(2, &region, |j| if j == 1 { None } else { Some(value.clone()) });
+        second.iter_mut().for_each(|row| row.src = 2);
+        [first, second]
+    }
+
+    #[test]
+    fn missing_policy_apply1() {
+        let [complete, with_missing] = regions_with_missing_row();
+        let kept = |policy: MissingPolicy, rows: &[TargetIpdRich]| policy.apply(rows.to_vec()).iter().map(|row| (row.strand, row.missing)).collect::<Vec<_>>();
+        for policy in [MissingPolicy::Zero, MissingPolicy::Na] {
+            assert_eq!(kept(policy, &with_missing), [('+', false), ('-', true)]);
+        }
+        assert_eq!(kept(MissingPolicy::SkipRow, &with_missing), [('+', false)]);
+        assert!(kept(MissingPolicy::DropRegion, &with_missing).is_empty());
+        assert_eq!(kept(MissingPolicy::DropRegion, &complete), [('+', false), ('-', false)]);
+    }
+
+    #[test]
+    fn missing_policy_in_aggregation() {
+        let (profile_file, summary_file) = (temporary_file("policy.profile.csv"), temporary_file("policy.summary.csv"));
+        let (profile_path, summary_path) = (profile_file.path().to_str().unwrap(), summary_file.path().to_str().unwrap());
+        let read_csv = |path: &str| csv::Reader::from_path(path).unwrap().records().map(|record| record.unwrap()).collect::<Vec<_>>();
+        // (n of the plus and minus strands in the profile, n_missing of each occurrence in the summary)
+        let aggregate = |policy: MissingPolicy| {
+            let mut profile = Profile::new(ValueField::TMean, Vec::new(), None, ProfileWeighting::None, RobustOptions::default());
+            let mut summary = RegionSummary::new(ValueField::TMean, Some(2));
+            for rows in regions_with_missing_row() {
+                let rows = policy.apply(rows);
+                rows.iter().for_each(|row| profile.add(row));
+                summary.add(&IpdSummaryKey::new("chr".to_string(), 101, 0), &rows);
+            }
+            profile.write(profile_path, &mut Manifest::new()).unwrap();
+            summary.write(summary_path, &mut Manifest::new()).unwrap();
+            let n = read_csv(profile_path).iter().map(|record| record[2].to_string()).collect::<Vec<_>>();
+            let n_missing = read_csv(summary_path).iter().map(|record| record[13].to_string()).collect::<Vec<_>>();
+            (n, n_missing)
+        };
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        // rows without data are not aggregated, and a left-out row is still counted as missing
+        for policy in [MissingPolicy::Zero, MissingPolicy::Na, MissingPolicy::SkipRow] {
+            assert_eq!(aggregate(policy), (strings(&["2", "1"]), strings(&["0", "1"])));
+        }
+        // the region with a missing row is dropped as a whole
+        assert_eq!(aggregate(MissingPolicy::DropRegion), (strings(&["1", "1"]), strings(&["0"])));
+    }
+
+    #[test]
+    fn sample_value_filters() {
+        let values = [
+            IpdSummaryValue { score: 20, coverage: 5, frac: Some(0.2), ..IpdSummaryValue::default() },
+            IpdSummaryValue { score: 40, coverage: 15, frac: None, ..IpdSummaryValue::default() },
+        ];
+        let value_of = |k: usize| Some(values[k].clone());
+        let options = || InputOptions::new("", 1, 0);
+        let kept = |input_options: InputOptions| (0..2).map(|sample| input_options.sample_value(sample, 2, value_of).is_some()).collect::<Vec<_>>();
+        assert_eq!(kept(options()), [true, true]);
+        // minimum coverage and score are inclusive
+        assert_eq!(kept(InputOptions { min_coverage: Some(5), ..options() }), [true, true]);
+        assert_eq!(kept(InputOptions { min_coverage: Some(6), ..options() }), [false, true]);
+        assert_eq!(kept(InputOptions { min_score: Some(20), ..options() }), [true, true]);
+        assert_eq!(kept(InputOptions { min_score: Some(21), ..options() }), [false, true]);
+        // the frac range is inclusive at both ends, and a value without frac is out of it
+        assert_eq!(kept(InputOptions { frac_range: Some((0.2, 0.2)), ..options() }), [true, false]);
+        assert_eq!(kept(InputOptions { frac_range: Some((0.0, 0.1)), ..options() }), [false, false]);
+        // merged replicates are filtered by their summed coverage and merged frac
+        let merged = |input_options: InputOptions| input_options.sample_value(0, 2, value_of).map(|value| value.coverage);
+        assert_eq!(merged(InputOptions { merge_replicates: true, min_coverage: Some(20), ..options() }), Some(20));
+        assert_eq!(merged(InputOptions { merge_replicates: true, min_coverage: Some(21), ..options() }), None);
+        assert_eq!(merged(InputOptions { merge_replicates: true, frac_range: Some((0.2, 0.5)), ..options() }), Some(20));
+    }
+
+    #[test]
+    fn is_poorly_covered1() {
+        // coverage 100 in the flanks, and 10 and 20 at the two target bases
+        let coverages = [100, 10, 20, 100];
+        let rows = alternating_strand_rows(8, &RegionSpec::new(2, 1), |j| Some(IpdSummaryValue { coverage: coverages[j as usize / 2], ..IpdSummaryValue::default() }));
+        let flank_rows = rows.iter().filter(|row| row.region == "Upstream" || row.region == "Downstream").cloned().collect::<Vec<_>>();
+        let poorly_covered = |min_region_coverage, rows: &[TargetIpdRich]| InputOptions { min_region_coverage, ..InputOptions::new("", 2, 1) }.is_poorly_covered(rows);
+        assert!(!poorly_covered(None, &rows));
+        assert!(!poorly_covered(Some((15.0, RegionCoverageStat::Mean)), &rows));
+        assert!(poorly_covered(Some((15.5, RegionCoverageStat::Mean)), &rows));
+        assert!(!poorly_covered(Some((10.0, RegionCoverageStat::Min)), &rows));
+        assert!(poorly_covered(Some((11.0, RegionCoverageStat::Min)), &rows));
+        // without target rows, as when --edge-policy clip leaves out the target part
+        assert!(poorly_covered(Some((1.0, RegionCoverageStat::Mean)), &flank_rows));
+        assert!(poorly_covered(Some((1.0, RegionCoverageStat::Min)), &flank_rows));
+    }
+
+    #[test]
+    fn fill_value1() {
+        let value = IpdSummaryValue { tMean: 2.0, ipdRatio: 1.5, coverage: 10, ..IpdSummaryValue::default() };
+        let mut rows = alternating_strand_rows(2, &RegionSpec::new(1, 0), |j| if j == 1 { None } else { Some(value.clone()) });
+        let input_options = InputOptions { fill_value: Some(-1.0), ..InputOptions::new("", 1, 0) };
+        rows.iter_mut().for_each(|row| input_options.annotate_row(row, &OccValues::default()));
+        // only the row without kinetics data is filled
+        assert_eq!(rows.iter().map(|row| (row.missing, row.value, row.ipdRatio)).collect::<Vec<_>>(), [(false, 2.0, 1.5), (true, -1.0, -1.0)]);
+    }
+
+    /// Collect tMean (1.5 at both strands of chr1:1-10, which is 10 bp long) around occurrences in `occ_records`
+    /// of width 1 extended by 2 bp with options changed by `options`, and return (ref_position, value) of plus-strand rows;
+    /// `name` keeps the files of tests apart
+    fn collect_near_chromosome_ends<F: FnOnce(InputOptions) -> InputOptions>(name: &str, occ_records: &str, options: F) -> Result<Vec<(i64, String)>, Box<dyn Error>> {
+        let file = |suffix: &str| temporary_file(&format!("{}.{}", name, suffix));
+        let (occ_file, kinetics_file, sizes_file, output_file) = (file("occ"), file("kinetics.csv"), file("sizes"), file("csv"));
+        std::fs::write(occ_file.path(), occ_records)?;
+        let records = (1..=10).map(|tpl| format!("chr1,{},0,A,10,1.5,0.1,1.0,1.5,20,,,\nchr1,{},1,T,10,1.5,0.1,1.0,1.5,20,,,\n", tpl, tpl)).collect::<String>();
+        std::fs::write(kinetics_file.path(), format!("refName,tpl,strand,base,score,tMean,tErr,modelPrediction,ipdRatio,coverage,frac,fracLow,fracUp\n{}", records))?;
+        std::fs::write(sizes_file.path(), "chr1\t10\n")?;
+        let chrom_sizes = ChromSizes::from_path(sizes_file.path().to_str().unwrap())?;
+        let input_options = options(InputOptions { chrom_sizes: Some(chrom_sizes), ..InputOptions::new(occ_file.path().to_str().unwrap(), 1, 2) });
+        collect_ipd_summary_in_merged_occ(&[kinetics_file.path()], &input_options, &OutputOptions::default(), output_file.path(), &mut Manifest::new(), &mut RunReport::new())?;
+        let mut reader = csv::Reader::from_path(output_file.path())?;
+        let header = reader.headers()?.clone();
+        let index = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (strand, ref_position, value) = (index("strand"), index("ref_position"), index("value"));
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if &record[strand] == "+" {
+                rows.push((record[ref_position].parse()?, record[value].to_string()));
+            }
+        }
+        Ok(rows)
+    }
+
+    fn covered_rows(positions: std::ops::RangeInclusive<i64>) -> Vec<(i64, String)> {
+        positions.map(|position| (position, "1.5".to_string())).collect()
+    }
+
+    #[test]
+    fn chrom_end_policies() {
+        // the region of the second occurrence is chr1:8-12
+        let occs = "chr1 5 +\nchr1 9 +\n";
+        let policy = |policy| move |input_options: InputOptions| InputOptions { chrom_end_policy: Some(policy), ..input_options };
+        let error = collect_near_chromosome_ends("chrom_end_error", occs, policy(ChromEndPolicy::Error)).unwrap_err();
+        assert!(error.to_string().contains("chr1:7-12 of the occ record at line 2 runs past the chromosome end"), "{}", error);
+        assert_eq!(collect_near_chromosome_ends("chrom_end_skip", occs, policy(ChromEndPolicy::Skip)).unwrap(), covered_rows(4..=8));
+        let mut expected = [covered_rows(4..=8), covered_rows(8..=10)].concat();
+        expected.extend([(11, "NA".to_string()), (12, "NA".to_string())]);
+        assert_eq!(collect_near_chromosome_ends("chrom_end_clip", occs, policy(ChromEndPolicy::Clip)).unwrap(), expected);
+    }
+
+    #[test]
+    fn edge_policies() {
+        // the region of the occurrence is chr1:-1-3
+        let occs = "chr1 0 +\n";
+        let policy = |policy| move |input_options: InputOptions| InputOptions { edge_policy: Some(policy), ..input_options };
+        assert!(collect_near_chromosome_ends("edge_skip", occs, policy(EdgePolicy::Skip)).unwrap().is_empty());
+        // clipped regions have fewer rows than the others, which is not an error
+        assert_eq!(collect_near_chromosome_ends("edge_clip", occs, policy(EdgePolicy::Clip)).unwrap(), covered_rows(1..=3));
+        let expected = [vec![(-1, "NA".to_string()), (0, "NA".to_string())], covered_rows(1..=3)].concat();
+        assert_eq!(collect_near_chromosome_ends("edge_pad_na", occs, policy(EdgePolicy::PadNa)).unwrap(), expected);
+    }
+
+    #[test]
+    fn two_samples_in_turn() {
+        let occ_file = temporary_file("two_samples.occ");
+        let kinetics_files = [temporary_file("two_samples.a.csv"), temporary_file("two_samples.b.csv")];
+        let output_file = temporary_file("two_samples.csv");
+        std::fs::write(occ_file.path(), "chr1 1 +\nchr1 3 +\n").unwrap();
+        for (file, t_mean) in kinetics_files.iter().zip(["1.5", "2.5"]) {
+            let records = (2..=4).map(|tpl| format!("chr1,{},0,A,10,{},0.1,1.0,1.5,20,,,\nchr1,{},1,T,10,{},0.1,1.0,1.5,20,,,\n", tpl, t_mean, tpl, t_mean)).collect::<String>();
+            std::fs::write(file.path(), format!("refName,tpl,strand,base,score,tMean,tErr,modelPrediction,ipdRatio,coverage,frac,fracLow,fracUp\n{}", records)).unwrap();
         }
+        let input_options = InputOptions::new(occ_file.path().to_str().unwrap(), 1, 0);
+        let output_options = OutputOptions { sample_names: vec!["a".to_string(), "b".to_string()], ..OutputOptions::default() };
+        let kinetics_paths = kinetics_files.iter().map(|file| file.path()).collect::<Vec<_>>();
+        let n_rows = collect_ipd_summary_in_merged_occ(&kinetics_paths, &input_options, &output_options, output_file.path(), &mut Manifest::new(), &mut RunReport::new()).unwrap();
+        assert_eq!(n_rows, 8);
+
+        let mut reader = csv::Reader::from_path(output_file.path()).unwrap();
+        let header = reader.headers().unwrap().clone();
+        assert_eq!(header.get(0), Some("sample"));
+        let index = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (sample, src, strand, value) = (index("sample"), index("src"), index("strand"), index("value"));
+        let rows = reader.records().map(|record| {
+            let record = record.unwrap();
+            (record[sample].to_string(), record[src].to_string(), record[strand].to_string(), record[value].to_string())
+        }).collect::<Vec<_>>();
+        // all occurrences of sample a, then those of sample b
+        let expected = [("a", "1", "+", "1.5"), ("a", "1", "-", "1.5"), ("a", "2", "+", "1.5"), ("a", "2", "-", "1.5"),
+            ("b", "1", "+", "2.5"), ("b", "1", "-", "2.5"), ("b", "2", "+", "2.5"), ("b", "2", "-", "2.5")];
+        assert_eq!(rows, expected.map(|(a, b, c, d)| (a.to_string(), b.to_string(), c.to_string(), d.to_string())));
+    }
+
+    #[test]
+    fn multiple_value_columns() {
+        assert_eq!(ValueField::column_names("value", &[ValueField::IpdRatio]), vec!["value"]);
+        let value_fields = [ValueField::TMean, ValueField::Coverage];
+        assert_eq!(ValueField::column_names("Target1p", &value_fields), vec!["Target1p_tMean", "Target1p_coverage"]);
+        let header = Layout::Long.header(&[Column::Position, Column::Value, Column::Coverage], &value_fields, &RegionSpec::new(1, 0));
+        assert_eq!(header, vec!["position", "value_tMean", "value_coverage", "coverage"]);
+    }
+
+    #[test]
+    fn split_output_path1() {
+        assert_eq!(split_output_path("dir/out.csv.gz", "plus"), Path::new("dir/out.plus.csv.gz"));
+        assert_eq!(split_output_path("out", "minus"), Path::new("out.minus"));
     }
 }
 
-#[derive(Debug, Default)]
+impl TryFrom<MergedOcc> for IpdSummaryKey {
+    type Error = OccError;
+
+    fn try_from(merged_occ: MergedOcc) -> Result<Self, OccError> {
+        let strand = match merged_occ.strand {
+            '+' => 0,
+            '-' => 1,
+            strand => return Err(OccError::UnexpectedStrand { chr: merged_occ.refName, start: merged_occ.start, strand }),
+        };
+        // MergedOcc: 0-based even if read from a 1-based occ file, IpdSummary: 1-based
+        let tpl = merged_occ.start.checked_add(1)
+            .ok_or_else(|| OccError::Overflow { chr: merged_occ.refName.clone(), start: merged_occ.start, extension: 1 })?;
+        Ok(Self { refName: merged_occ.refName, tpl, strand })
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 #[allow(non_snake_case)]
 #[allow(dead_code)]
 struct IpdSummaryValue {
@@ -224,385 +845,3541 @@ struct IpdSummaryValue {
     frac: Option<f32>,
     fracLow: Option<f32>,
     fracUp: Option<f32>,
+    pwMean: Option<f32>,
+    pwErr: Option<f32>,
 }
 
-/// a record for a .merged_occ file, or a position list of motif occurrences
-#[derive(Debug, Deserialize)]
-#[allow(non_snake_case)]
-struct MergedOcc {
-    refName: String,
-    /// 0-based left-most position regardless of strand
-    start: i64,
-    strand: char,
+/// Handling of non-finite (NaN or infinite) frac values of ipdSummary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum NonFiniteFrac {
+    /// Write frac, fracLow, and fracUp as missing
+    Null,
+    /// Write them as they are
+    Keep,
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct TargetIpd {
-    position: i64,
-    strand: char,
-    value: f32,
-    label: String,
-    src: i64,
+/// Handling of occurrences with an unknown strand ('.'), e.g. in unstranded peak files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum UnknownStrand {
+    /// Read them as plus-strand occurrences
+    Plus,
+    /// Read each of them as a plus-strand and a minus-strand occurrence
+    Both,
+    /// Skip them as rejected records
+    Skip,
+    /// Stop with the exit code of an unexpected strand, even without --strict
+    Error,
 }
 
-impl TargetIpd {
-    fn create_label(position: i64, region_width: i64, region_extension: i64, strand: char) -> String {
-        let part = match position {
-            p if p <= 0 => panic!("[ERROR] Position ({}) is smaller than 1", p),
-            // start-side / upstream of the target region
-            p if p <= region_extension => 's',
-            // motif / target region
-            p if p <= region_extension + region_width => 'm',
-            // end-side / downstream of the target region
-            p if p <= 2 * region_extension + region_width => 'e',
-            p => panic!("[ERROR] Position ({}) is larger than the target region length", p),
-        };
-        let relative_position = match part {
-            's' => position,
-            'm' => position - region_extension,
-            'e' => position - region_extension - region_width,
-            _ => panic!("[ERROR] Unknown region part name"),
-        };
-        let label_strand = match strand {
-            '+' => 'p',
-            '-' => 'm',
-            _ => panic!("[ERROR] Unknown strand"),
-        };
-        format!("{}{}{}", part, relative_position, label_strand)
-    }
+/// Handling of occurrences on chromosomes absent from all kinetics inputs, usually because of different naming
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum MissingChrom {
+    /// Stop with an error listing the chromosomes of the kinetics inputs
+    Error,
+    /// Skip the occurrences with a warning
+    Skip,
+    /// Write rows without data for them
+    Zero,
+}
 
-    #[allow(dead_code)]
-    fn new(position: i64, strand: char, value: f32, src: i64, region_width: i64, region_extension: i64) -> Self {
-        Self {
-            position,
-            strand,
-            value,
-            label: Self::create_label(position, region_width, region_extension, strand),
-            src,
+/// Handling of rows of bases without kinetics data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum MissingPolicy {
+    /// Write zeros, or --na-string if given
+    Zero,
+    /// Write NA, or --na-string if given
+    Na,
+    /// Leave out the rows
+    SkipRow,
+    /// Leave out target regions with any such row
+    DropRegion,
+}
+
+impl MissingPolicy {
+    /// Rows of a target region in this policy
+    fn apply(&self, rows: Vec<TargetIpdRich>) -> Vec<TargetIpdRich> {
+        match self {
+            MissingPolicy::SkipRow => rows.into_iter().filter(|row| !row.missing).collect(),
+            MissingPolicy::DropRegion if rows.iter().any(|row| row.missing) => Vec::new(),
+            _ => rows,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-#[allow(non_snake_case)]
-struct TargetIpdRich {
-    /// Relative position in a target region
-    position: i64,
-    /// Relative strand in a target region
-    strand: char,
-    /// tMean (IPD) in a ipdSummary result
-    value: f32,
-    label: String,
-    /// Index of the source in targets
-    src: i64,
-    base: Option<char>,
-    score: u32,
-    tErr: f32,
-    modelPrediction: f32,
-    ipdRatio: f32,
-    coverage: u32,
-    /// Chromosome of this base in the source data
-    ref_chr: String,
-    /// Position (1-based) of this base in the source data
-    ref_position: i64,
-    ref_strand: u8,
-    region: String,
+/// Statistic of coverage in the target part of a region compared with --min-region-coverage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum RegionCoverageStat {
+    Mean,
+    Min,
 }
 
-impl TargetIpdRich {
-    const HEADER: &'static str = "position,strand,value,label,src,base,score,tErr,modelPrediction,ipdRatio,coverage,ref_chr,ref_position,ref_strand,region";
+/// Coordinate system of start positions in occ files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum OccCoords {
+    /// 0-based, e.g. lists derived from BED
+    #[clap(name = "0")]
+    ZeroBased,
+    /// 1-based, e.g. lists derived from GFF
+    #[clap(name = "1")]
+    OneBased,
+}
 
-    fn create_region(position: i64, region_width: i64, region_extension: i64) -> String {
-        match position {
-            p if p <= 0 => panic!("[ERROR] Position ({}) is smaller than 1", p),
-            // start-side / upstream of the target region
-            p if p <= region_extension => "Upstream",
-            // motif / target region
-            p if p <= region_extension + region_width => "Target",
-            // end-side / downstream of the target region
-            p if p <= 2 * region_extension + region_width => "Downstream",
-            p => panic!("[ERROR] Position ({}) is larger than the target region length", p),
-        }.to_string()
+impl OccCoords {
+    /// An occurrence read in this coordinate system with a 0-based start
+    fn to_zero_based(self, occ: MergedOcc) -> Result<MergedOcc, OccError> {
+        match self {
+            OccCoords::ZeroBased => Ok(occ),
+            OccCoords::OneBased => match occ.start.checked_sub(1) {
+                Some(start) => Ok(MergedOcc { start, ..occ }),
+                None => Err(OccError::Overflow { chr: occ.refName, start: occ.start, extension: 1 }),
+            },
+        }
+    }
+}
+
+impl IpdSummaryValue {
+    /// A kinetics field as a number; None for absent frac
+    fn value_of(&self, field: ValueField) -> Option<f32> {
+        match field {
+            ValueField::TMean => Some(self.tMean),
+            ValueField::IpdRatio => Some(self.ipdRatio),
+            ValueField::Score => Some(self.score as f32),
+            ValueField::Coverage => Some(self.coverage as f32),
+            ValueField::Frac => self.frac,
+        }
+    }
+
+    /// Set frac, fracLow, and fracUp to None if frac is not finite
+    fn null_nonfinite_frac(self) -> Self {
+        if self.frac.map_or(true, |frac| frac.is_finite()) {
+            self
+        } else {
+            Self { frac: None, fracLow: None, fracUp: None, ..self }
+        }
     }
 
-    fn new(position: i64, strand: char, src: i64, region_width: i64, region_extension: i64, key: IpdSummaryKey, values: &IpdSummaryValue) -> Self {
+    /// Convert IPD fields (tMean, tErr, and modelPrediction) and pulse widths from frames into milliseconds
+    fn frames_to_milliseconds(self, frame_rate_hz: f64) -> Self {
+        let ms_per_frame = (1000.0 / frame_rate_hz) as f32;
         Self {
-            position,
-            strand,
-            value: values.tMean,
-            label: TargetIpd::create_label(position, region_width, region_extension, strand),
-            src,
-            base: values.base,
-            score: values.score,
-            tErr: values.tErr,
-            modelPrediction: values.modelPrediction,
-            ipdRatio: values.ipdRatio,
-            coverage: values.coverage,
-            ref_chr: key.refName,
-            ref_position: key.tpl,
-            ref_strand: key.strand,
-            region: Self::create_region(position, region_width, region_extension),
+            tMean: self.tMean * ms_per_frame,
+            tErr: self.tErr * ms_per_frame,
+            modelPrediction: self.modelPrediction * ms_per_frame,
+            pwMean: self.pwMean.map(|v| v * ms_per_frame),
+            pwErr: self.pwErr.map(|v| v * ms_per_frame),
+            ..self
         }
     }
 }
 
-fn collect_ipd_summary_in_merged_occ<P: AsRef<Path>>(
-    kinetics_path: P, occ_path: P, occ_width: i64, occ_extension: i64, output_path: P) -> Result<(), Box<dyn Error>>
-{
-    let mut occ_reader = csv::ReaderBuilder::new()
-        .delimiter(b' ')
-        .has_headers(false)
-        .from_path(occ_path)?;
-    let mut occ_peekable = occ_reader.deserialize::<MergedOcc>().enumerate().peekable();
-    if occ_peekable.peek().is_none() {
-        use std::io::Write;
-        let mut output = std::fs::File::create(output_path)?;
-        output.write_all(TargetIpdRich::HEADER.as_bytes())?;
-        output.write_all(b"\n")?;
-        output.flush()?;
-        return Ok(());
-    }
-    let mut kinetics_reader = csv::Reader::from_path(kinetics_path)?;
-    let kinetics = kinetics_reader.deserialize::<IpdSummary>().map(|e| e.unwrap().into_pair()).collect::<HashMap<_,_>>();
-    let default_ipd_summary_value = IpdSummaryValue::default();
-    let target_kinetics = occ_peekable.flat_map(|(i, occ)| {
-        let target_key = IpdSummaryKey::from(occ.unwrap());
-        // generate key(-extension)..key(+width+extension) for each strand
-        let pre_target_keys = target_key.extend_without_strand(occ_extension, occ_extension + occ_width - 1);
-        let target_keys: Box<dyn Iterator<Item = _>> = match target_key.strand {
-            0 => Box::new(pre_target_keys),
-            1 => Box::new(pre_target_keys.rev()),
-            _ => panic!("Unexpected strand"),
-        };
-        let target_vals = target_keys.enumerate().map(|(j, key)| {
-            let target_val = kinetics.get(&key).unwrap_or(&default_ipd_summary_value);
-            let target_strand = if j % 2 == 0 { '+' } else { '-' };
-            //TargetIpd::new(((j / 2) + 1) as i64, target_strand, target_val.tMean, (i + 1) as i64, occ_width, occ_extension)
-            TargetIpdRich::new(((j / 2) + 1) as i64, target_strand, (i + 1) as i64, occ_width, occ_extension, key, target_val)
-        }).collect::<Vec<_>>();
-        assert_eq!(target_vals.len() as i64, (occ_extension * 2 + occ_width) * 2, "Unexpected length of results for a motif occ");
-        target_vals
-    });
-    let mut result_writer = csv::Writer::from_path(output_path)?;
-    for target in target_kinetics {
-        result_writer.serialize(target)?;
+/// Open a file skipping the UTF-8 byte order mark written at its start by some Windows programs
+fn open_without_bom<P: AsRef<Path>>(path: P) -> std::io::Result<std::io::BufReader<std::fs::File>> {
+    use std::io::BufRead;
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    if reader.fill_buf()?.starts_with(&[0xef, 0xbb, 0xbf]) {
+        reader.consume(3);
     }
-    result_writer.flush()?;
-    Ok(())
+    Ok(reader)
 }
 
-/// Chromosomal kinetics data for PacBio ipdSummary output in HDF5 format
-#[derive(Default)]
+/// Whether the first line of an occ file other than empty and comment lines is a header such as `refName start strand`,
+/// i.e. its second field is not a number
+fn has_occ_header(occ_path: &str) -> Result<bool, Box<dyn Error>> {
+    use std::io::BufRead;
+    for line in open_without_bom(occ_path)?.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        return Ok(line.split(' ').nth(1).is_some_and(|field| field.trim().parse::<i64>().is_err()));
+    }
+    Ok(false)
+}
+
+/// a record for a .merged_occ file, or a position list of motif occurrences
+#[derive(Debug, Clone, Deserialize)]
 #[allow(non_snake_case)]
-struct ChrKineticsHdf5 {
-    tpl: Vec<u32>,
-    strand: Vec<u8>,
-    // convert into Vec<Option<char>>?
-    base: Vec<String>,
-    score: Vec<u32>,
-    tMean: Vec<f32>,
-    tErr: Vec<f32>,
-    modelPrediction: Vec<f32>,
-    ipdRatio: Vec<f32>,
-    coverage: Vec<u32>,
-    frac: Vec<f32>,
-    fracLow: Vec<f32>,
-    fracUp: Vec<f32>,
+struct MergedOcc {
+    refName: String,
+    /// 0-based left-most position regardless of strand, converted on reading a 1-based occ file (--occ-coords 1)
+    start: i64,
+    strand: char,
+    /// True if `strand` is assumed for an occurrence of an unknown strand
+    #[serde(skip)]
+    strand_assumed: bool,
+    /// Number of other occurrences whose extended target regions overlap that of this one, by --overlapping-occ count
+    #[serde(skip)]
+    overlap_count: u32,
 }
 
-impl ChrKineticsHdf5 {
-    fn read_hdf5_f32(data: Dataset) -> Vec<f32> {
-        assert_eq!(data.dtype().unwrap().to_descriptor().unwrap(), TypeDescriptor::Float(FloatSize::U4));
-        data.read_raw::<f32>().unwrap()
+impl MergedOcc {
+    /// Identifier of this occurrence independent of its order in the occ file:
+    /// the first 8 bytes of SHA-256 of refName, start, and strand separated by tabs
+    fn stable_id(&self) -> u64 {
+        use sha2::{Digest, Sha256};
+        let digest = Sha256::digest(format!("{}\t{}\t{}", self.refName, self.start, self.strand).as_bytes());
+        u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 digest is longer than 8 bytes"))
     }
+}
+
+/// Values shared by all rows of an occurrence
+#[derive(Debug, Clone, Copy, Default)]
+struct OccValues {
+    occ_id: u64,
+    gc_target: Option<f32>,
+    gc_window: Option<f32>,
+    strand_assumed: bool,
+    overlap_count: u32,
+}
+
+/// tMean of control kinetics: chromosome -> (1-based position, strand) -> tMean
+type ControlKinetics = HashMap<String, HashMap<(i64, u8), f32>>;
+
+/// Options on how to read inputs: motif occurrences, the target regions around them, and kinetics
+#[derive(Debug, Clone)]
+struct InputOptions {
+    occ_path: String,
+    occ_width: i64,
+    /// Extension to the left of target regions in reference coordinates
+    occ_extension: i64,
+    /// Extension to the right of target regions in reference coordinates
+    occ_extension_down: i64,
+    /// Apply `occ_extension` upstream and `occ_extension_down` downstream on the strand of each occurrence instead
+    strand_relative_extension: bool,
+    /// Chromosome lengths to reject occurrences starting beyond chromosome ends
+    chrom_sizes: Option<ChromSizes>,
+    /// How extended target regions running past the ends of chromosomes in `chrom_sizes` are handled;
+    /// None writes rows without data for positions beyond them
+    chrom_end_policy: Option<ChromEndPolicy>,
+    /// How extended target regions running before position 1 are handled; None writes rows without data for them
+    edge_policy: Option<EdgePolicy>,
+    /// Skip malformed input records and duplicate occurrences instead of stopping with an error
+    permissive: bool,
+    /// Stop at a malformed occ record instead of skipping it with a warning, unless `permissive`
+    strict: bool,
+    /// Skip malformed kinetics CSV records even without `permissive`
+    skip_bad_kinetics: bool,
+    /// Skip duplicate occurrences (same refName, start, and strand), keeping the first one
+    dedup_occ: bool,
+    /// Tab-separated file of skipped input records and reasons
+    rejects_path: Option<String>,
+    /// Frame rate of the sequencer to report IPDs in milliseconds; None keeps IPDs in frames
+    frame_rate_hz: Option<f64>,
+    reference: Option<Reference>,
+    /// Tracks whose values are written as extra columns
+    annotation_tracks: Vec<AnnotationTrack>,
+    /// Number of minus-strand occurrences whose rows are printed to check their orientation
+    audit_strand: Option<usize>,
+    nonfinite_frac: NonFiniteFrac,
+    unknown_strand: UnknownStrand,
+    occ_coords: OccCoords,
+    missing_chrom: MissingChrom,
+    missing_policy: MissingPolicy,
+    /// tMean and ipdRatio of rows of bases without kinetics data instead of zeros
+    fill_value: Option<f32>,
+    /// Coverage below which kinetics values are treated as absent
+    min_coverage: Option<u32>,
+    /// Score below which kinetics values are treated as absent
+    min_score: Option<u32>,
+    /// Inclusive range of frac out of which (or without frac) kinetics values are treated as absent
+    frac_range: Option<(f32, f32)>,
+    /// Minimum statistic of coverage in the target part for an occurrence to be written
+    min_region_coverage: Option<(f64, RegionCoverageStat)>,
+    /// Half width of the reference sequence context of each row
+    context: Option<usize>,
+    /// Compute GC fractions of target regions and extended windows
+    gc_content: bool,
+    /// Maximum number of rows of the long layout allowed without confirmation
+    max_rows: u64,
+    /// Continue with warnings even if the output looks suspiciously large
+    confirmed: bool,
+    /// Show progress bars of loading kinetics and collecting target regions on stderr
+    progress: bool,
+    /// Occurrences compared with those of `occ_path`
+    control_occ_path: Option<String>,
+    /// tMean of control kinetics
+    control_kinetics: Option<ControlKinetics>,
+    /// Pool all kinetics inputs into one dataset instead of collecting each of them as a sample
+    merge_replicates: bool,
+    /// Random positions to standardize values into zscore_background
+    background: Option<BackgroundOptions>,
+    /// Read only up to this number of accepted occurrences from each occ file
+    max_occ: Option<usize>,
+    /// Anchor of occurrences of the given width, which are read as occurrences of width 1
+    anchor: Option<(Anchor, i64)>,
+    /// Windows tiling chromosomes of the kinetics inputs, collected instead of occurrences of `occ_path`
+    windows: Option<Windows>,
+    /// Chromosomes of occurrences and kinetics read
+    chrom_filter: ChromFilter,
+    /// Kinetics chromosome names of chromosome names in occ files
+    chrom_alias: ChromAlias,
+    /// Intervals where extended target regions of occurrences must not overlap
+    blacklist: Option<Blacklist>,
+    /// How occurrences with overlapping extended target regions are handled; None keeps them as they are
+    overlapping_occ: Option<OverlappingOcc>,
+}
 
-    fn read_hdf5_u32(data: Dataset) -> Vec<u32> {
-        assert_eq!(data.dtype().unwrap().to_descriptor().unwrap(), TypeDescriptor::Unsigned(IntSize::U4));
-        data.read_raw::<u32>().unwrap()
+impl InputOptions {
+    /// Options with the defaults of the command line
+    fn new(occ_path: &str, occ_width: i64, occ_extension: i64) -> Self {
+        Self {
+            occ_path: occ_path.to_string(),
+            occ_width,
+            occ_extension,
+            occ_extension_down: occ_extension,
+            strand_relative_extension: false,
+            chrom_sizes: None,
+            chrom_end_policy: None,
+            edge_policy: None,
+            permissive: false,
+            strict: false,
+            skip_bad_kinetics: false,
+            dedup_occ: false,
+            rejects_path: None,
+            frame_rate_hz: None,
+            reference: None,
+            annotation_tracks: Vec::new(),
+            audit_strand: None,
+            nonfinite_frac: NonFiniteFrac::Null,
+            unknown_strand: UnknownStrand::Error,
+            occ_coords: OccCoords::ZeroBased,
+            missing_chrom: MissingChrom::Error,
+            missing_policy: MissingPolicy::Zero,
+            fill_value: None,
+            min_coverage: None,
+            min_score: None,
+            frac_range: None,
+            min_region_coverage: None,
+            context: None,
+            gc_content: false,
+            max_rows: u64::MAX,
+            confirmed: false,
+            progress: false,
+            control_occ_path: None,
+            control_kinetics: None,
+            merge_replicates: false,
+            background: None,
+            max_occ: None,
+            anchor: None,
+            windows: None,
+            chrom_filter: ChromFilter::default(),
+            chrom_alias: ChromAlias::default(),
+            blacklist: None,
+            overlapping_occ: None,
+        }
     }
 
-    fn read_hdf5_u8(data: Dataset) -> Vec<u8> {
-        assert_eq!(data.dtype().unwrap().to_descriptor().unwrap(), TypeDescriptor::Unsigned(IntSize::U1));
-        data.read_raw::<u8>().unwrap()
+    /// Number of samples collected from `n_inputs` kinetics inputs
+    fn n_samples(&self, n_inputs: usize) -> usize {
+        if self.merge_replicates { 1 } else { n_inputs }
     }
 
-    fn read_hdf5_str(data: Dataset) -> Vec<String> {
-        assert_eq!(data.dtype().unwrap().to_descriptor().unwrap(), TypeDescriptor::FixedAscii(1));
-        data.read_raw::<FixedAscii<1>>().unwrap().iter().map(|e| e.as_str().to_string()).collect()
+    /// Value of a sample at a position given `value_of` each kinetics input, or values of all inputs merged;
+    /// None below the minimum coverage or score, or out of the frac range
+    fn sample_value<F: Fn(usize) -> Option<IpdSummaryValue>>(&self, sample: usize, n_inputs: usize, value_of: F) -> Option<IpdSummaryValue> {
+        let value = if self.merge_replicates {
+            let values = (0..n_inputs).filter_map(&value_of).collect::<Vec<_>>();
+            merge_values(&values.iter().collect::<Vec<_>>())
+        } else {
+            value_of(sample)
+        };
+        value.filter(|value| self.min_coverage.map_or(true, |min_coverage| value.coverage >= min_coverage))
+            .filter(|value| self.min_score.map_or(true, |min_score| value.score >= min_score))
+            .filter(|value| self.frac_range.map_or(true, |(min, max)| value.frac.is_some_and(|frac| min <= frac && frac <= max)))
     }
 
-    fn new(chr_file: hdf5::Group) -> Self {
-        Self {
-            tpl: Self::read_hdf5_u32(chr_file.dataset("tpl").unwrap()),
-            strand: Self::read_hdf5_u8(chr_file.dataset("strand").unwrap()),
-            base: Self::read_hdf5_str(chr_file.dataset("base").unwrap()),
-            score: Self::read_hdf5_u32(chr_file.dataset("score").unwrap()),
-            tMean: Self::read_hdf5_f32(chr_file.dataset("tMean").unwrap()),
-            tErr: Self::read_hdf5_f32(chr_file.dataset("tErr").unwrap()),
-            modelPrediction: Self::read_hdf5_f32(chr_file.dataset("modelPrediction").unwrap()),
-            ipdRatio: Self::read_hdf5_f32(chr_file.dataset("ipdRatio").unwrap()),
-            coverage: Self::read_hdf5_u32(chr_file.dataset("coverage").unwrap()),
-            frac: Self::read_hdf5_f32(chr_file.dataset("frac").unwrap()),
-            fracLow: Self::read_hdf5_f32(chr_file.dataset("fracLow").unwrap()),
-            fracUp: Self::read_hdf5_f32(chr_file.dataset("fracUp").unwrap()),
-        }
-    }
-
-    fn kinetics_datasets_from_hdf5_path<P: AsRef<Path>>(path: P) -> Result<HashMap<String, ChrKineticsHdf5>, Box<dyn Error>> {
-        let file = hdf5::File::open(path)?;
-        let datasets = file.member_names()?.into_iter().map(|chr| {
-            let chr_file = file.group(&chr).unwrap();
-            let chr_kinetics = Self::new(chr_file);
-            (chr, chr_kinetics)
-        }).collect::<HashMap<_,_>>();
-        file.close()?;
-        Ok(datasets)
+    /// Convert IPD fields of a kinetics value into the requested unit, and handle its non-finite frac.
+    /// Both backends pass values through here, so that their outputs are comparable.
+    fn normalize_value(&self, value: IpdSummaryValue) -> IpdSummaryValue {
+        let value = match self.nonfinite_frac {
+            NonFiniteFrac::Null => value.null_nonfinite_frac(),
+            NonFiniteFrac::Keep => value,
+        };
+        match self.frame_rate_hz {
+            Some(frame_rate_hz) => value.frames_to_milliseconds(frame_rate_hz),
+            None => value,
+        }
     }
 
-    fn get_ipd_summary_value(&self, key: &IpdSummaryKey) -> IpdSummaryValue {
-        // IpdSummaryKey tpl (position) is 1-based
-        let pre_index: i64 = (key.tpl - 1) * 2 + (key.strand as i64);
-        let opt_index: Option<usize> = if pre_index >= 0 {
-            Some(pre_index.try_into().unwrap_or_else(|_|panic!("Key position cannot be converted to usize variable")))
+    /// Skip a malformed record in the permissive mode, or return an error
+    fn skip_malformed(&self, rejects: &mut Rejects, source: &'static str, line: u64, record: String, error: String) -> Result<(), Box<dyn Error>> {
+        if self.permissive {
+            rejects.add(source, line, "malformed_record", record);
+            Ok(())
         } else {
-            None
-        };
-        match opt_index {
-            // TODO?: we can use get_unchecked to skip index bound check
-            Some(index) if index < self.coverage.len() && self.coverage[index] > 0 => {
-                debug_assert_eq!(self.tpl[index] as i64, key.tpl);
-                debug_assert_eq!(self.strand[index], key.strand);
-                let has_frac = self.frac[index].is_finite();
-                IpdSummaryValue {
-                    base: self.base[index].chars().next(),
-                    score: self.score[index],
-                    tMean: self.tMean[index],
-                    tErr: self.tErr[index],
-                    modelPrediction: self.modelPrediction[index],
-                    ipdRatio: self.ipdRatio[index],
-                    coverage: self.coverage[index],
-                    frac: if has_frac { Some(self.frac[index]) } else { None },
-                    fracLow: if has_frac { Some(self.fracLow[index]) } else { None },
-                    fracUp: if has_frac { Some(self.fracUp[index]) } else { None },
+            Err(format!("Malformed {} record at line {}: {}", source, line, error).into())
+        }
+    }
+
+    /// Skip a malformed occ record with a warning of its line unless strict, or handle it as other malformed records
+    fn skip_malformed_occ(&self, rejects: &mut Rejects, source: &'static str, line: u64, record: String, error: String) -> Result<(), Box<dyn Error>> {
+        if !self.strict && !self.permissive {
+            eprintln!("[WARNING] Skipped a malformed {} record at line {}: {}", source, line, error);
+            rejects.add(source, line, "malformed_record", record);
+            return Ok(());
+        }
+        self.skip_malformed(rejects, source, line, record, error)
+    }
+
+    /// Skip a malformed kinetics CSV record with --skip-bad-kinetics-rows or --permissive, or return an error with its file and line
+    fn skip_malformed_kinetics(&self, rejects: &mut Rejects, path: &Path, line: u64, record: String, error: String) -> Result<(), Box<dyn Error>> {
+        if self.skip_bad_kinetics || self.permissive {
+            rejects.add("kinetics", line, "malformed_record", record);
+            Ok(())
+        } else {
+            Err(format!("Malformed kinetics record at {}:{}: {} (see --skip-bad-kinetics-rows)", path.display(), line, error).into())
+        }
+    }
+
+    /// Read occurrences with their 0-based indices in the occ file, except for rejected ones,
+    /// stopping after `max_occ` of them if given
+    fn read_occ(&self, rejects: &mut Rejects) -> Result<Vec<(usize, MergedOcc)>, Box<dyn Error>> {
+        self.read_occ_path(&self.occ_path, "occ", rejects)
+    }
+
+    /// Read occurrences in an occ file, rejected as records of `source`
+    fn read_occ_path(&self, occ_path: &str, source: &'static str, rejects: &mut Rejects) -> Result<Vec<(usize, MergedOcc)>, Box<dyn Error>> {
+        let header = has_occ_header(occ_path)?;
+        if header {
+            eprintln!("[INFO] Skipped the header line of {}", occ_path);
+        }
+        // CRLF line endings are handled by the reader
+        let mut occ_reader = csv::ReaderBuilder::new()
+            .delimiter(b' ')
+            .has_headers(header)
+            .comment(Some(b'#'))
+            .from_reader(open_without_bom(occ_path)?);
+        let mut accepted = Vec::new();
+        // line numbers of accepted occurrences
+        let mut lines = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut record = csv::StringRecord::new();
+        for i in 0.. {
+            if self.max_occ.is_some_and(|max_occ| accepted.len() >= max_occ) {
+                eprintln!("[INFO] Read only the first {} occurrences of {} (--max-occ)", accepted.len(), occ_path);
+                break;
+            }
+            let line = match occ_reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => record.position().map_or(0, |p| p.line()),
+                Err(e) => {
+                    self.skip_malformed_occ(rejects, source, e.position().map_or(0, |p| p.line()), String::new(), e.to_string())?;
+                    continue;
+                },
+            };
+            let original = || record.iter().collect::<Vec<_>>().join(" ");
+            let occs = match record.deserialize::<MergedOcc>(None) {
+                Ok(occ) if occ.strand == '+' || occ.strand == '-' => vec![occ],
+                Ok(occ) if occ.strand == '.' && self.unknown_strand != UnknownStrand::Error => {
+                    let assumed = |strand: char| MergedOcc { strand, strand_assumed: true, ..occ.clone() };
+                    match self.unknown_strand {
+                        UnknownStrand::Plus => vec![assumed('+')],
+                        UnknownStrand::Both => vec![assumed('+'), assumed('-')],
+                        _ => {
+                            rejects.add(source, line, "unknown_strand", original());
+                            continue;
+                        },
+                    }
+                },
+                Ok(occ) if occ.strand == '.' || (self.strict && !self.permissive) => {
+                    // stop with the exit code of an unexpected strand rather than that of a malformed record;
+                    // --unknown-strand-policy error stops even without --strict
+                    let occ = self.occ_coords.to_zero_based(occ)?;
+                    return Err(OccError::UnexpectedStrand { chr: occ.refName, start: occ.start, strand: occ.strand }.into());
+                },
+                Ok(occ) => {
+                    self.skip_malformed_occ(rejects, source, line, original(), format!("unexpected strand: {}", occ.strand))?;
+                    continue;
+                },
+                Err(e) => {
+                    self.skip_malformed_occ(rejects, source, line, original(), e.to_string())?;
+                    continue;
+                },
+            };
+            for occ in occs {
+                let occ = self.occ_coords.to_zero_based(occ)?;
+                let occ = if self.chrom_alias.is_identity() { occ } else { MergedOcc { refName: self.chrom_alias.map(&occ.refName), ..occ } };
+                self.check_occ(&occ)?;
+                if !self.chrom_filter.includes(&occ.refName) {
+                    rejects.add(source, line, "excluded_chromosome", original());
+                } else if let Some(reason) = self.chrom_sizes.as_ref().and_then(|sizes| sizes.rejection_reason(&occ.refName, occ.start)) {
+                    rejects.add(source, line, &reason, original());
+                } else if (self.permissive || self.dedup_occ) && !seen.insert((occ.refName.clone(), occ.start, occ.strand)) {
+                    rejects.add(source, line, "duplicate_occ", original());
+                } else {
+                    let occ = match self.anchor {
+                        Some((anchor, width)) => {
+                            let occ = anchor.anchor(occ, width)?;
+                            // the extended region is now around the anchor base
+                            self.check_occ(&occ)?;
+                            occ
+                        },
+                        None => occ,
+                    };
+                    let (start, end) = self.extended_interval(&occ);
+                    let beyond_end = self.chrom_end_policy.filter(|&policy| policy != ChromEndPolicy::Clip)
+                        .and_then(|policy| self.chrom_sizes.as_ref().and_then(|sizes| sizes.region_reason(&occ.refName, start, end)).map(|reason| (policy, reason)));
+                    if self.edge_policy == Some(EdgePolicy::Skip) && start < 0 {
+                        rejects.add(source, line, "region_before_chromosome_start", original());
+                    } else if let Some((policy, reason)) = beyond_end {
+                        if policy == ChromEndPolicy::Error {
+                            return Err(format!("Extended region {}:{}-{} of the {} record at line {} runs past the chromosome end: {} (see --chrom-end-policy)",
+                                occ.refName, start, end, source, line, reason).into());
+                        }
+                        rejects.add(source, line, &reason, original());
+                    } else if self.is_blacklisted(&occ) {
+                        rejects.add(source, line, "blacklisted", original());
+                    } else {
+                        accepted.push((i, occ));
+                        lines.push(line);
+                    }
                 }
+            }
+        }
+        Ok(match self.overlapping_occ {
+            Some(how) => self.resolve_overlaps(accepted, &lines, how, source, rejects),
+            None => accepted,
+        })
+    }
+
+    /// Count overlaps of each occurrence with the others, or reject all but the left-most occurrence of each cluster of overlapping ones
+    fn resolve_overlaps(&self, occs: Vec<(usize, MergedOcc)>, lines: &[u64], how: OverlappingOcc, source: &'static str, rejects: &mut Rejects) -> Vec<(usize, MergedOcc)> {
+        let intervals = occs.iter().map(|(_, occ)| {
+            let (start, end) = self.extended_interval(occ);
+            (occ.refName.as_str(), start, end)
+        }).collect::<Vec<_>>();
+        match how {
+            OverlappingOcc::Count => {
+                let counts = overlaps::overlap_counts(&intervals);
+                let n_overlapping = counts.iter().filter(|&&count| count > 0).count();
+                eprintln!("[INFO] {} of {} {} occurrences overlap others", n_overlapping, occs.len(), source);
+                occs.into_iter().zip(counts).map(|((i, occ), overlap_count)| (i, MergedOcc { overlap_count, ..occ })).collect()
+            },
+            OverlappingOcc::Collapse => {
+                let representatives = overlaps::cluster_representatives(&intervals);
+                occs.into_iter().zip(representatives).zip(lines).filter_map(|(((i, occ), representative), &line)| {
+                    if !representative {
+                        rejects.add(source, line, "overlapping_occ", format!("{} {} {}", occ.refName, occ.start, occ.strand));
+                    }
+                    representative.then_some((i, occ))
+                }).collect()
             },
-            _ => IpdSummaryValue::default(),
         }
     }
-}
 
-fn collect_hdf5_ipd_summary_in_merged_occ<P: AsRef<Path>>(
-    kinetics_path: P, occ_path: P, occ_width: i64, occ_extension: i64, output_path: P) -> Result<(), Box<dyn Error>>
-{
-    let mut occ_reader = csv::ReaderBuilder::new()
-        .delimiter(b' ')
-        .has_headers(false)
-        .from_path(occ_path)?;
-    let mut occ_peekable = occ_reader.deserialize::<MergedOcc>().enumerate().peekable();
-    if occ_peekable.peek().is_none() {
-        use std::io::Write;
-        let mut output = std::fs::File::create(output_path)?;
-        output.write_all(TargetIpdRich::HEADER.as_bytes())?;
-        output.write_all(b"\n")?;
-        output.flush()?;
-        return Ok(());
-    }
-    let default_chr_kinetics = ChrKineticsHdf5::default();
-    let kinetics_datasets = ChrKineticsHdf5::kinetics_datasets_from_hdf5_path(kinetics_path)?;
-    let target_kinetics = occ_peekable.flat_map(|(i, occ)| {
-        let target_key = IpdSummaryKey::from(occ.unwrap());
-        // generate key(-extension)..key(+width+extension) for each strand
-        let pre_target_keys = target_key.extend_without_strand(occ_extension, occ_extension + occ_width - 1);
-        let target_keys: Box<dyn Iterator<Item = _>> = match target_key.strand {
-            0 => Box::new(pre_target_keys),
-            1 => Box::new(pre_target_keys.rev()),
-            _ => panic!("Unexpected strand"),
+    /// Read tMean of a control kinetics CSV file, in the same unit as the main kinetics
+    fn read_control_kinetics(&self, control_path: &str) -> Result<ControlKinetics, Box<dyn Error>> {
+        let mut rejects = Rejects::default();
+        let mut control_kinetics = ControlKinetics::new();
+        for (key, value) in self.read_kinetics_csv(control_path, &mut rejects)? {
+            control_kinetics.entry(key.refName).or_default().insert((key.tpl, key.strand), value.tMean);
+        }
+        rejects.report();
+        Ok(control_kinetics)
+    }
+
+    /// Read a kinetics CSV file into a map
+    fn read_kinetics_csv<P: AsRef<Path>>(&self, kinetics_path: P, rejects: &mut Rejects) -> Result<HashMap<IpdSummaryKey, IpdSummaryValue>, Box<dyn Error>> {
+        let kinetics_path = kinetics_path.as_ref();
+        let mut kinetics_reader = csv::Reader::from_reader(open_without_bom(kinetics_path).map_err(|e| format!("{}: {}", kinetics_path.display(), e))?);
+        let headers = kinetics_reader.headers().map_err(|e| format!("{}: {}", kinetics_path.display(), e))?.clone();
+        let mut kinetics = HashMap::new();
+        let mut record = csv::StringRecord::new();
+        let size = std::fs::metadata(kinetics_path).map_or(0, |m| m.len());
+        let mut progress = Progress::new(&format!("Loading {}", kinetics_path.display()), size, ProgressUnit::Bytes, self.progress);
+        for n in 1.. {
+            // update the position only occasionally to keep the loop fast
+            if n % PROGRESS_ROWS == 0 {
+                progress.set_position(kinetics_reader.position().byte());
+            }
+            match kinetics_reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => match record.deserialize::<IpdSummary>(Some(&headers)) {
+                    Ok(summary) => {
+                        let (key, value) = summary.into_pair();
+                        // a base with non-finite tMean (nan or inf) is handled as a base without data per --missing-policy
+                        if self.chrom_filter.includes(&key.refName) && value.tMean.is_finite() {
+                            kinetics.insert(key, self.normalize_value(value));
+                        }
+                    },
+                    Err(e) => {
+                        let line = record.position().map_or(0, |p| p.line());
+                        // name the offending field
+                        let field = match e.kind() {
+                            csv::ErrorKind::Deserialize { err, .. } => err.field().and_then(|i| headers.get(i as usize)),
+                            _ => None,
+                        };
+                        let error = match field {
+                            Some(field) => format!("{} (field {})", e, field),
+                            None => e.to_string(),
+                        };
+                        self.skip_malformed_kinetics(rejects, kinetics_path, line, record.iter().collect::<Vec<_>>().join(","), error)?;
+                    },
+                },
+                Err(e) => self.skip_malformed_kinetics(rejects, kinetics_path, e.position().map_or(0, |p| p.line()), String::new(), e.to_string())?,
+            }
+        }
+        progress.set_position(size);
+        progress.finish();
+        Ok(kinetics)
+    }
+
+    /// Extensions to the left and right of a target region on `strand` in reference coordinates
+    fn flanks(&self, strand: char) -> (i64, i64) {
+        if self.strand_relative_extension && strand == '-' {
+            (self.occ_extension_down, self.occ_extension)
+        } else {
+            (self.occ_extension, self.occ_extension_down)
+        }
+    }
+
+    /// Check that the strand of an occurrence is known and that coordinates of its extended target region do not overflow,
+    /// which would panic later
+    fn check_occ(&self, occ: &MergedOcc) -> Result<(), OccError> {
+        if occ.strand != '+' && occ.strand != '-' {
+            return Err(OccError::UnexpectedStrand { chr: occ.refName.clone(), start: occ.start, strand: occ.strand });
+        }
+        let (left, right) = self.flanks(occ.strand);
+        let overflow = || OccError::Overflow { chr: occ.refName.clone(), start: occ.start, extension: left.max(right) };
+        occ.start.checked_sub(left).ok_or_else(overflow)?;
+        // the last position is also 1-based
+        occ.start.checked_add(self.occ_width).and_then(|end| end.checked_add(right)).and_then(|end| end.checked_add(1)).ok_or_else(overflow)?;
+        Ok(())
+    }
+
+    /// 0-based half-open interval of the extended target region of an occurrence
+    fn extended_interval(&self, occ: &MergedOcc) -> (i64, i64) {
+        let (left, right) = self.flanks(occ.strand);
+        (occ.start - left, occ.start + self.occ_width + right)
+    }
+
+    /// Whether rows of an occurrence have coverage in the target part (both relative strands) below --min-region-coverage;
+    /// a target part without rows, e.g. clipped by --edge-policy clip, has no coverage
+    fn is_poorly_covered(&self, rows: &[TargetIpdRich]) -> bool {
+        let (min_coverage, stat) = match self.min_region_coverage {
+            Some(min_region_coverage) => min_region_coverage,
+            None => return false,
         };
-        let chr_kinetics = kinetics_datasets.get(&target_key.refName).unwrap_or(&default_chr_kinetics);
-        let target_vals = target_keys.enumerate().map(|(j, key)| {
-            let target_val = chr_kinetics.get_ipd_summary_value(&key);
-            let target_strand = if j % 2 == 0 { '+' } else { '-' };
-            //TargetIpd::new(((j / 2) + 1) as i64, target_strand, target_val.tMean, (i + 1) as i64, occ_width, occ_extension)
-            TargetIpdRich::new(((j / 2) + 1) as i64, target_strand, (i + 1) as i64, occ_width, occ_extension, key, &target_val)
-        }).collect::<Vec<_>>();
-        assert_eq!(target_vals.len() as i64, (occ_extension * 2 + occ_width) * 2, "Unexpected length of results for a motif occ");
-        target_vals
-    });
-    let mut result_writer = csv::Writer::from_path(output_path)?;
-    for target in target_kinetics {
-        result_writer.serialize(target)?;
+        let coverages = rows.iter().filter(|row| !matches!(row.region.as_str(), "Upstream" | "Downstream"))
+            .map(|row| row.coverage as f64).collect::<Vec<_>>();
+        let coverage = match stat {
+            _ if coverages.is_empty() => 0.0,
+            RegionCoverageStat::Mean => coverages.iter().sum::<f64>() / coverages.len() as f64,
+            RegionCoverageStat::Min => coverages.iter().copied().fold(f64::INFINITY, f64::min),
+        };
+        coverage < min_coverage
+    }
+
+    /// Whether every target region has all of its rows
+    fn has_full_regions(&self) -> bool {
+        self.edge_policy != Some(EdgePolicy::Clip) && self.missing_policy != MissingPolicy::SkipRow
+    }
+
+    /// Whether the row of `key` is left out of its target region by --edge-policy clip
+    fn is_clipped(&self, key: &IpdSummaryKey) -> bool {
+        self.edge_policy == Some(EdgePolicy::Clip) && key.tpl < 1
+    }
+
+    /// Whether the extended target region of an occurrence overlaps the blacklist
+    fn is_blacklisted(&self, occ: &MergedOcc) -> bool {
+        let (start, end) = self.extended_interval(occ);
+        self.blacklist.as_ref().is_some_and(|blacklist| blacklist.overlaps(&occ.refName, start, end))
+    }
+
+    /// Keys of rows of the extended target region of an occurrence at `key`
+    fn target_keys<'a>(&self, key: &'a IpdSummaryKey) -> Result<Box<dyn Iterator<Item = IpdSummaryKey> + 'a>, OccError> {
+        if self.strand_relative_extension {
+            Ok(key.target_keys_on_strand(self.occ_width, self.occ_extension, self.occ_extension_down)?)
+        } else {
+            key.target_keys(self.occ_width, self.occ_extension, self.occ_extension_down)
+        }
+    }
+
+    /// Values shared by all rows of an occurrence
+    fn occ_values(&self, occ: &MergedOcc) -> OccValues {
+        let gc = |start: i64, end: i64| self.reference.as_ref().and_then(|r| r.gc_fraction(&occ.refName, start, end));
+        let target_end = occ.start + self.occ_width;
+        let (left, right) = self.flanks(occ.strand);
+        OccValues {
+            occ_id: occ.stable_id(),
+            gc_target: if self.gc_content { gc(occ.start, target_end) } else { None },
+            gc_window: if self.gc_content { gc(occ.start - left, target_end + right) } else { None },
+            strand_assumed: occ.strand_assumed,
+            overlap_count: occ.overlap_count,
+        }
+    }
+
+    /// Fill values of the occurrence of a row, and values of annotation tracks (regardless of strand),
+    /// the sequence context, and the control tMean ratio from its reference position
+    fn annotate_row(&self, target: &mut TargetIpdRich, occ_values: &OccValues) {
+        target.occ_id = occ_values.occ_id;
+        target.gc_target = occ_values.gc_target;
+        target.gc_window = occ_values.gc_window;
+        target.strand_assumed = occ_values.strand_assumed;
+        target.overlap_count = occ_values.overlap_count;
+        let before_start = self.edge_policy == Some(EdgePolicy::PadNa) && target.ref_position < 1;
+        let beyond_end = self.chrom_end_policy == Some(ChromEndPolicy::Clip)
+            && self.chrom_sizes.as_ref().is_some_and(|sizes| sizes.is_off_contig(&target.ref_chr, target.ref_position));
+        if before_start || beyond_end {
+            target.off_contig = true;
+            target.missing = true;
+        }
+        if let Some(fill_value) = self.fill_value.filter(|_| target.missing) {
+            target.value = fill_value;
+            target.ipdRatio = fill_value;
+        }
+        if let Some(control_kinetics) = self.control_kinetics.as_ref() {
+            let control = control_kinetics.get(&target.ref_chr).and_then(|chr| chr.get(&(target.ref_position, target.ref_strand)));
+            target.ipdRatio_control = control.filter(|&&control| !target.missing && control > 0.0).map(|control| target.value / control);
+        }
+        target.annotations = self.annotation_tracks.iter().map(|track| track.value_at(&target.ref_chr, target.ref_position)).collect();
+        if let (Some(k), Some(reference)) = (self.context, self.reference.as_ref()) {
+            let k = k as i64;
+            // ref_position is 1-based
+            let start = target.ref_position - 1 - k;
+            let strand = if target.ref_strand == 0 { '+' } else { '-' };
+            target.context = reference.sequence(&target.ref_chr, start, start + 2 * k + 1, strand)
+                .map(|sequence| String::from_utf8_lossy(&sequence).into_owned());
+        }
+    }
+
+    /// Write the strand-oriented reference sequence of each extended target region into FASTA
+    fn write_region_fasta<P: AsRef<Path>>(&self, occs: &[(usize, MergedOcc)], fasta_path: P, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        use std::io::Write;
+        let reference = self.reference.as_ref().ok_or("A reference is required to write region sequences")?;
+        let regions = occs.iter().map(|(i, occ)| {
+            // 0-based half-open interval of the extended region
+            let (left, right) = self.flanks(occ.strand);
+            let start = occ.start - left;
+            let end = occ.start + self.occ_width + right;
+            let sequence = reference.sequence(&occ.refName, start, end, occ.strand)
+                .ok_or_else(|| format!("Chromosome {} is not found in the reference", occ.refName))?;
+            let header = format!("src={} {}:{}-{}({})", i + 1, occ.refName, start + 1, end, occ.strand);
+            Ok((header, sequence))
+        }).collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        write_atomically(&fasta_path, |output| {
+            for (header, sequence) in regions.iter() {
+                writeln!(output, ">{}", header)?;
+                output.write_all(sequence)?;
+                writeln!(output)?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(fasta_path, "region_fasta", regions.len() as u64);
+        Ok(())
+    }
+
+    /// Check chromosomes of occurrences against `kinetics_chroms` of the kinetics inputs,
+    /// and drop occurrences on the missing ones in the skip policy
+    fn resolve_missing_chroms(&self, occs: Vec<(usize, MergedOcc)>, kinetics_chroms: &HashSet<&str>) -> Result<Vec<(usize, MergedOcc)>, Box<dyn Error>> {
+        let missing = occs.iter().map(|(_, occ)| occ.refName.as_str()).filter(|chr| !kinetics_chroms.contains(chr))
+            .map(|chr| chr.to_string()).collect::<BTreeSet<_>>();
+        if missing.is_empty() {
+            return Ok(occs);
+        }
+        let list = |names: Vec<&str>| if names.len() > 20 { format!("{}, ... ({} in total)", names[..20].join(", "), names.len()) } else { names.join(", ") };
+        let missing_list = list(missing.iter().map(|chr| chr.as_str()).collect());
+        match self.missing_chrom {
+            MissingChrom::Error => {
+                let mut available = kinetics_chroms.iter().copied().collect::<Vec<_>>();
+                available.sort_unstable();
+                Err(format!("Chromosomes of occurrences are not in the kinetics input: {}; its chromosomes are: {} \
+                    (see --chrom-alias, --chr-prefix, and --missing-chrom-policy)", missing_list, list(available)).into())
+            },
+            MissingChrom::Skip => {
+                let n_occ = occs.len();
+                let occs = occs.into_iter().filter(|(_, occ)| !missing.contains(&occ.refName)).collect::<Vec<_>>();
+                eprintln!("[WARNING] Skipped {} occurrences on chromosomes without kinetics data: {}", n_occ - occs.len(), missing_list);
+                Ok(occs)
+            },
+            MissingChrom::Zero => {
+                eprintln!("[WARNING] Rows of occurrences on chromosomes without kinetics data have no data: {}", missing_list);
+                Ok(occs)
+            },
+        }
+    }
+
+    /// Check the size of the output, and write or print requested information on occurrences before collecting kinetics
+    fn prepare_occs(&self, occs: &[(usize, MergedOcc)], region: &RegionSpec, output_options: &OutputOptions, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        self.check_size(occs.len(), region)?;
+        // rows of a minus-strand occurrence are reversed, so their upstream rows would be the extension to the right
+        if !self.strand_relative_extension && self.occ_extension != self.occ_extension_down && occs.iter().any(|(_, occ)| occ.strand == '-') {
+            return Err("Asymmetric extensions of minus-strand occurrences require --strand-relative-extension".into());
+        }
+        if let Some(n) = self.audit_strand {
+            self.print_strand_audit(occs, n, region)?;
+        }
+        if let Some(fasta_output) = output_options.fasta_output.as_ref() {
+            self.write_region_fasta(occs, fasta_output, manifest)?;
+        }
+        Ok(())
+    }
+
+    /// Stop if the output would be suspiciously large, e.g. because --occ-width and --extend are swapped,
+    /// unless `confirmed`, in which case only warn
+    fn check_size(&self, n_occ: usize, region: &RegionSpec) -> Result<(), Box<dyn Error>> {
+        let mut problems = Vec::new();
+        let n_rows = region.n_rows() as u128 * n_occ as u128;
+        if n_rows > self.max_rows as u128 {
+            problems.push(format!("the long layout would have {} rows, more than --max-rows {}", n_rows, self.max_rows));
+        }
+        let max_length = self.chrom_sizes.as_ref().and_then(|sizes| sizes.max_length())
+            .or_else(|| self.reference.as_ref().and_then(|reference| reference.max_length()));
+        let extension = self.occ_extension.max(self.occ_extension_down);
+        if let Some(max_length) = max_length.filter(|length| extension > *length) {
+            problems.push(format!("--extend {} is longer than any chromosome (at most {} bp)", extension, max_length));
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let message = format!("Suspicious --occ-width {} and --extend {}: {}", self.occ_width, extension, problems.join("; "));
+        if self.confirmed {
+            eprintln!("[WARNING] {}", message);
+            Ok(())
+        } else {
+            Err(format!("{} (use --yes to continue anyway)", message).into())
+        }
+    }
+
+    /// Print keys, labels, and reference bases of rows for the first `n` minus-strand occurrences to stderr,
+    /// to check the orientation of target regions before a large run
+    fn print_strand_audit(&self, occs: &[(usize, MergedOcc)], n: usize, region: &RegionSpec) -> Result<(), OccError> {
+        if self.reference.is_none() {
+            eprintln!("[WARNING] Reference bases are not shown in the strand audit without --reference");
+        }
+        let minus_occs = occs.iter().filter(|(_, occ)| occ.strand == '-').take(n).collect::<Vec<_>>();
+        if minus_occs.is_empty() {
+            eprintln!("[WARNING] No minus-strand occurrence to audit");
+        }
+        for (i, occ) in minus_occs {
+            eprintln!("[INFO] Strand audit: src {} ({}:{} {}, 0-based start)", i + 1, occ.refName, occ.start, occ.strand);
+            eprintln!("[INFO] position\tstrand\tlabel\tref_chr\tref_position\tref_strand\tref_base");
+            let target_key = IpdSummaryKey::try_from(occ.clone())?;
+            for (j, key) in self.target_keys(&target_key)?.enumerate() {
+                let position = ((j / 2) + 1) as i64;
+                let strand = if j % 2 == 0 { '+' } else { '-' };
+                let label = region.label(position, strand)?;
+                // base on the strand of the key
+                let ref_base = self.reference.as_ref()
+                    .and_then(|r| r.sequence(&key.refName, key.tpl - 1, key.tpl, if key.strand == 0 { '+' } else { '-' }))
+                    .map_or_else(|| "NA".to_string(), |b| String::from_utf8_lossy(&b).into_owned());
+                eprintln!("[INFO] {}\t{}\t{}\t{}\t{}\t{}\t{}", position, strand, label, key.refName, key.tpl, key.strand, ref_base);
+            }
+        }
+        Ok(())
+    }
+
+    /// Report skipped records, and write them if requested
+    fn finish_rejects(&self, rejects: &Rejects, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        rejects.report();
+        if let Some(rejects_path) = self.rejects_path.as_ref() {
+            rejects.write(rejects_path, manifest)?;
+        }
+        Ok(())
     }
-    result_writer.flush()?;
-    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct RegionOverflow {
-    message: String,
+#[derive(Debug, Clone, Serialize)]
+struct TargetIpd {
+    position: i64,
+    strand: char,
+    value: f32,
+    label: String,
+    src: i64,
 }
-impl std::fmt::Display for RegionOverflow {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "RegionOverflow: {}", self.message)
+
+impl TargetIpd {
+    fn create_label(position: i64, region_width: i64, region_extension: i64, region_downstream: i64, strand: char) -> Result<String, OccError> {
+        let length = region_extension + region_width + region_downstream;
+        let out_of_region = OccError::OutOfRegion { position, strand, length };
+        let (part, relative_position) = match position {
+            p if p <= 0 => return Err(out_of_region),
+            // start-side / upstream of the target region
+            p if p <= region_extension => ('s', p),
+            // motif / target region
+            p if p <= region_extension + region_width => ('m', p - region_extension),
+            // end-side / downstream of the target region
+            p if p <= length => ('e', p - region_extension - region_width),
+            _ => return Err(out_of_region),
+        };
+        let label_strand = match strand {
+            '+' => 'p',
+            '-' => 'm',
+            _ => return Err(out_of_region),
+        };
+        Ok(format!("{}{}{}", part, relative_position, label_strand))
+    }
+
+    #[allow(dead_code)]
+    fn new(position: i64, strand: char, value: f32, src: i64, region_width: i64, region_extension: i64) -> Result<Self, OccError> {
+        Ok(Self {
+            position,
+            strand,
+            value,
+            label: Self::create_label(position, region_width, region_extension, region_extension, strand)?,
+            src,
+        })
     }
 }
-impl Error for RegionOverflow {}
-impl std::default::Default for RegionOverflow {
-    fn default() -> Self {
-        RegionOverflow {
-            message: "Total region length exceeds u64".to_string(),
+
+/// Style of position labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum LabelStyle {
+    /// Region part and position in the part, e.g. s3p, m1p, e2m
+    Part,
+    /// Signed offset from the target, e.g. -3p, 1p, +2m
+    Signed,
+}
+
+/// Point of an occurrence in its orientation on which the extended region is anchored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum Anchor {
+    /// The first base (5' end on the strand of the occurrence)
+    Start,
+    /// The middle base, or the 5'-side one of the two middle bases of an even width
+    Center,
+    /// The last base
+    End,
+}
+
+impl Anchor {
+    /// The anchor base of an occurrence of `width` as an occurrence of width 1
+    fn anchor(&self, occ: MergedOcc, width: i64) -> Result<MergedOcc, OccError> {
+        let offset = match self {
+            Anchor::Start => 0,
+            Anchor::Center => (width - 1) / 2,
+            Anchor::End => width - 1,
+        };
+        let start = if occ.strand == '-' { occ.start.checked_add(width - 1 - offset) } else { occ.start.checked_add(offset) };
+        match start {
+            Some(start) => Ok(MergedOcc { start, ..occ }),
+            None => Err(OccError::Overflow { chr: occ.refName, start: occ.start, extension: width - 1 }),
         }
     }
 }
 
-/// Collect kinetics info at specified regions
-#[derive(Debug, Parser)]
-#[clap(about, version, author)]
-// Make csv input and HDF5 input mutually exclusive
-#[clap(group(
-        ArgGroup::new("inputs").required(true).args(&["kinetics", "kinetics-hdf5"]),
-        ))]
-struct Args {
-    /// Kinetics CSV file generated by PacBio `ipdSummary`
-    #[clap(long, short)]
-    kinetics: Option<String>,
+/// Shape of an extended target region and how its positions are labeled
+#[derive(Debug, Clone)]
+struct RegionSpec {
+    width: i64,
+    /// Length of the upstream extension, i.e. before the target in the order of rows, which follows the strand of each occurrence
+    extension: i64,
+    /// Length of the downstream extension
+    downstream: i64,
+    label_style: LabelStyle,
+    /// Lengths of consecutive sub-parts of the target, summing up to `width`; empty for an undivided target
+    parts: Vec<i64>,
+    /// Only rows of the strand of the occurrence (the relative plus strand) at each position
+    target_strand_only: bool,
+    /// Rows of both strands combined into the relative plus-strand row at each position
+    combined_strands: bool,
+}
 
-    /// Kinetics HDF5 (.h5) file generated by PacBio `ipdSummary`
-    #[clap(long, short = 'H')]
-    kinetics_hdf5: Option<String>,
+impl RegionSpec {
+    fn new(width: i64, extension: i64) -> Self {
+        Self { width, extension, downstream: extension, label_style: LabelStyle::Part, parts: Vec::new(), target_strand_only: false, combined_strands: false }
+    }
 
-    /// File listing positions of motif occurrences or target bases.
-    /// Each row has chromosome name, 0-based start position, and strand with delimiter of single
-    /// space, without header line.
-    #[clap(long)]
-    occ: String,
+    /// Relative strands of rows at each position, in the order of rows
+    fn strands(&self) -> &'static [char] {
+        if self.target_strand_only || self.combined_strands { &['+'] } else { &['+', '-'] }
+    }
 
-    /// Length of the motif or target region including the start position
-    #[clap(long)]
-    occ_width: i64,
+    /// Whether the `j`-th key of `IpdSummaryKey::target_keys` (alternating relative strands) has a row
+    fn has_row(&self, j: usize) -> bool {
+        !self.target_strand_only || j % 2 == 0
+    }
 
-    /// Length of an extended region for each end of a target region
-    #[clap(long)]
-    extend: i64,
+    /// Number of rows of a target region
+    fn n_rows(&self) -> i64 {
+        self.length() * self.strands().len() as i64
+    }
 
-    /// Output CSV path
-    #[clap(long, short)]
-    output: String,
+    /// Number of positions in the extended region
+    fn length(&self) -> i64 {
+        self.extension + self.width + self.downstream
+    }
+
+    /// Check that `parts` are positive and sum up to `width`
+    fn check_parts(&self) -> Result<(), String> {
+        if self.parts.is_empty() {
+            return Ok(());
+        }
+        if self.parts.iter().any(|&part| part <= 0) || self.parts.iter().sum::<i64>() != self.width {
+            return Err(format!("Region parts ({:?}) must be positive and sum up to the target width ({})", self.parts, self.width));
+        }
+        Ok(())
+    }
+
+    /// 1-based index of the sub-part containing a 1-based `position` in the target and the 1-based position in the part,
+    /// or None for an undivided target
+    fn target_part(&self, position: i64) -> Option<(usize, i64)> {
+        let mut start = 0;
+        for (i, part) in self.parts.iter().enumerate() {
+            if position <= start + part {
+                return Some((i + 1, position - start));
+            }
+            start += part;
+        }
+        None
+    }
+
+    /// Region of a 1-based `position` in the extended region: Upstream, Target (or Target1, Target2, ... with parts), or Downstream
+    /// None if `position` is outside the extended region
+    fn region(&self, position: i64) -> Option<String> {
+        let region = TargetIpdRich::create_region(position, self.width, self.extension, self.downstream)?;
+        Some(match self.target_part(position - self.extension) {
+            Some((part, _)) if region == "Target" => format!("{}{}", region, part),
+            _ => region,
+        })
+    }
+
+    /// All regions in the order of positions
+    fn regions(&self) -> Vec<String> {
+        let mut regions = vec!["Upstream".to_string()];
+        if self.parts.is_empty() {
+            regions.push("Target".to_string());
+        } else {
+            regions.extend((1..=self.parts.len()).map(|part| format!("Target{}", part)));
+        }
+        regions.push("Downstream".to_string());
+        regions
+    }
+
+    /// Error of a `position` and a relative `strand` outside the extended region
+    fn out_of_region(&self, position: i64, strand: char) -> OccError {
+        OccError::OutOfRegion { position, strand, length: self.length() }
+    }
+
+    fn strand_letter(&self, position: i64, strand: char) -> Result<char, OccError> {
+        match strand {
+            '+' => Ok('p'),
+            '-' => Ok('m'),
+            _ => Err(self.out_of_region(position, strand)),
+        }
+    }
+
+    /// Label of a 1-based `position` and a relative `strand` in the extended region
+    fn label(&self, position: i64, strand: char) -> Result<String, OccError> {
+        let in_target = position > self.extension && position <= self.extension + self.width;
+        match self.label_style {
+            // m<part>_<position in the part> in a divided target
+            LabelStyle::Part if in_target && !self.parts.is_empty() => {
+                let (part, part_position) = self.target_part(position - self.extension).ok_or_else(|| self.out_of_region(position, strand))?;
+                Ok(format!("m{}_{}{}", part, part_position, self.strand_letter(position, strand)?))
+            },
+            LabelStyle::Part => TargetIpd::create_label(position, self.width, self.extension, self.downstream, strand),
+            LabelStyle::Signed => {
+                let offset = match position {
+                    p if p <= 0 => return Err(self.out_of_region(position, strand)),
+                    p if p <= self.extension => format!("-{}", self.extension - p + 1),
+                    p if p <= self.extension + self.width => (p - self.extension).to_string(),
+                    p if p <= self.length() => format!("+{}", p - self.extension - self.width),
+                    _ => return Err(self.out_of_region(position, strand)),
+                };
+                Ok(format!("{}{}", offset, self.strand_letter(position, strand)?))
+            },
+        }
+    }
+
+    /// Labels of positions and strands in the order of rows in a target region
+    fn labels(&self) -> Vec<String> {
+        (1..=self.length()).flat_map(|position| {
+            self.strands().iter().map(move |&strand| self.label(position, strand).expect("rows are in the extended region"))
+        }).collect()
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let occ_path = args.occ;
-    let occ_width = args.occ_width;
-    let region_extension = args.extend;
-    let output_path = args.output;
-    // check if (region_extension * 2 + occ_width) overflows
-    region_extension.checked_mul(2).ok_or(RegionOverflow::default())?.checked_add(occ_width).ok_or(RegionOverflow::default())?;
-    if let Some(kinetics) = args.kinetics {
-        collect_ipd_summary_in_merged_occ(kinetics, occ_path, occ_width, region_extension, output_path)?;
-    } else if let Some(kinetics_hdf5) = args.kinetics_hdf5 {
-        collect_hdf5_ipd_summary_in_merged_occ(kinetics_hdf5, occ_path, occ_width, region_extension, output_path)?;
-    } else {
-        unreachable!();
+#[derive(Debug, Clone, Serialize)]
+#[allow(non_snake_case)]
+struct TargetIpdRich {
+    /// Relative position in a target region
+    position: i64,
+    /// Relative strand in a target region
+    strand: char,
+    /// A kinetics field chosen by `ValueField`, tMean (IPD) by default
+    value: f32,
+    label: String,
+    /// Index of the source in targets
+    src: i64,
+    base: Option<char>,
+    score: u32,
+    tErr: f32,
+    modelPrediction: f32,
+    ipdRatio: f32,
+    coverage: u32,
+    /// Chromosome of this base in the source data
+    ref_chr: String,
+    /// Position (1-based) of this base in the source data
+    ref_position: i64,
+    ref_strand: u8,
+    region: String,
+    frac: Option<f32>,
+    fracLow: Option<f32>,
+    fracUp: Option<f32>,
+    pwMean: Option<f32>,
+    pwErr: Option<f32>,
+    /// Signed offset from the first base of the target region in its orientation:
+    /// negative upstream, 0 to width - 1 within the target, and width or more downstream
+    distance: i64,
+    /// Reference sequence around this base on `ref_strand`
+    context: Option<String>,
+    /// Identifier of the occurrence from its position, written in hexadecimal
+    occ_id: u64,
+    /// GC fraction of the target region and of the extended window
+    gc_target: Option<f32>,
+    gc_window: Option<f32>,
+    /// tMean over tMean of control kinetics at the same position and strand
+    ipdRatio_control: Option<f32>,
+    /// z-score of the value against values at random positions
+    zscore_background: Option<f32>,
+    /// True if the strand of the occurrence is assumed for an occurrence of an unknown strand
+    strand_assumed: bool,
+    /// Number of other occurrences whose extended target regions overlap that of this one
+    overlap_count: u32,
+    /// True if this base is beyond the ends of its chromosome, written as NA by --edge-policy pad-na or --chrom-end-policy clip
+    off_contig: bool,
+    /// True if the kinetics source has no data at this base
+    #[serde(rename = "is_missing")]
+    missing: bool,
+    /// Index of the kinetics input of this row
+    #[serde(skip)]
+    sample: usize,
+    /// Values of annotation tracks, written after the other columns
+    #[serde(skip)]
+    annotations: Vec<Option<f32>>,
+}
+
+/// Column of the long layout, in the default order.
+/// This is the single source of the header, the record fields, and the columnar schema;
+/// names of stored (not computed) columns must match the serialized fields of `TargetIpdRich`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum Column {
+    Position,
+    Strand,
+    Value,
+    Label,
+    Src,
+    Base,
+    Score,
+    #[clap(name = "tErr")]
+    TErr,
+    #[clap(name = "modelPrediction")]
+    ModelPrediction,
+    #[clap(name = "ipdRatio")]
+    IpdRatio,
+    Coverage,
+    #[clap(name = "ref_chr")]
+    RefChr,
+    #[clap(name = "ref_position")]
+    RefPosition,
+    #[clap(name = "ref_strand")]
+    RefStrand,
+    Region,
+    /// Estimated fraction of modified molecules, only in the output of --frac-columns
+    Frac,
+    #[clap(name = "fracLow")]
+    FracLow,
+    #[clap(name = "fracUp")]
+    FracUp,
+    /// log2(tMean / modelPrediction), only in the output of --log2-obs-over-model
+    #[clap(name = "log2_obs_over_model")]
+    Log2ObsOverModel,
+    /// Mean pulse width, only in the output of --pw-columns
+    #[clap(name = "pwMean")]
+    PwMean,
+    #[clap(name = "pwErr")]
+    PwErr,
+    /// Signed offset from the first base of the target region, only in the output of --distance-column
+    Distance,
+    /// Reference sequence context, only in the output of --context
+    Context,
+    /// Stable identifier of the occurrence, only in the output of --occ-id
+    #[clap(name = "occ_id")]
+    OccId,
+    /// GC fraction of the target region, only in the output of --gc-content
+    #[clap(name = "gc_target")]
+    GcTarget,
+    /// GC fraction of the extended window, only in the output of --gc-window
+    #[clap(name = "gc_window")]
+    GcWindow,
+    /// tMean over control tMean, only in the output of --control-kinetics
+    #[clap(name = "ipdRatio_control")]
+    IpdRatioControl,
+    /// z-score against random positions, only in the output of --zscore-background
+    #[clap(name = "zscore_background")]
+    ZscoreBackground,
+    /// Whether the strand of the occurrence is assumed, only in the output of --unknown-strand-policy both
+    #[clap(name = "strand_assumed")]
+    StrandAssumed,
+    /// Number of other occurrences overlapping the extended target region, only in the output of --overlapping-occ count
+    #[clap(name = "overlap_count")]
+    OverlapCount,
+    /// Whether the base is beyond the ends of its chromosome, only in the output of --edge-policy pad-na or --chrom-end-policy clip
+    #[clap(name = "off_contig")]
+    OffContig,
+    /// Whether the base has no kinetics data, only in the output of --missing-column
+    #[clap(name = "is_missing")]
+    IsMissing,
+}
+
+impl Column {
+    /// Column name in the header, which is the same as its name in --column-order
+    fn name(&self) -> &'static str {
+        self.to_possible_value().expect("no hidden column").get_name()
+    }
+
+    const FRAC: [Column; 3] = [Column::Frac, Column::FracLow, Column::FracUp];
+    const PW: [Column; 2] = [Column::PwMean, Column::PwErr];
+
+    /// Columns written only on request
+    fn is_optional(&self) -> bool {
+        Column::FRAC.contains(self) || Column::PW.contains(self) || matches!(self, Column::Distance | Column::Context | Column::OccId | Column::GcTarget | Column::GcWindow | Column::IpdRatioControl
+            | Column::ZscoreBackground | Column::StrandAssumed | Column::OverlapCount | Column::OffContig | Column::IsMissing)
+            || self.is_computed()
+    }
+
+    /// Columns computed from other fields rather than stored in `TargetIpdRich`
+    fn is_computed(&self) -> bool {
+        matches!(self, Column::Log2ObsOverModel)
+    }
+
+    /// Columns written by default, plus the requested `optional` columns
+    fn defaults(optional: &[Column]) -> Vec<Column> {
+        Column::value_variants().iter().filter(|c| !c.is_optional() || optional.contains(c)).copied().collect()
+    }
+
+    /// Columns listed in `order` first, followed by the other columns of `defaults(optional)` in the default order
+    fn reorder(order: &[Column], optional: &[Column]) -> Result<Vec<Column>, String> {
+        let mut columns = Vec::with_capacity(Column::value_variants().len());
+        for column in order {
+            if columns.contains(column) {
+                return Err(format!("Column {} is listed more than once in --column-order", column.name()));
+            }
+            columns.push(*column);
+        }
+        columns.extend(Column::defaults(optional).into_iter().filter(|c| !order.contains(c)));
+        Ok(columns)
+    }
+}
+
+impl TargetIpdRich {
+
+    /// None if `position` is outside the extended region
+    fn create_region(position: i64, region_width: i64, region_extension: i64, region_downstream: i64) -> Option<String> {
+        let region = match position {
+            p if p <= 0 => return None,
+            // start-side / upstream of the target region
+            p if p <= region_extension => "Upstream",
+            // motif / target region
+            p if p <= region_extension + region_width => "Target",
+            // end-side / downstream of the target region
+            p if p <= region_extension + region_width + region_downstream => "Downstream",
+            _ => return None,
+        };
+        Some(region.to_string())
+    }
+
+    /// `values` of None means no kinetics data at `key`, filled with default values
+    fn new(position: i64, strand: char, src: i64, region: &RegionSpec, key: IpdSummaryKey, values: Option<&IpdSummaryValue>) -> Result<Self, OccError> {
+        let default_values = IpdSummaryValue::default();
+        let missing = values.is_none();
+        let values = values.unwrap_or(&default_values);
+        Ok(Self {
+            position,
+            strand,
+            value: values.tMean,
+            label: region.label(position, strand)?,
+            src,
+            base: values.base,
+            score: values.score,
+            tErr: values.tErr,
+            modelPrediction: values.modelPrediction,
+            ipdRatio: values.ipdRatio,
+            coverage: values.coverage,
+            ref_chr: key.refName,
+            ref_position: key.tpl,
+            ref_strand: key.strand,
+            region: region.region(position).ok_or_else(|| region.out_of_region(position, strand))?,
+            frac: values.frac,
+            fracLow: values.fracLow,
+            fracUp: values.fracUp,
+            pwMean: values.pwMean,
+            pwErr: values.pwErr,
+            distance: position - region.extension - 1,
+            context: None,
+            occ_id: 0,
+            gc_target: None,
+            gc_window: None,
+            ipdRatio_control: None,
+            zscore_background: None,
+            strand_assumed: false,
+            overlap_count: 0,
+            missing,
+            off_contig: false,
+            sample: 0,
+            annotations: Vec::new(),
+        })
+    }
+
+    fn format_occ_id(&self) -> String {
+        format!("{:016x}", self.occ_id)
+    }
+
+    /// log2(tMean / modelPrediction); None if either value is not positive or the base has no data
+    fn log2_obs_over_model(&self) -> Option<f32> {
+        if self.missing || self.value <= 0.0 || self.modelPrediction <= 0.0 {
+            None
+        } else {
+            Some((self.value / self.modelPrediction).log2())
+        }
+    }
+
+    /// A kinetics field as a number; None for absent frac
+    fn value_of(&self, field: ValueField) -> Option<f32> {
+        match field {
+            ValueField::TMean => Some(self.value),
+            ValueField::IpdRatio => Some(self.ipdRatio),
+            ValueField::Score => Some(self.score as f32),
+            ValueField::Coverage => Some(self.coverage as f32),
+            ValueField::Frac => self.frac,
+        }
+    }
+
+    /// A kinetics field formatted as its own column would be
+    fn format_value(&self, field: ValueField, value_format: &ValueFormat) -> String {
+        self.format_field(field.column(), value_format)
+    }
+
+    /// Whether `format_field` writes a kinetics field of `column` as NA, where columnar outputs write null:
+    /// at bases beyond the ends of the chromosome, and at bases without kinetics data if an NA string is specified
+    fn is_na(&self, column: Column, value_format: &ValueFormat) -> bool {
+        match column {
+            Column::Value | Column::Score | Column::TErr | Column::ModelPrediction | Column::IpdRatio => {
+                self.off_contig || (self.missing && value_format.na_string.is_some())
+            },
+            Column::Coverage => self.off_contig,
+            _ => false,
+        }
+    }
+
+    fn format_field(&self, column: Column, value_format: &ValueFormat) -> String {
+        let kinetics = |field: String| if self.off_contig { value_format.explicit_na() } else { value_format.missing_or(self.missing, field) };
+        match column {
+            Column::Position => self.position.to_string(),
+            Column::Strand => self.strand.to_string(),
+            // tMean; other fields of `value` are handled by `format_value`
+            Column::Value => kinetics(value_format.float(self.value)),
+            Column::Label => self.label.clone(),
+            Column::Src => self.src.to_string(),
+            Column::Base => self.base.map_or_else(|| value_format.na(), |b| b.to_string()),
+            Column::Score => kinetics(self.score.to_string()),
+            Column::TErr => kinetics(value_format.float(self.tErr)),
+            Column::ModelPrediction => kinetics(value_format.float(self.modelPrediction)),
+            Column::IpdRatio => kinetics(value_format.float(self.ipdRatio)),
+            Column::Coverage => if self.off_contig { value_format.explicit_na() } else { self.coverage.to_string() },
+            Column::RefChr => self.ref_chr.clone(),
+            Column::RefPosition => self.ref_position.to_string(),
+            Column::RefStrand => self.ref_strand.to_string(),
+            Column::Region => self.region.clone(),
+            Column::Frac => self.frac.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::FracLow => self.fracLow.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::FracUp => self.fracUp.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::Log2ObsOverModel => self.log2_obs_over_model().map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::PwMean => self.pwMean.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::PwErr => self.pwErr.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::Distance => self.distance.to_string(),
+            Column::Context => self.context.clone().unwrap_or_else(|| value_format.na()),
+            Column::OccId => self.format_occ_id(),
+            Column::GcTarget => self.gc_target.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::GcWindow => self.gc_window.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::IpdRatioControl => self.ipdRatio_control.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::ZscoreBackground => self.zscore_background.map_or_else(|| value_format.na(), |v| value_format.float(v)),
+            Column::StrandAssumed => self.strand_assumed.to_string(),
+            Column::OverlapCount => self.overlap_count.to_string(),
+            Column::OffContig => self.off_contig.to_string(),
+            Column::IsMissing => self.missing.to_string(),
+        }
+    }
+
+    /// Format fields in the order of `columns`, where the value column is expanded into `value_fields`
+    fn to_record(&self, columns: &[Column], value_fields: &[ValueField], value_format: &ValueFormat) -> Vec<String> {
+        let mut record = Vec::with_capacity(columns.len() + value_fields.len());
+        for column in columns {
+            match column {
+                Column::Value => record.extend(value_fields.iter().map(|field| self.format_value(*field, value_format))),
+                _ => record.push(self.format_field(*column, value_format)),
+            }
+        }
+        record.extend(self.annotations.iter().map(|v| v.map_or_else(|| value_format.na(), |v| value_format.float(v))));
+        record
+    }
+}
+
+/// Kinetics field written into the `value` column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum ValueField {
+    #[clap(name = "tMean")]
+    TMean,
+    #[clap(name = "ipdRatio")]
+    IpdRatio,
+    Score,
+    Coverage,
+    Frac,
+}
+
+impl ValueField {
+    /// Column holding this field when it is not the value
+    fn column(&self) -> Column {
+        match self {
+            ValueField::TMean => Column::Value,
+            ValueField::IpdRatio => Column::IpdRatio,
+            ValueField::Score => Column::Score,
+            ValueField::Coverage => Column::Coverage,
+            ValueField::Frac => Column::Frac,
+        }
+    }
+
+    /// Names of columns holding `value_fields` in place of a column `base`:
+    /// `base` itself for a single field, or `<base>_<field>` for each of multiple fields
+    fn column_names(base: &str, value_fields: &[ValueField]) -> Vec<String> {
+        match value_fields {
+            [_] => vec![base.to_string()],
+            _ => value_fields.iter().map(|field| {
+                format!("{}_{}", base, field.to_possible_value().expect("no hidden field").get_name())
+            }).collect(),
+        }
+    }
+}
+
+/// Text formatting of values in CSV output
+#[derive(Debug, Clone, Default)]
+struct ValueFormat {
+    /// String for missing values; None keeps the former behavior,
+    /// i.e. empty fields for absent optional values and zeros for bases without kinetics data
+    na_string: Option<String>,
+    /// Number of digits after the decimal point; None means the shortest representation
+    float_precision: Option<usize>,
+}
+
+impl ValueFormat {
+    fn float(&self, value: f32) -> String {
+        match self.float_precision {
+            Some(precision) if value.is_finite() => format!("{:.*}", precision, value),
+            // same representation as serialization by csv
+            _ => ryu::Buffer::new().format(value).to_string(),
+        }
+    }
+
+    /// String for an absent optional value
+    fn na(&self) -> String {
+        self.na_string.clone().unwrap_or_default()
+    }
+
+    /// NA string even if not specified, for fields that must not look like values
+    fn explicit_na(&self) -> String {
+        self.na_string.clone().unwrap_or_else(|| "NA".to_string())
+    }
+
+    /// Replace a field with the NA string if the base is missing and an NA string is specified
+    fn missing_or(&self, missing: bool, field: String) -> String {
+        match &self.na_string {
+            Some(na_string) if missing => na_string.clone(),
+            _ => field,
+        }
+    }
+}
+
+/// Layout of the output table
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Layout {
+    /// One row per position and strand of each target region
+    Long,
+    /// One row per target region, with one column per labeled position and strand
+    Wide,
+}
+
+impl Layout {
+    /// Number of rows written for target regions
+    fn count_rows(&self, targets: &[(IpdSummaryKey, Vec<TargetIpdRich>)]) -> u64 {
+        match self {
+            Layout::Long => targets.iter().map(|(_, vals)| vals.len() as u64).sum(),
+            Layout::Wide => targets.len() as u64,
+        }
+    }
+
+    /// `columns` is the column order of the long layout, and `value_fields` are written for each value
+    fn header(&self, columns: &[Column], value_fields: &[ValueField], region: &RegionSpec) -> Vec<String> {
+        match self {
+            Layout::Long => columns.iter().flat_map(|c| ValueField::column_names(c.name(), match c {
+                Column::Value => value_fields,
+                _ => &[ValueField::TMean],
+            })).collect(),
+            Layout::Wide => {
+                let mut header = vec!["src", "ref_chr", "ref_position", "ref_strand"].into_iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                for label in region.labels() {
+                    header.extend(ValueField::column_names(&label, value_fields));
+                }
+                header
+            },
+        }
+    }
+}
+
+/// Options on how to write collected kinetics
+#[derive(Debug, Clone)]
+struct OutputOptions {
+    layout: Layout,
+    format: OutputFormat,
+    split_by: Option<SplitBy>,
+    /// Column order of the long layout
+    columns: Vec<Column>,
+    /// Kinetics fields written in place of the value column
+    value_fields: Vec<ValueField>,
+    value_format: ValueFormat,
+    /// Metadata written as comment lines in CSV or as schema metadata in Feather and Parquet
+    provenance: Option<Provenance>,
+    /// Append rows to an existing CSV output without writing its header again
+    append: bool,
+    /// Values of an additional `sample` column for each kinetics input; empty for no sample column
+    sample_names: Vec<String>,
+    /// Complement bases of rows on the relative minus strand, so that bases are on the relative strand
+    relative_base: bool,
+    /// Names of the kinetics inputs and the occ input written in `kinetics_source` and `occ_source` columns
+    source_names: Option<(Vec<String>, String)>,
+    compression: Compression,
+    /// None means the default level of the compression format
+    compression_level: Option<i32>,
+    /// Prefix of stranded bedGraph tracks of collected values
+    bedgraph_prefix: Option<String>,
+    track_value: TrackValue,
+    /// BED file of positions passing `call_thresholds`
+    calls_bed: Option<String>,
+    call_thresholds: CallThresholds,
+    /// CSV of statistics of `profile_value` aggregated over all occurrences per label and relative strand
+    profile_output: Option<String>,
+    profile_value: ValueField,
+    /// Probabilities of quantiles written into `profile_output`
+    profile_quantiles: Vec<f64>,
+    /// Bootstrap of occurrences for confidence intervals in `profile_output`
+    profile_bootstrap: Option<Bootstrap>,
+    profile_weighting: ProfileWeighting,
+    profile_robust: RobustOptions,
+    /// CSV of per-position tests of `profile_value` between the occurrences and control occurrences
+    comparison_output: Option<String>,
+    /// CSV of statistics of `profile_value` in the target region and its flanks of each occurrence
+    region_summary: Option<String>,
+    /// Self-contained HTML report of `profile_value` with run metadata
+    report_output: Option<String>,
+    report_metadata: Provenance,
+    /// MultiQC custom content (`*_mqc.json` or `*_mqc.tsv`) summarizing `profile_value` and coverage of each sample
+    multiqc_output: Option<String>,
+    /// CSV of kinetics at random positions
+    background_table: Option<BackgroundTable>,
+    /// FASTA of the reference sequence of each extended target region
+    fasta_output: Option<String>,
+    /// Names of annotation columns written after the other columns of the long layout
+    annotation_names: Vec<String>,
+    label_style: LabelStyle,
+    /// Lengths of sub-parts of target regions
+    region_parts: Vec<i64>,
+    /// Length of the downstream extension of target regions if it differs from the upstream one
+    extend_down: Option<i64>,
+    /// Write only rows of the strand of each occurrence
+    target_strand_only: bool,
+    /// Combine rows of both strands at each position
+    combine_strands: Option<CombineStrands>,
+    /// Fold mirrored positions of both strands together in `profile_output`
+    fold_palindrome: bool,
+    /// Number of consecutive positions averaged into a row of the long layout
+    bin_size: Option<i64>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            layout: Layout::Long,
+            format: OutputFormat::Csv,
+            split_by: None,
+            columns: Column::defaults(&[]),
+            value_fields: vec![ValueField::TMean],
+            value_format: ValueFormat::default(),
+            provenance: None,
+            append: false,
+            sample_names: Vec::new(),
+            relative_base: false,
+            source_names: None,
+            compression: Compression::None,
+            compression_level: None,
+            bedgraph_prefix: None,
+            track_value: TrackValue::TMean,
+            calls_bed: None,
+            call_thresholds: CallThresholds { min_score: 20, min_ipd_ratio: 0.0 },
+            profile_output: None,
+            profile_value: ValueField::TMean,
+            profile_quantiles: vec![0.05, 0.25, 0.75, 0.95],
+            profile_bootstrap: None,
+            profile_weighting: ProfileWeighting::None,
+            profile_robust: RobustOptions::default(),
+            comparison_output: None,
+            region_summary: None,
+            background_table: None,
+            report_output: None,
+            report_metadata: Provenance::default(),
+            multiqc_output: None,
+            fasta_output: None,
+            annotation_names: Vec::new(),
+            label_style: LabelStyle::Part,
+            region_parts: Vec::new(),
+            extend_down: None,
+            target_strand_only: false,
+            combine_strands: None,
+            fold_palindrome: false,
+            bin_size: None,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Target regions of `occ_width` extended by `occ_extension` (and `extend_down` downstream if given), labeled in the requested style
+    fn region_spec(&self, occ_width: i64, occ_extension: i64) -> RegionSpec {
+        RegionSpec {
+            downstream: self.extend_down.unwrap_or(occ_extension),
+            label_style: self.label_style,
+            parts: self.region_parts.clone(),
+            target_strand_only: self.target_strand_only,
+            combined_strands: self.combine_strands.is_some(),
+            ..RegionSpec::new(occ_width, occ_extension)
+        }
+    }
+
+    /// Columns with the same value in every row of a kinetics input, written first: sample, kinetics_source, and occ_source.
+    /// Each column has its values indexed by `TargetIpdRich::sample`.
+    fn constant_columns(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut columns = Vec::new();
+        if !self.sample_names.is_empty() {
+            columns.push(("sample", self.sample_names.clone()));
+        }
+        if let Some((kinetics_sources, occ_source)) = self.source_names.as_ref() {
+            columns.push(("kinetics_source", kinetics_sources.clone()));
+            columns.push(("occ_source", vec![occ_source.clone(); kinetics_sources.len()]));
+        }
+        columns
+    }
+
+    /// Header of the main output table
+    fn header(&self, occ_width: i64, occ_extension: i64) -> Vec<String> {
+        let mut header = self.constant_columns().into_iter().map(|(name, _)| name.to_string()).collect::<Vec<_>>();
+        header.extend(self.layout.header(&self.columns, &self.value_fields, &self.region_spec(occ_width, occ_extension)));
+        header.extend(self.annotation_names.iter().cloned());
+        header
+    }
+
+    /// Create a CSV writer; `append_to_existing` appends to an existing output instead of truncating it
+    fn create_writer<P: AsRef<Path>>(&self, output_path: P, append_to_existing: bool) -> Result<csv::Writer<OutputWriter>, Box<dyn Error>> {
+        let mut output = OutputWriter::create(output_path, self.compression, self.compression_level, append_to_existing)?;
+        if let (Some(provenance), false) = (self.provenance.as_ref(), append_to_existing) {
+            use std::io::Write;
+            output.write_all(provenance.comment_lines().as_bytes())?;
+        }
+        // headers are written explicitly according to the layout
+        Ok(csv::WriterBuilder::new().has_headers(false).from_writer(output))
+    }
+
+    fn finish_writer(result_writer: csv::Writer<OutputWriter>) -> Result<(), Box<dyn Error>> {
+        result_writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+        Ok(())
+    }
+}
+
+/// Check that the header of an existing uncompressed CSV output, after provenance comment lines, is `header`
+fn check_existing_header<P: AsRef<Path>>(output_path: P, header: &[String]) -> Result<(), Box<dyn Error>> {
+    use std::io::BufRead;
+    let output_path = output_path.as_ref();
+    let reader = std::io::BufReader::new(std::fs::File::open(output_path)?);
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim_end() == header.join(",") {
+            return Ok(());
+        }
+        return Err(format!("Cannot append to {}: its header differs from the current output columns: {}", output_path.display(), line).into());
+    }
+    Ok(())
+}
+
+/// Write outputs without rows for the case of no target regions.
+/// They go through the same sinks as non-empty outputs, so that the format, layout and columns
+/// are the same as requested, e.g. only a header line in CSV and only a schema in Feather and Parquet.
+fn write_empty_target_kinetics<P: AsRef<Path>>(occ_width: i64, occ_extension: i64, output_options: &OutputOptions, output_path: P, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+    write_target_kinetics(std::iter::empty(), occ_width, occ_extension, output_options, output_path, manifest)?;
+    Ok(())
+}
+
+/// Writer of a main output table in the chosen format and layout, passing target regions to an `OutputSink` in batches.
+/// The output table is written into a temporary file and renamed to the output path when finished,
+/// so that an interrupted run does not leave a truncated output; the temporary file is removed if writing fails.
+/// A tabix index is renamed likewise, after the table, so that it never exists without its table.
+struct TableWriter {
+    sink: Box<dyn OutputSink>,
+    layout: Layout,
+    batch: Vec<TargetKinetics>,
+    batch_rows: u64,
+    n_rows: u64,
+    /// (temporary file, output_path); None when appending to an existing output in place
+    rename: Option<(TemporaryFile, PathBuf)>,
+    /// (temporary file, index_path) of a tabix index
+    index_rename: Option<(TemporaryFile, PathBuf)>,
+}
+
+impl TableWriter {
+    fn create<P: AsRef<Path>>(occ_width: i64, occ_extension: i64, output_options: &OutputOptions, output_path: P) -> Result<Self, Box<dyn Error>> {
+        let output_path = output_path.as_ref();
+        let append_to_existing = output_options.format == OutputFormat::Csv && output_options.append
+            && std::fs::metadata(output_path).is_ok_and(|m| m.len() > 0);
+        let rename = if append_to_existing { None } else { Some((TemporaryFile::new(output_path), output_path.to_path_buf())) };
+        let write_path = rename.as_ref().map_or(output_path, |(temporary, _)| temporary.path());
+        let mut index_rename = None;
+        let sink: Box<dyn OutputSink> = match output_options.format {
+            OutputFormat::Csv => Box::new(CsvSink::create(write_path, occ_width, occ_extension, output_options, append_to_existing)?),
+            OutputFormat::Feather | OutputFormat::Parquet => Box::new(ColumnarSink::create(write_path, occ_width, occ_extension, output_options)?),
+            OutputFormat::Tabix => {
+                let index_path = tabix::index_path(output_path);
+                let index = TemporaryFile::new(&index_path);
+                let sink = TabixSink::create(write_path, index.path(), output_options)?;
+                index_rename = Some((index, index_path));
+                Box::new(sink)
+            },
+        };
+        Ok(TableWriter {
+            sink,
+            layout: output_options.layout,
+            batch: Vec::new(),
+            batch_rows: 0,
+            n_rows: 0,
+            rename,
+            index_rename,
+        })
+    }
+
+    fn write_target(&mut self, target_key: IpdSummaryKey, target_vals: Vec<TargetIpdRich>) -> Result<(), Box<dyn Error>> {
+        self.batch_rows += match self.layout {
+            Layout::Long => target_vals.len() as u64,
+            Layout::Wide => 1,
+        };
+        self.batch.push((target_key, target_vals));
+        if self.batch_rows >= BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.batch.is_empty() {
+            self.n_rows += self.sink.write_batch(std::mem::take(&mut self.batch))?;
+            self.batch_rows = 0;
+        }
+        Ok(())
+    }
+
+    /// Close the output and return the number of written rows
+    fn finish(mut self) -> Result<u64, Box<dyn Error>> {
+        self.write_batch()?;
+        self.sink.finish()?;
+        if let Some((temporary, output_path)) = self.rename {
+            temporary.persist(output_path)?;
+        }
+        if let Some((temporary, index_path)) = self.index_rename {
+            temporary.persist(index_path)?;
+        }
+        Ok(self.n_rows)
+    }
+}
+
+/// Criterion to split the main output into multiple files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum SplitBy {
+    /// Relative strand in a target region; rows go into `<stem>.plus.<ext>` and `<stem>.minus.<ext>`
+    Strand,
+}
+
+/// Insert `part` before the extensions of a file name, e.g. out.csv.gz -> out.plus.csv.gz
+fn split_output_path<P: AsRef<Path>>(output_path: P, part: &str) -> std::path::PathBuf {
+    let output_path = output_path.as_ref();
+    let file_name = output_path.file_name().map_or_else(String::new, |f| f.to_string_lossy().into_owned());
+    let split_name = match file_name.split_once('.') {
+        Some((stem, extensions)) => format!("{}.{}.{}", stem, part, extensions),
+        None => format!("{}.{}", file_name, part),
+    };
+    output_path.with_file_name(split_name)
+}
+
+/// Write the main output table(s), register them in `manifest`, and return the number of written rows
+fn write_target_kinetics_table<P, I>(targets: I, occ_width: i64, occ_extension: i64, output_options: &OutputOptions, output_path: P, manifest: &mut Manifest) -> Result<u64, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<TargetKinetics, OccError>>,
+{
+    match output_options.split_by {
+        None => {
+            let mut table_writer = TableWriter::create(occ_width, occ_extension, output_options, &output_path)?;
+            for target in targets {
+                let (target_key, target_vals) = target?;
+                table_writer.write_target(target_key, target_vals)?;
+            }
+            let n_rows = table_writer.finish()?;
+            manifest.add_file(&output_path, "kinetics", n_rows);
+            if output_options.format == OutputFormat::Tabix {
+                manifest.add_file(tabix::index_path(&output_path), "tabix_index", 0);
+            }
+            Ok(n_rows)
+        },
+        Some(SplitBy::Strand) => {
+            let plus_path = split_output_path(&output_path, "plus");
+            let minus_path = split_output_path(&output_path, "minus");
+            let mut plus_writer = TableWriter::create(occ_width, occ_extension, output_options, &plus_path)?;
+            let mut minus_writer = TableWriter::create(occ_width, occ_extension, output_options, &minus_path)?;
+            for target in targets {
+                let (target_key, target_vals) = target?;
+                let (plus_vals, minus_vals): (Vec<_>, Vec<_>) = target_vals.into_iter().partition(|t| t.strand == '+');
+                plus_writer.write_target(target_key.clone(), plus_vals)?;
+                minus_writer.write_target(target_key, minus_vals)?;
+            }
+            let n_plus = plus_writer.finish()?;
+            let n_minus = minus_writer.finish()?;
+            manifest.add_file(&plus_path, "kinetics", n_plus);
+            manifest.add_file(&minus_path, "kinetics", n_minus);
+            if output_options.format == OutputFormat::Tabix {
+                manifest.add_file(tabix::index_path(&plus_path), "tabix_index", 0);
+                manifest.add_file(tabix::index_path(&minus_path), "tabix_index", 0);
+            }
+            Ok(n_plus + n_minus)
+        },
+    }
+}
+
+/// Paths of files to be written, except for the main output in the append mode
+fn planned_outputs<P: AsRef<Path>>(output_options: &OutputOptions, output_path: P, manifest_path: Option<&str>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if !output_options.append {
+        let tables = match output_options.split_by {
+            None => vec![output_path.as_ref().to_path_buf()],
+            Some(SplitBy::Strand) => vec![split_output_path(&output_path, "plus"), split_output_path(&output_path, "minus")],
+        };
+        if output_options.format == OutputFormat::Tabix {
+            paths.extend(tables.iter().map(tabix::index_path));
+        }
+        paths.extend(tables);
+    }
+    if let Some(prefix) = output_options.bedgraph_prefix.as_ref() {
+        paths.push(PathBuf::from(format!("{}.plus.bedGraph", prefix)));
+        paths.push(PathBuf::from(format!("{}.minus.bedGraph", prefix)));
+    }
+    paths.extend(output_options.calls_bed.iter().map(PathBuf::from));
+    paths.extend(output_options.profile_output.iter().map(PathBuf::from));
+    paths.extend(output_options.comparison_output.iter().map(PathBuf::from));
+    paths.extend(output_options.region_summary.iter().map(PathBuf::from));
+    paths.extend(output_options.background_table.iter().map(|table| PathBuf::from(&table.path)));
+    paths.extend(output_options.report_output.iter().map(PathBuf::from));
+    paths.extend(output_options.multiqc_output.iter().map(PathBuf::from));
+    paths.extend(output_options.fasta_output.iter().map(PathBuf::from));
+    paths.extend(manifest_path.map(PathBuf::from));
+    paths
+}
+
+/// Write kinetics collected for each target region and the requested sidecar outputs,
+/// register them in `manifest`, and return the number of rows in the main output
+/// Each item of `targets` is a pair of the target key and its rows in the long layout, or an error that stops writing
+/// without leaving the output
+fn write_target_kinetics<P, I>(targets: I, occ_width: i64, occ_extension: i64, output_options: &OutputOptions, output_path: P, manifest: &mut Manifest) -> Result<u64, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Result<TargetKinetics, OccError>>,
+{
+    let mut tracks = output_options.bedgraph_prefix.as_ref().map(|_| BedGraphTracks::new(output_options.track_value));
+    let mut calls = output_options.calls_bed.as_ref().map(|_| CallsBed::new(output_options.call_thresholds.clone()));
+    let mut profile = output_options.profile_output.as_ref().map(|_| Profile::new(output_options.profile_value, output_options.profile_quantiles.clone(), output_options.profile_bootstrap.clone(), output_options.profile_weighting, output_options.profile_robust.clone()))
+        .map(|profile| if output_options.fold_palindrome { profile.folded(output_options.region_spec(occ_width, occ_extension).length()) } else { profile });
+    // binned regions have fewer rows
+    let n_region_rows = output_options.bin_size.is_none().then(|| output_options.region_spec(occ_width, occ_extension).n_rows() as usize);
+    let mut summary = output_options.region_summary.as_ref().map(|_| RegionSummary::new(output_options.profile_value, n_region_rows));
+    let mut report = output_options.report_output.as_ref().map(|_| Report::new(output_options.profile_value, output_options.report_metadata.clone()));
+    let mut multiqc = output_options.multiqc_output.as_ref().map(|_| MultiQc::new(output_options.profile_value, output_options.sample_names.clone()));
+    let targets = targets.map(|target| target.map(|(key, target_vals)| match output_options.bin_size {
+        Some(bin_size) => (key, bin_rows(target_vals, bin_size)),
+        None => (key, target_vals),
+    })).inspect(|target| {
+        let (key, target_vals) = match target {
+            Ok(target) => target,
+            Err(_) => return,
+        };
+        if let Some(tracks) = tracks.as_mut() {
+            target_vals.iter().for_each(|row| tracks.add(row));
+        }
+        if let Some(calls) = calls.as_mut() {
+            target_vals.iter().for_each(|row| calls.add(row));
+        }
+        if let Some(profile) = profile.as_mut() {
+            target_vals.iter().for_each(|row| profile.add(row));
+        }
+        if let Some(summary) = summary.as_mut() {
+            summary.add(key, target_vals);
+        }
+        if let Some(report) = report.as_mut() {
+            target_vals.iter().for_each(|row| report.add(row));
+        }
+        if let Some(multiqc) = multiqc.as_mut() {
+            target_vals.iter().for_each(|row| multiqc.add(row));
+        }
+    });
+    let n_rows = write_target_kinetics_table(targets, occ_width, occ_extension, output_options, &output_path, manifest)?;
+    if let (Some(tracks), Some(prefix)) = (tracks, output_options.bedgraph_prefix.as_ref()) {
+        tracks.write(prefix, manifest)?;
+    }
+    if let (Some(calls), Some(path)) = (calls, output_options.calls_bed.as_ref()) {
+        calls.write(path, manifest)?;
+    }
+    if let (Some(profile), Some(path)) = (profile, output_options.profile_output.as_ref()) {
+        profile.write(path, manifest)?;
+    }
+    if let (Some(summary), Some(path)) = (summary, output_options.region_summary.as_ref()) {
+        summary.write(path, manifest)?;
+    }
+    if let (Some(report), Some(path)) = (report, output_options.report_output.as_ref()) {
+        report.write(path, manifest)?;
+    }
+    if let (Some(multiqc), Some(path)) = (multiqc, output_options.multiqc_output.as_ref()) {
+        multiqc.write(path, manifest)?;
+    }
+    Ok(n_rows)
+}
+
+/// Occurrences read for a collection, with the number of kinetics inputs and the lengths of their chromosomes.
+/// The kinetics inputs themselves are kept by each backend, which looks up their values.
+struct CollectionInputs {
+    occs: Vec<(usize, MergedOcc)>,
+    n_inputs: usize,
+    chromosomes: Vec<(String, i64)>,
+    rejects: Rejects,
+}
+
+/// Read occurrences, or tile windows over chromosomes of the kinetics inputs, and load the kinetics inputs by `load`.
+/// Occurrences are read first, so that the kinetics inputs are not loaded without occurrences;
+/// None in that case, after writing empty outputs.
+fn read_occs_and_kinetics<D, L, C, P>(mut load: L, lengths_of: C, input_options: &InputOptions, output_options: &OutputOptions, output_path: P,
+    manifest: &mut Manifest, run_report: &mut RunReport) -> Result<Option<(CollectionInputs, Vec<D>)>, Box<dyn Error>>
+where
+    L: FnMut(&mut Rejects) -> Result<Vec<D>, Box<dyn Error>>,
+    C: Fn(&[D]) -> Vec<(String, i64)>,
+    P: AsRef<Path>,
+{
+    let (occ_width, occ_extension) = (input_options.occ_width, input_options.occ_extension);
+    let mut rejects = Rejects::default();
+    // windows are tiled over chromosomes of the kinetics inputs, which are read first
+    let mut windowed_kinetics = None;
+    let occs = match input_options.windows.as_ref() {
+        Some(windows) => {
+            run_report.start_phase("read_kinetics");
+            let kinetics = load(&mut rejects)?;
+            let chromosomes = lengths_of(&kinetics);
+            let occs = windows.tile(&chromosomes);
+            windowed_kinetics = Some((kinetics, chromosomes));
+            occs
+        },
+        None => {
+            run_report.start_phase("read_occ");
+            input_options.read_occ(&mut rejects)?
+        },
+    };
+    input_options.prepare_occs(&occs, &output_options.region_spec(occ_width, occ_extension), output_options, manifest)?;
+    if occs.is_empty() {
+        write_empty_target_kinetics(occ_width, occ_extension, output_options, output_path, manifest)?;
+        run_report.add_rejects(&rejects);
+        input_options.finish_rejects(&rejects, manifest)?;
+        return Ok(None);
+    }
+    let (kinetics, chromosomes) = match windowed_kinetics {
+        Some(windowed_kinetics) => windowed_kinetics,
+        None => {
+            run_report.start_phase("read_kinetics");
+            let kinetics = load(&mut rejects)?;
+            let chromosomes = lengths_of(&kinetics);
+            (kinetics, chromosomes)
+        },
+    };
+    let occs = input_options.resolve_missing_chroms(occs, &chromosomes.iter().map(|(chr, _)| chr.as_str()).collect())?;
+    Ok(Some((CollectionInputs { occs, n_inputs: kinetics.len(), chromosomes, rejects }, kinetics)))
+}
+
+/// Collect kinetics of each kinetics input around occurrences and write them, where `value_of` is the value of a kinetics input
+/// (by its index) at a key, or None without data. Both backends collect through here and differ only in `value_of`.
+fn collect_kinetics_in_merged_occ<F, P>(inputs: CollectionInputs, value_of: F, input_options: &InputOptions, output_options: &OutputOptions,
+    output_path: P, manifest: &mut Manifest, run_report: &mut RunReport) -> Result<u64, Box<dyn Error>>
+where
+    F: Fn(usize, &IpdSummaryKey) -> Option<IpdSummaryValue>,
+    P: AsRef<Path>,
+{
+    let CollectionInputs { occs, n_inputs, chromosomes, mut rejects } = inputs;
+    let (occ_width, occ_extension) = (input_options.occ_width, input_options.occ_extension);
+    let region_spec = output_options.region_spec(occ_width, occ_extension);
+    let n_samples = input_options.n_samples(n_inputs);
+    run_report.set_occ_processed(occs.len());
+    run_report.start_phase("collect");
+    let value_at = |sample: usize, key: &IpdSummaryKey| input_options.sample_value(sample, n_inputs, |k| value_of(k, key));
+    let backgrounds = input_options.background.as_ref()
+        .map(|options| sample_backgrounds(options, &chromosomes, n_samples, value_at));
+    if let Some(table) = output_options.background_table.as_ref() {
+        let occ_chromosomes = occs.iter().map(|(_, occ)| occ.refName.as_str()).collect::<Vec<_>>();
+        table.write(&chromosomes, &occ_chromosomes, &output_options.sample_names, n_samples, value_at, manifest)?;
+    }
+    let occ_rows = |sample: usize, i: usize, occ: MergedOcc| -> Result<TargetKinetics, OccError> {
+        let occ_values = input_options.occ_values(&occ);
+        let target_key = IpdSummaryKey::try_from(occ)?;
+        let target_keys = input_options.target_keys(&target_key)?;
+        let target_vals = target_keys.enumerate().filter(|(j, key)| region_spec.has_row(*j) && !input_options.is_clipped(key)).map(|(j, key)| {
+            let target_val = value_at(sample, &key);
+            let target_strand = if j % 2 == 0 { '+' } else { '-' };
+            let mut target = TargetIpdRich::new(((j / 2) + 1) as i64, target_strand, (i + 1) as i64, &region_spec, key, target_val.as_ref())?;
+            input_options.annotate_row(&mut target, &occ_values);
+            target.sample = sample;
+            if let Some(backgrounds) = backgrounds.as_ref() {
+                target.zscore_background = backgrounds[sample].zscore(&target);
+            }
+            if output_options.relative_base && target_strand == '-' {
+                target.base = target.base.map(|b| complement(b as u8) as char);
+            }
+            Ok(target)
+        }).collect::<Result<Vec<_>, OccError>>()?;
+        let target_vals = if input_options.is_poorly_covered(&target_vals) { Vec::new() } else { target_vals };
+        let target_vals = match output_options.combine_strands {
+            Some(how) => combine_strands(target_vals, how),
+            None => target_vals,
+        };
+        let target_vals = input_options.missing_policy.apply(target_vals);
+        if input_options.has_full_regions() && !target_vals.is_empty() && target_vals.len() as i64 != region_spec.n_rows() {
+            return Err(OccError::RowCount { chr: target_key.refName, start: target_key.tpl - 1, rows: target_vals.len(), expected: region_spec.n_rows() });
+        }
+        Ok((target_key, target_vals))
+    };
+    let mut comparison = output_options.comparison_output.as_ref().map(|_| Comparison::new(output_options.profile_value));
+    // occurrences of each kinetics input in turn
+    let mut n_dropped = 0;
+    let n_regions = (n_samples * occs.len()) as u64;
+    let mut progress = Progress::new("Collecting", n_regions, ProgressUnit::Regions, input_options.progress);
+    let target_kinetics = (0..n_samples).flat_map(|sample| occs.iter().map(move |(i, occ)| (sample, *i, occ.clone())))
+        .map(|(sample, i, occ)| occ_rows(sample, i, occ)).inspect(|_| progress.inc(1)).filter(|target| {
+        // an error is passed on to stop writing
+        let empty = matches!(target, Ok((_, target_vals)) if target_vals.is_empty());
+        n_dropped += empty as usize;
+        !empty
+    }).inspect(|target| {
+        if let Ok((_, target_vals)) = target {
+            run_report.add_rows(target_vals);
+            if let Some(comparison) = comparison.as_mut() {
+                target_vals.iter().for_each(|row| comparison.add(row, true));
+            }
+        }
+    });
+    let n_rows = write_target_kinetics(target_kinetics, occ_width, occ_extension, output_options, output_path, manifest)?;
+    progress.finish();
+    if n_dropped > 0 {
+        eprintln!("[INFO] Left out {} target regions without rows to write (--missing-policy, --edge-policy, or --min-region-coverage)", n_dropped);
+    }
+    let all_samples_rows = |i: usize, occ: MergedOcc| (0..n_samples)
+        .map(|sample| occ_rows(sample, i, occ.clone()).map(|(_, target_vals)| target_vals)).collect::<Result<Vec<_>, _>>().map(|rows| rows.concat());
+    write_comparison(comparison, all_samples_rows, &chromosomes, input_options, output_options, &mut rejects, manifest)?;
+    run_report.add_rejects(&rejects);
+    run_report.finish(n_dropped, n_rows);
+    input_options.finish_rejects(&rejects, manifest)?;
+    Ok(n_rows)
+}
+
+/// Collect kinetics in kinetics CSV files
+fn collect_ipd_summary_in_merged_occ<K: AsRef<Path>, P: AsRef<Path>>(
+    kinetics_paths: &[K], input_options: &InputOptions, output_options: &OutputOptions, output_path: P, manifest: &mut Manifest, run_report: &mut RunReport) -> Result<u64, Box<dyn Error>>
+{
+    let load = |rejects: &mut Rejects| kinetics_paths.iter().map(|path| input_options.read_kinetics_csv(path, rejects)).collect::<Result<Vec<_>, _>>();
+    let lengths_of = |kinetics: &[HashMap<IpdSummaryKey, IpdSummaryValue>]| {
+        chromosome_lengths(kinetics.iter().flat_map(|k| k.keys().map(|key| (key.refName.as_str(), key.tpl))))
+    };
+    let (inputs, kinetics) = match read_occs_and_kinetics(load, lengths_of, input_options, output_options, &output_path, manifest, run_report)? {
+        Some(loaded) => loaded,
+        None => return Ok(0),
+    };
+    // values are normalized on loading
+    collect_kinetics_in_merged_occ(inputs, |k, key| kinetics[k].get(key).cloned(), input_options, output_options, output_path, manifest, run_report)
+}
+
+/// Chromosomal kinetics data for PacBio ipdSummary output in HDF5 format
+#[derive(Default)]
+#[allow(non_snake_case)]
+struct ChrKineticsHdf5 {
+    tpl: Vec<u32>,
+    strand: Vec<u8>,
+    // convert into Vec<Option<char>>?
+    base: Vec<String>,
+    score: Vec<u32>,
+    tMean: Vec<f32>,
+    tErr: Vec<f32>,
+    modelPrediction: Vec<f32>,
+    ipdRatio: Vec<f32>,
+    coverage: Vec<u32>,
+    frac: Vec<f32>,
+    fracLow: Vec<f32>,
+    fracUp: Vec<f32>,
+    /// Pulse width datasets, if present
+    pwMean: Option<Vec<f32>>,
+    pwErr: Option<Vec<f32>>,
+}
+
+impl ChrKineticsHdf5 {
+    /// Check that a dataset has the type of the ipdSummary HDF5 output
+    fn check_dtype(data: &Dataset, expected: TypeDescriptor) -> Result<(), Box<dyn Error>> {
+        let found = data.dtype()?.to_descriptor()?;
+        if found != expected {
+            return Err(format!("Dataset {} has type {} instead of {}", data.name(), found, expected).into());
+        }
+        Ok(())
+    }
+
+    fn read_hdf5_f32(data: Dataset) -> Result<Vec<f32>, Box<dyn Error>> {
+        Self::check_dtype(&data, TypeDescriptor::Float(FloatSize::U4))?;
+        Ok(data.read_raw::<f32>()?)
+    }
+
+    fn read_hdf5_u32(data: Dataset) -> Result<Vec<u32>, Box<dyn Error>> {
+        Self::check_dtype(&data, TypeDescriptor::Unsigned(IntSize::U4))?;
+        Ok(data.read_raw::<u32>()?)
+    }
+
+    fn read_hdf5_u8(data: Dataset) -> Result<Vec<u8>, Box<dyn Error>> {
+        Self::check_dtype(&data, TypeDescriptor::Unsigned(IntSize::U1))?;
+        Ok(data.read_raw::<u8>()?)
+    }
+
+    fn read_hdf5_str(data: Dataset) -> Result<Vec<String>, Box<dyn Error>> {
+        Self::check_dtype(&data, TypeDescriptor::FixedAscii(1))?;
+        Ok(data.read_raw::<FixedAscii<1>>()?.iter().map(|e| e.as_str().to_string()).collect())
+    }
+
+    fn new(chr_file: hdf5::Group) -> Result<Self, Box<dyn Error>> {
+        let dataset = |name: &str| chr_file.dataset(name).map_err(|e| format!("Dataset {} in group {}: {}", name, chr_file.name(), e));
+        Ok(Self {
+            tpl: Self::read_hdf5_u32(dataset("tpl")?)?,
+            strand: Self::read_hdf5_u8(dataset("strand")?)?,
+            base: Self::read_hdf5_str(dataset("base")?)?,
+            score: Self::read_hdf5_u32(dataset("score")?)?,
+            tMean: Self::read_hdf5_f32(dataset("tMean")?)?,
+            tErr: Self::read_hdf5_f32(dataset("tErr")?)?,
+            modelPrediction: Self::read_hdf5_f32(dataset("modelPrediction")?)?,
+            ipdRatio: Self::read_hdf5_f32(dataset("ipdRatio")?)?,
+            coverage: Self::read_hdf5_u32(dataset("coverage")?)?,
+            frac: Self::read_hdf5_f32(dataset("frac")?)?,
+            fracLow: Self::read_hdf5_f32(dataset("fracLow")?)?,
+            fracUp: Self::read_hdf5_f32(dataset("fracUp")?)?,
+            pwMean: chr_file.dataset("pwMean").ok().map(Self::read_hdf5_f32).transpose()?,
+            pwErr: chr_file.dataset("pwErr").ok().map(Self::read_hdf5_f32).transpose()?,
+        })
+    }
+
+    /// Read datasets of chromosomes included by `chrom_filter`, with a progress bar of rows if `show_progress`
+    fn kinetics_datasets_from_hdf5_path<P: AsRef<Path>>(path: P, chrom_filter: &ChromFilter, show_progress: bool) -> Result<HashMap<String, ChrKineticsHdf5>, Box<dyn Error>> {
+        let label = format!("Loading {}", path.as_ref().display());
+        let file = hdf5::File::open(path)?;
+        let chromosomes = file.member_names()?.into_iter().filter(|chr| chrom_filter.includes(chr)).collect::<Vec<_>>();
+        // the number of rows from the shapes of datasets, without reading them
+        let n_rows = if show_progress {
+            chromosomes.iter().map(|chr| file.group(chr).and_then(|group| group.dataset("tpl")).map_or(0, |data| data.size() as u64)).sum()
+        } else {
+            0
+        };
+        let mut progress = Progress::new(&label, n_rows, ProgressUnit::Rows, show_progress);
+        let mut datasets = HashMap::new();
+        for chr in chromosomes {
+            let chr_kinetics = Self::new(file.group(&chr)?)?;
+            progress.inc(chr_kinetics.tpl.len() as u64);
+            datasets.insert(chr, chr_kinetics);
+        }
+        progress.finish();
+        file.close()?;
+        Ok(datasets)
+    }
+
+    /// Return None if no data is available at `key`
+    fn get_ipd_summary_value(&self, key: &IpdSummaryKey) -> Option<IpdSummaryValue> {
+        // IpdSummaryKey tpl (position) is 1-based
+        let pre_index: i64 = (key.tpl - 1) * 2 + (key.strand as i64);
+        // None for a negative index
+        let opt_index: Option<usize> = pre_index.try_into().ok();
+        match opt_index {
+            // TODO?: we can use get_unchecked to skip index bound check
+            // a base with non-finite tMean (nan or inf) is handled as a base without data as in the CSV backend
+            Some(index) if index < self.coverage.len() && self.coverage[index] > 0 && self.tMean[index].is_finite() => {
+                debug_assert_eq!(self.tpl[index] as i64, key.tpl);
+                debug_assert_eq!(self.strand[index], key.strand);
+                Some(IpdSummaryValue {
+                    base: self.base[index].chars().next(),
+                    score: self.score[index],
+                    tMean: self.tMean[index],
+                    tErr: self.tErr[index],
+                    modelPrediction: self.modelPrediction[index],
+                    ipdRatio: self.ipdRatio[index],
+                    coverage: self.coverage[index],
+                    // non-finite frac is handled by `InputOptions::normalize_value` as in the CSV backend
+                    frac: Some(self.frac[index]),
+                    fracLow: Some(self.fracLow[index]),
+                    fracUp: Some(self.fracUp[index]),
+                    pwMean: self.pwMean.as_ref().map(|pw| pw[index]),
+                    pwErr: self.pwErr.as_ref().map(|pw| pw[index]),
+                })
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Collect kinetics in ipdSummary HDF5 files
+fn collect_hdf5_ipd_summary_in_merged_occ<K: AsRef<Path>, P: AsRef<Path>>(
+    kinetics_paths: &[K], input_options: &InputOptions, output_options: &OutputOptions, output_path: P, manifest: &mut Manifest, run_report: &mut RunReport) -> Result<u64, Box<dyn Error>>
+{
+    let load = |_: &mut Rejects| kinetics_paths.iter()
+        .map(|path| ChrKineticsHdf5::kinetics_datasets_from_hdf5_path(path, &input_options.chrom_filter, input_options.progress)).collect::<Result<Vec<_>, _>>();
+    let lengths_of = |kinetics: &[HashMap<String, ChrKineticsHdf5>]| {
+        chromosome_lengths(kinetics.iter().flat_map(|k| k.iter().map(|(chr, data)| (chr.as_str(), data.coverage.len() as i64 / 2))))
+    };
+    let (inputs, kinetics) = match read_occs_and_kinetics(load, lengths_of, input_options, output_options, &output_path, manifest, run_report)? {
+        Some(loaded) => loaded,
+        None => return Ok(0),
+    };
+    let value_of = |k: usize, key: &IpdSummaryKey| {
+        kinetics[k].get(&key.refName).and_then(|data| data.get_ipd_summary_value(key)).map(|v| input_options.normalize_value(v))
+    };
+    collect_kinetics_in_merged_occ(inputs, value_of, input_options, output_options, output_path, manifest, run_report)
+}
+
+/// Add rows of control occurrences, collected by `occ_rows` from all kinetics inputs, to `comparison` of case occurrences and write it
+fn write_comparison<F>(comparison: Option<Comparison>, occ_rows: F, chromosomes: &[(String, i64)], input_options: &InputOptions, output_options: &OutputOptions,
+    rejects: &mut Rejects, manifest: &mut Manifest) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(usize, MergedOcc) -> Result<Vec<TargetIpdRich>, OccError>,
+{
+    let (mut comparison, control_occ_path, path) = match (comparison, input_options.control_occ_path.as_ref(), output_options.comparison_output.as_ref()) {
+        (Some(comparison), Some(control_occ_path), Some(path)) => (comparison, control_occ_path, path),
+        _ => return Ok(()),
+    };
+    let control_occs = input_options.read_occ_path(control_occ_path, "control_occ", rejects)?;
+    // control occurrences follow --missing-chrom-policy as the others do
+    let control_occs = input_options.resolve_missing_chroms(control_occs, &chromosomes.iter().map(|(chr, _)| chr.as_str()).collect())?;
+    eprintln!("[INFO] Comparing with {} control occurrences", control_occs.len());
+    for (i, occ) in control_occs {
+        let target_vals = occ_rows(i, occ)?;
+        target_vals.iter().for_each(|row| comparison.add(row, false));
+    }
+    comparison.write(path, manifest)
+}
+
+#[derive(Debug, Clone)]
+struct RegionOverflow {
+    message: String,
+}
+impl std::fmt::Display for RegionOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RegionOverflow: {}", self.message)
+    }
+}
+impl Error for RegionOverflow {}
+impl std::default::Default for RegionOverflow {
+    fn default() -> Self {
+        RegionOverflow {
+            message: "Total region length exceeds u64".to_string(),
+        }
+    }
+}
+
+/// Collect kinetics info at specified regions
+#[derive(Debug, Parser, Serialize)]
+#[clap(about, version, author)]
+// Make csv input and HDF5 input mutually exclusive
+#[clap(group(
+        ArgGroup::new("inputs").required(true).args(&["kinetics", "kinetics-hdf5"]),
+        ))]
+// Arguments for collection are not required when a subcommand is given
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[clap(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+
+    /// Kinetics CSV file generated by PacBio `ipdSummary`.
+    /// Can be given multiple times with --sample-names to collect replicates into one output
+    #[clap(long, short, multiple_occurrences = true)]
+    #[serde(skip)]
+    kinetics: Vec<String>,
+
+    /// Kinetics HDF5 (.h5) file generated by PacBio `ipdSummary`.
+    /// Can be given multiple times with --sample-names to collect replicates into one output
+    #[clap(long, short = 'H', multiple_occurrences = true)]
+    #[serde(skip)]
+    kinetics_hdf5: Vec<String>,
+
+    /// File listing positions of motif occurrences or target bases.
+    /// Each row has chromosome name, 0-based start position, and strand with delimiter of single
+    /// space. A header line such as `refName start strand` is optional and detected automatically.
+    #[clap(long, required_unless_present_any = &["window", "whole-chroms"], conflicts_with_all = &["window", "whole-chroms"])]
+    #[serde(skip)]
+    occ: Option<String>,
+
+    /// Length of the motif or target region including the start position
+    #[clap(long, required_unless_present_any = &["window", "whole-chroms"], conflicts_with_all = &["window", "whole-chroms"])]
+    occ_width: Option<i64>,
+
+    /// Instead of --occ, export kinetics of every base of these comma-separated chromosomes in the output schema,
+    /// one target region of width 1 per base (as full-chromosome tracks rather than regional extracts)
+    #[clap(long, use_value_delimiter = true, conflicts_with = "window")]
+    whole_chroms: Option<Vec<String>>,
+
+    /// Instead of --occ, tile each chromosome with kinetics data into windows of this length, collected as
+    /// plus-strand target regions (e.g. with --bin-size or --region-summary for genome-wide scans)
+    #[clap(long)]
+    window: Option<i64>,
+
+    /// Distance between the starts of consecutive windows of --window (by default, the window length)
+    #[clap(long, requires = "window")]
+    step: Option<i64>,
+
+    /// Length of an extended region for each end of a target region (0 by default with --window and --whole-chroms)
+    #[clap(long)]
+    extend: Option<i64>,
+
+    /// Length of the extended region before (to the left of) a target region in reference coordinates, instead of --extend;
+    /// asymmetric extensions of minus-strand occurrences require --strand-relative-extension
+    #[clap(long)]
+    extend_up: Option<i64>,
+
+    /// Length of the extended region after (to the right of) a target region in reference coordinates, instead of --extend
+    #[clap(long)]
+    extend_down: Option<i64>,
+
+    /// Apply --extend-up and --extend-down on the strand of each occurrence, i.e. upstream of a minus-strand occurrence
+    /// is to its right (3'-ward) in reference coordinates. Rows, labels, and regions always follow the orientation of
+    /// each occurrence, so Upstream rows are the --extend-up extension on both strands only with this option
+    #[clap(long)]
+    strand_relative_extension: bool,
+
+    /// Write only the row of the strand of each occurrence (the relative plus strand) at each position,
+    /// instead of rows of both strands
+    #[clap(long)]
+    target_strand_only: bool,
+
+    /// Combine rows of both strands at each position into one row (labeled as the relative plus strand)
+    /// with the mean, sum, or max of values of strands with data, e.g. for palindromic motifs or CpG sites
+    #[clap(long, arg_enum, conflicts_with = "target-strand-only")]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    combine_strands: Option<CombineStrands>,
+
+    /// Center the extended region on this base of each occurrence in its orientation instead of spanning
+    /// the whole occurrence: the target region is the single anchor base (e.g. for peak summits with `center`)
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    anchor: Option<Anchor>,
+
+    /// Output CSV path
+    #[clap(long, short, required = true)]
+    output: Option<String>,
+
+    /// Configure output options for a downstream tool; options given explicitly with non-default values take precedence
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    preset: Option<Preset>,
+
+    /// Style of position labels: `part` writes a region part (s: upstream, m: target, e: downstream) and a position in it,
+    /// e.g. s3p, m1p, e2m; `signed` writes a signed offset, e.g. -3p (upstream), 1p (target), +2m (downstream).
+    /// The last letter is the relative strand (p: plus, m: minus). Upstream and downstream are in the orientation of each occurrence
+    #[clap(long, arg_enum, default_value = "part")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    label_style: LabelStyle,
+
+    /// Comma-separated lengths of sub-parts dividing each target region, e.g. 2,2 for a 4-bp motif,
+    /// which sum up to --occ-width. Their regions are Target1, Target2, ..., and their labels of the `part` style are
+    /// m<part>_<position in the part><strand>, e.g. m2_1p
+    #[clap(long, use_value_delimiter = true)]
+    region_parts: Vec<i64>,
+
+    /// Average rows of each relative strand within bins of this number of consecutive positions of the extended region
+    /// and write one row per bin (long layout only), e.g. for profiles of wide regions.
+    /// A bin row has the position, label, region, and reference position of its first base and no base;
+    /// kinetics fields are means over bases with data. Sidecar outputs also use bin rows, except --comparison-output
+    #[clap(long, value_name = "B")]
+    bin_size: Option<i64>,
+
+    /// Output layout: `long` writes one row per position and strand,
+    /// `wide` writes one row per target region with labeled positions as columns
+    #[clap(long, arg_enum, default_value = "long")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    layout: Layout,
+
+    /// File format of the output.
+    /// If omitted, it is guessed from the extension of the output path (.feather or .arrow for Feather, .parquet for Parquet,
+    /// .bgz for Tabix). `tabix` writes tab-separated rows of the long layout sorted by ref_chr and ref_position,
+    /// compressed with bgzip and indexed into <output>.tbi, e.g. for `tabix` queries and IGV tracks
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    output_format: Option<OutputFormat>,
+
+    /// Split the output into multiple files.
+    /// `strand` writes rows of each relative strand into <stem>.plus.<ext> and <stem>.minus.<ext>
+    /// derived from the output path (long layout only)
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    split_by: Option<SplitBy>,
+
+    /// Comma-separated columns written first in the long layout, e.g. region,strand,label;
+    /// the other columns follow in the default order.
+    /// Placing low-cardinality columns first makes compressed output smaller
+    #[clap(long, arg_enum, use_value_delimiter = true)]
+    #[serde(serialize_with = "provenance::arg_names")]
+    column_order: Vec<Column>,
+
+    /// Comma-separated kinetics fields written into the `value` column (and the columns of labeled positions in the wide layout).
+    /// Multiple fields are written into columns suffixed with field names, e.g. value_tMean,value_ipdRatio
+    #[clap(long, arg_enum, use_value_delimiter = true, default_value = "tMean")]
+    #[serde(serialize_with = "provenance::arg_names")]
+    value: Vec<ValueField>,
+
+    /// Add frac, fracLow and fracUp columns of ipdSummary to the long layout; they are empty if absent
+    #[clap(long)]
+    frac_columns: bool,
+
+    /// Add pwMean and pwErr columns of pulse widths to the long layout;
+    /// they are empty unless the kinetics input has pwMean and pwErr fields (or datasets in HDF5)
+    #[clap(long)]
+    pw_columns: bool,
+
+    /// Handling of non-finite frac values (e.g. NaN in HDF5 for positions without an estimate), the same in both backends:
+    /// `null` writes frac, fracLow, and fracUp as missing, and `keep` writes them as they are
+    #[clap(long, arg_enum, default_value = "null")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    nonfinite_frac: NonFiniteFrac,
+
+    /// Handling of occurrences with strand '.' (e.g. unstranded peaks): `plus` reads them on the plus strand,
+    /// `both` reads each of them on both strands with a strand_assumed column, `skip` rejects them,
+    /// and `error` stops with exit code 3
+    #[clap(long, arg_enum, default_value = "error")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    unknown_strand_policy: UnknownStrand,
+
+    /// Handling of occurrences on chromosomes absent from the kinetics inputs: `error` stops listing the chromosomes
+    /// of the kinetics inputs, `skip` drops the occurrences, and `zero` writes rows without data for them.
+    /// Earlier versions always wrote rows without data; use `zero` to keep that behavior
+    #[clap(long, arg_enum, default_value = "error")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    missing_chrom_policy: MissingChrom,
+
+    /// Handling of rows of bases without kinetics data: `zero` writes zeros and `na` writes NA (or --na-string if given),
+    /// `skip-row` leaves out the rows, and `drop-region` leaves out target regions with any of them; summaries and
+    /// profiles are computed over the rows written
+    #[clap(long, arg_enum, default_value = "zero")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    missing_policy: MissingPolicy,
+
+    /// Treat kinetics of bases with coverage below N (of merged replicates with --merge-replicates) as absent,
+    /// written per --missing-policy and excluded from summaries and profiles like bases without data
+    #[clap(long, value_name = "N")]
+    min_coverage: Option<u32>,
+
+    /// Treat kinetics of bases with an ipdSummary score below this as absent: NA-masked with --missing-policy na,
+    /// or omitted with --missing-policy skip-row
+    #[clap(long, value_name = "SCORE")]
+    min_score: Option<u32>,
+
+    /// Treat kinetics of bases with frac (the estimated fraction of modified molecules) below this as absent;
+    /// bases without frac are also treated as absent with --min-frac or --max-frac
+    #[clap(long, value_name = "FRAC")]
+    min_frac: Option<f32>,
+
+    /// Treat kinetics of bases with frac above this as absent
+    #[clap(long, value_name = "FRAC")]
+    max_frac: Option<f32>,
+
+    /// Leave out occurrences whose coverage in the target part (both relative strands) is below this,
+    /// as the statistic of --region-coverage-stat; the number of them is reported
+    #[clap(long, value_name = "COVERAGE")]
+    min_region_coverage: Option<f64>,
+
+    /// Statistic of coverage in the target part compared with --min-region-coverage
+    #[clap(long, arg_enum, default_value = "mean")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    region_coverage_stat: RegionCoverageStat,
+
+    /// Value written for tMean and ipdRatio of bases without kinetics data in --missing-policy zero instead of 0,
+    /// which is a legal IPD, e.g. -1
+    #[clap(long, conflicts_with = "na-string", allow_hyphen_values = true)]
+    fill_value: Option<f32>,
+
+    /// Coordinate system of starts in occ files: 0 for 0-based (e.g. from BED), or 1 for 1-based (e.g. from GFF)
+    #[clap(long, arg_enum, default_value = "0")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    occ_coords: OccCoords,
+
+    /// Add a distance column of the signed offset of each row from the first base of the target region,
+    /// in the orientation of the target: negative upstream, 0 to occ-width - 1 within the target, and larger downstream
+    #[clap(long)]
+    distance_column: bool,
+
+    /// Kinetics CSV file of control DNA (e.g. WGA or PCR-amplified) to add an ipdRatio_control column
+    /// of tMean over control tMean at the same position and strand (NA without data in either input)
+    #[clap(long)]
+    #[serde(skip)]
+    control_kinetics: Option<String>,
+
+    /// Add a gc_target column of the GC fraction (among A, C, G, and T) of the target region of each row;
+    /// requires --reference
+    #[clap(long, requires = "reference")]
+    gc_content: bool,
+
+    /// Add a gc_window column of the GC fraction of the extended window (the target region and both extensions);
+    /// requires --reference
+    #[clap(long, requires = "reference")]
+    gc_window: bool,
+
+    /// Add an is_missing column of whether each base has no kinetics data, instead of inferring it from zero coverage
+    #[clap(long)]
+    missing_column: bool,
+
+    /// Add an occ_id column identifying each occurrence by a hash of its chromosome, start, and strand,
+    /// so that occurrences can be joined across runs regardless of their order (unlike src)
+    #[clap(long)]
+    occ_id: bool,
+
+    /// Add a log2_obs_over_model column of log2(tMean / modelPrediction) to the long layout;
+    /// it is NA (--na-string or empty) if either value is zero or the base has no kinetics data
+    #[clap(long)]
+    log2_obs_over_model: bool,
+
+    /// String written for missing values in CSV output, i.e. kinetics of bases without data
+    /// and absent optional values. If omitted, such bases are written as zeros and absent values as empty fields
+    #[clap(long)]
+    na_string: Option<String>,
+
+    /// Number of digits after the decimal point of floating-point values in CSV output.
+    /// If omitted, the shortest representation is written
+    #[clap(long)]
+    float_precision: Option<usize>,
+
+    /// Prepend provenance metadata (tool version, command line, input files and sizes, timestamp,
+    /// and options that are set) to CSV output as lines starting with '#', or embed it as schema metadata in Feather and Parquet
+    #[clap(long)]
+    provenance: bool,
+
+    /// Append rows to an existing output (CSV only) instead of overwriting it.
+    /// The header is written only if the output does not exist or is empty
+    #[clap(long)]
+    append: bool,
+
+    /// Add a `sample` column with this value as the first column,
+    /// e.g. to combine runs over different kinetics files with --append
+    #[clap(long)]
+    sample_name: Option<String>,
+
+    /// Comma-separated sample names of the kinetics inputs in the order given, written in a `sample` column
+    /// as the first column; required for multiple kinetics inputs.
+    /// Of the sidecar outputs, only --multiqc-output is written for multiple samples
+    #[clap(long, use_value_delimiter = true, conflicts_with = "sample-name")]
+    sample_names: Vec<String>,
+
+    /// Pool multiple kinetics inputs, e.g. SMRT cells of the same sample, into one dataset before collection:
+    /// coverage is summed, and tMean and the other fields are averaged with weights of coverage
+    /// (tErr as the standard error of the weighted mean, and ipdRatio recomputed from tMean and modelPrediction)
+    #[clap(long, conflicts_with = "sample-names")]
+    merge_replicates: bool,
+
+    /// Write the base column on the relative strand of each row rather than the strand of the kinetics data,
+    /// by complementing bases of relative minus-strand rows
+    #[clap(long)]
+    relative_base: bool,
+
+    /// Add `kinetics_source` and `occ_source` columns with the file names (without directories) of --kinetics
+    /// (or --kinetics-hdf5) of each row and --occ after the sample column, so that outputs combined with --append stay self-describing
+    #[clap(long)]
+    source_columns: bool,
+
+    /// Overwrite existing output files
+    #[clap(long)]
+    force: bool,
+
+    /// Compression format of the output.
+    /// If omitted, it is guessed from the extension of the output path (.gz or .zst)
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    output_compression: Option<Compression>,
+
+    /// Compression level (gzip: 0-9, zstd: 1-22).
+    /// If omitted, the default level of the compression format is used
+    #[clap(long)]
+    compression_level: Option<i32>,
+
+    /// Also write collected values at their reference coordinates as stranded bedGraph tracks,
+    /// <PREFIX>.plus.bedGraph and <PREFIX>.minus.bedGraph (convertible with UCSC bedGraphToBigWig)
+    #[clap(long)]
+    bedgraph_prefix: Option<String>,
+
+    /// Kinetics field written into bedGraph tracks
+    #[clap(long, arg_enum, default_value = "tMean")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    track_value: TrackValue,
+
+    /// Also write positions in target regions passing --call-min-score and --call-min-ipd-ratio
+    /// as putative modifications in BED6 (score: ipdSummary score, name: source region, label, ipdRatio, and coverage)
+    #[clap(long)]
+    calls_bed: Option<String>,
+
+    /// Minimum ipdSummary score of a position written into --calls-bed
+    #[clap(long, default_value = "20")]
+    call_min_score: u32,
+
+    /// Minimum ipdRatio of a position written into --calls-bed
+    #[clap(long, default_value = "0")]
+    call_min_ipd_ratio: f32,
+
+    /// Also write a CSV of --profile-value aggregated over all occurrences per label and relative strand:
+    /// n, mean, median, sd, and --profile-quantiles, counting only positions with kinetics data
+    #[clap(long)]
+    profile_output: Option<String>,
+
+    /// Kinetics field aggregated into --profile-output, --region-summary, --comparison-output, and --report
+    #[clap(long, arg_enum, default_value = "tMean")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    profile_value: ValueField,
+
+    /// Comma-separated probabilities of quantiles written into --profile-output as columns q<percent>, e.g. q25
+    #[clap(long, use_value_delimiter = true, default_value = "0.05,0.25,0.75,0.95")]
+    profile_quantiles: Vec<f64>,
+
+    /// Weights of values in means and standard deviations (and bootstrap means) of --profile-output
+    #[clap(long, arg_enum, default_value = "none")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    profile_weighting: ProfileWeighting,
+
+    /// Cap values aggregated into --profile-output at this value, e.g. to limit pausing artifacts with extremely long IPDs
+    #[clap(long)]
+    cap_value: Option<f32>,
+
+    /// Add a trimmed_mean column to --profile-output, dropping this fraction of values at each end (0 to 0.5)
+    #[clap(long)]
+    profile_trim: Option<f64>,
+
+    /// Add a winsorized_mean column to --profile-output, clamping this fraction of values at each end (0 to 0.5)
+    #[clap(long)]
+    profile_winsorize: Option<f64>,
+
+    /// Number of bootstrap replicates resampling occurrences with replacement, which adds 95% confidence intervals
+    /// of means (mean_ci_low and mean_ci_high) to --profile-output
+    #[clap(long, requires = "profile-output")]
+    bootstrap: Option<usize>,
+
+    /// Fold both strands of palindromic motifs (e.g. GATC) together in --profile-output: position i on the relative
+    /// minus strand is aggregated with position L + 1 - i on the relative plus strand, where L is the length of
+    /// the extended region, giving one symmetric profile labeled by plus-strand positions. Requires symmetric extensions
+    #[clap(long, requires = "profile-output", conflicts_with_all = &["target-strand-only", "combine-strands", "bin-size"])]
+    fold_palindrome: bool,
+
+    /// Seed of the random number generator for --bootstrap, --zscore-background, and --background-n
+    #[clap(long, default_value = "1")]
+    seed: u64,
+
+    /// Add a zscore_background column of the --background-value of each row standardized with the mean and sd
+    /// of this number of random positions (and strands) with kinetics data in the same kinetics input
+    #[clap(long)]
+    zscore_background: Option<usize>,
+
+    /// Kinetics field of the background and zscore_background
+    #[clap(long, arg_enum, default_value = "tMean")]
+    #[serde(serialize_with = "provenance::arg_name")]
+    background_value: ValueField,
+
+    /// Standardize values with background positions of the same base (ipdSummary's base on the strand of the data)
+    #[clap(long, requires = "zscore-background")]
+    background_by_base: bool,
+
+    /// Occurrences compared with --occ (e.g. unmethylated instances of a motif) in the same format,
+    /// written into --comparison-output
+    #[clap(long, requires = "comparison-output")]
+    #[serde(skip)]
+    control_occ: Option<String>,
+
+    /// CSV of per-label tests of --profile-value between occurrences in --occ and --control-occ:
+    /// numbers and means of values, Welch's t-test, and the Wilcoxon rank-sum (Mann-Whitney U) test
+    #[clap(long, requires = "control-occ")]
+    comparison_output: Option<String>,
+
+    /// Also write a CSV with one row per occurrence: mean and median of --profile-value in the target region and
+    /// each flank, the enrichment ratio of the target mean over the flank mean, the minimum coverage,
+    /// and the number of positions without kinetics data (both relative strands), including rows left out by --missing-policy skip-row
+    #[clap(long)]
+    region_summary: Option<String>,
+
+    /// Also write a self-contained HTML report with run metadata, QC tables, the metaprofile of --profile-value,
+    /// a map of missing data per position, and per-chromosome statistics
+    #[clap(long)]
+    report: Option<String>,
+
+    /// Write a JSON report of the run for workflow managers: numbers of processed and skipped occurrences,
+    /// written rows, and missing positions per chromosome, wall-clock time per phase, peak memory, and effective parameters
+    #[clap(long)]
+    run_report: Option<String>,
+
+    /// Write a summary for MultiQC (regions collected, mean --profile-value in target regions and flanks, and coverage
+    /// of each sample) as custom content, in JSON or TSV by the file name, which must end with _mqc.json or _mqc.tsv
+    #[clap(long)]
+    multiqc_output: Option<String>,
+
+    /// Also write a CSV of kinetics at this number of random positions (and strands) with data in the kinetics input,
+    /// e.g. as a background of collected values; requires --background-output
+    #[clap(long, requires = "background-output")]
+    background_n: Option<usize>,
+
+    /// Output CSV of --background-n positions, drawn with --seed
+    #[clap(long, requires = "background-n")]
+    background_output: Option<String>,
+
+    /// Draw background positions from each chromosome in proportion to the occurrences on it
+    #[clap(long, requires = "background-n")]
+    background_per_chromosome: bool,
+
+    /// Process only the first N accepted occurrences (and control occurrences), e.g. for a quick dry run;
+    /// src keeps the numbering of occurrences in the whole occ file
+    #[clap(long, value_name = "N")]
+    max_occ: Option<usize>,
+
+    /// Tab-separated chromosome names and lengths (e.g. chrom.sizes or .fai) to reject occurrences
+    /// starting beyond chromosome ends or on unknown chromosomes, instead of writing rows without data for them
+    #[clap(long)]
+    #[serde(skip)]
+    chrom_sizes: Option<String>,
+
+    /// Handle extended target regions running past chromosome ends in --chrom-sizes: `clip` writes NA for
+    /// kinetics fields of positions beyond the ends, `skip` rejects the occurrences, and `error` stops
+    #[clap(long, arg_enum, requires = "chrom-sizes")]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    chrom_end_policy: Option<ChromEndPolicy>,
+
+    /// Handle extended target regions running before position 1 of chromosomes: `clip` leaves out rows of
+    /// positions before it, `skip` rejects the occurrences, and `pad-na` writes NA for kinetics fields of
+    /// those rows with an off_contig column
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    edge_policy: Option<EdgePolicy>,
+
+    /// BED of blacklisted intervals (e.g. the ENCODE blacklist): occurrences whose extended target regions overlap them
+    /// are skipped and reported as rejected records
+    #[clap(long)]
+    #[serde(skip)]
+    blacklist: Option<String>,
+
+    /// Tab-separated chromosome names in occ files and the corresponding names in kinetics (e.g. 1 and chr1,
+    /// or RefSeq accessions and UCSC names), applied before any other check of occurrences
+    #[clap(long)]
+    #[serde(skip)]
+    chrom_alias: Option<String>,
+
+    /// Add or remove the "chr" prefix of chromosome names in occ files without an alias in --chrom-alias
+    #[clap(long, arg_enum)]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    chr_prefix: Option<ChrPrefix>,
+
+    /// Comma-separated chromosomes (or glob patterns with * and ?) of occurrences and kinetics to read; all by default
+    #[clap(long, use_value_delimiter = true)]
+    chroms: Vec<String>,
+
+    /// Comma-separated chromosomes (or glob patterns with * and ?) whose occurrences and kinetics are not read, e.g. chrM,chrUn*
+    #[clap(long, use_value_delimiter = true)]
+    exclude_chroms: Vec<String>,
+
+    /// Skip malformed occ and kinetics records and duplicate occurrences instead of stopping with an error
+    #[clap(long)]
+    permissive: bool,
+
+    /// Skip malformed kinetics CSV records as rejected records instead of stopping with an error
+    #[clap(long)]
+    skip_bad_kinetics_rows: bool,
+
+    /// Stop at the first malformed occ record instead of skipping it with a warning of its line number
+    #[clap(long, conflicts_with = "permissive")]
+    strict: bool,
+
+    /// Skip duplicate occurrences (same refName, start, and strand) as rejected records, keeping the first one
+    /// and its src index; implied by --permissive
+    #[clap(long)]
+    dedup_occ: bool,
+
+    /// Handle occurrences whose extended target regions overlap, which count the same positions more than once:
+    /// `count` adds an overlap_count column, and `collapse` keeps only the left-most occurrence of each cluster
+    /// of overlapping ones and rejects the others
+    #[clap(long, arg_enum, conflicts_with_all = &["window", "whole-chroms"])]
+    #[serde(serialize_with = "provenance::opt_arg_name")]
+    overlapping_occ: Option<OverlappingOcc>,
+
+    /// Write skipped input records (by --permissive or --chrom-sizes) into this TSV
+    /// with their sources, line numbers, reason codes, and original records
+    #[clap(long, alias = "rejected-occ")]
+    rejects: Option<String>,
+
+    /// Frame rate (Hz) of the sequencing run, e.g. 80 or 100, to report tMean, tErr, and modelPrediction
+    /// in milliseconds instead of frames. Ratios (ipdRatio and log2_obs_over_model) are unchanged
+    #[clap(long)]
+    frame_rate_hz: Option<f64>,
+
+    /// Reference FASTA (optionally gzip-compressed) of the kinetics data
+    #[clap(long, alias = "fasta")]
+    #[serde(skip)]
+    reference: Option<String>,
+
+    /// Add a context column of the reference sequence from k bases before to k bases after each row
+    /// on the strand of its kinetics data (ref_strand), with N beyond chromosome ends; requires --reference
+    #[clap(long, value_name = "K", requires = "reference")]
+    context: Option<usize>,
+
+    /// Write the reference sequence of each extended target region into this FASTA,
+    /// reverse-complemented for occurrences on the minus strand, with src and coordinates (1-based) in headers
+    #[clap(long, requires = "reference")]
+    fasta_output: Option<String>,
+
+    /// Annotation track given as name=path, written as a column `name` of its values at each position (long layout only).
+    /// The track is a bedGraph, optionally gzip-compressed; bigWig has to be converted with bigWigToBedGraph.
+    /// Can be given multiple times
+    #[clap(long, multiple_occurrences = true)]
+    #[serde(skip)]
+    annotate_track: Vec<String>,
+
+    /// Print generated keys, labels, and reference bases (with --reference) of rows for this number of
+    /// minus-strand occurrences to stderr, to verify the orientation of target regions
+    #[clap(long)]
+    audit_strand: Option<usize>,
+
+    /// Stop before collection if the long layout would have more rows than this,
+    /// or if --extend is longer than any chromosome of --chrom-sizes or --reference, as when --occ-width and --extend are swapped
+    #[clap(long, default_value = "1000000000")]
+    max_rows: u64,
+
+    /// Continue with a warning when the checks of --max-rows fail
+    #[clap(long)]
+    yes: bool,
+
+    /// Do not show progress bars of loading kinetics and collecting target regions,
+    /// which are shown only when stderr is a terminal
+    #[clap(long)]
+    quiet: bool,
+
+    /// Read the occ file and inspect sizes and dataset shapes of kinetics inputs, print the numbers of target regions
+    /// and output rows with estimates of the peak memory and the output size, and exit without collecting kinetics
+    #[clap(long, conflicts_with_all = &["window", "whole-chroms"])]
+    dry_run: bool,
+
+    /// Write a JSON manifest listing all produced files with their row counts and SHA-256 checksums
+    #[clap(long)]
+    manifest: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run both the CSV and HDF5 backends on the same inputs and compare their outputs (for development)
+    CompareBackends(CompareBackendsArgs),
+    /// Generate synthetic kinetics of a random chromosome with modified motif occurrences
+    Simulate(SimulateArgs),
+    /// Estimate detection power of modified bases by simulation,
+    /// using the coverage distribution of a real kinetics file
+    Power(PowerArgs),
+    /// Validate the installation, including the HDF5 backend, on tiny synthetic inputs:
+    /// compare both backends, write each output format, and check row counts, labels, and strands
+    SelfTest(SelfTestArgs),
+    /// Compare native kinetics with control (e.g. WGA or PCR-amplified) kinetics in target regions of occurrences:
+    /// delta tMean, the ratio of ipdRatios, and the significance of delta tMean per position
+    Compare(CompareArgs),
+    /// Generate a control occ file of random occurrences matched to each occurrence on its chromosome and strand,
+    /// optionally near it (--max-distance) and with a similar GC fraction (--gc-tolerance)
+    ShuffleOcc(ShuffleOccArgs),
+    /// deepTools-style scale-regions mode: average a kinetics field of variable-length regions (e.g. gene bodies)
+    /// in a fixed number of bins, with flanks of single bases in absolute coordinates, on both relative strands
+    ScaleRegions(ScaleRegionsArgs),
+    /// Check the occ file (coordinates, strands, duplicates), the kinetics schema and ordering, and chromosome names
+    /// shared by them, printing a JSON report without producing output
+    Validate(ValidateArgs),
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("input").required(true).args(&["kinetics", "kinetics-hdf5"])))]
+struct ValidateArgs {
+    /// File listing positions of motif occurrences or target bases (same format as the main command)
+    #[clap(long)]
+    occ: String,
+
+    /// Kinetics CSV file generated by PacBio `ipdSummary`
+    #[clap(long, short)]
+    kinetics: Option<String>,
+
+    /// Kinetics HDF5 file generated by PacBio `ipdSummary`
+    #[clap(long)]
+    kinetics_hdf5: Option<String>,
+
+    /// Tab-separated chromosome names and lengths (e.g. chrom.sizes or .fai) to check occurrence coordinates
+    #[clap(long)]
+    chrom_sizes: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ScaleRegionsArgs {
+    /// Kinetics CSV file generated by PacBio `ipdSummary`
+    #[clap(long, short)]
+    kinetics: String,
+
+    /// BED file of regions; the name (4th) and strand (6th) columns are used if present
+    #[clap(long)]
+    regions: String,
+
+    /// Number of bins of each region body
+    #[clap(long, default_value = "100")]
+    bins: usize,
+
+    /// Length of the flanks on each side of regions, written base by base
+    #[clap(long, default_value = "0")]
+    extend: i64,
+
+    /// Kinetics field averaged in each bin
+    #[clap(long, arg_enum, default_value = "tMean")]
+    value: ValueField,
+
+    /// Also write a CSV of the mean over regions of each position and relative strand
+    #[clap(long)]
+    profile_output: Option<String>,
+
+    /// Output CSV file with one row per region, position (flank base or body bin), and relative strand
+    #[clap(long, short)]
+    output: String,
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("lengths").required(true).args(&["chrom-sizes", "reference"])))]
+struct ShuffleOccArgs {
+    /// File listing positions of motif occurrences or target bases (same format as the main command)
+    #[clap(long)]
+    occ: String,
+
+    /// Length of the motif or target region including the start position
+    #[clap(long)]
+    occ_width: i64,
+
+    /// Tab-separated chromosome names and lengths (e.g. chrom.sizes or .fai)
+    #[clap(long)]
+    chrom_sizes: Option<String>,
+
+    /// Reference FASTA (optionally gzip-compressed) for chromosome lengths and GC fractions
+    #[clap(long)]
+    reference: Option<String>,
+
+    /// Number of control occurrences for each occurrence
+    #[clap(long, default_value = "1")]
+    n: usize,
+
+    /// Maximum distance of the start of a control occurrence from that of its original occurrence
+    #[clap(long)]
+    max_distance: Option<i64>,
+
+    /// Maximum absolute difference of GC fractions of a control occurrence and its original occurrence; requires --reference
+    #[clap(long, requires = "reference")]
+    gc_tolerance: Option<f32>,
+
+    /// Length added to each end of occurrences for their GC fractions
+    #[clap(long, default_value = "0")]
+    gc_flank: i64,
+
+    /// Number of random draws for each control occurrence before giving up on it
+    #[clap(long, default_value = "1000")]
+    max_attempts: usize,
+
+    /// Seed of the random number generator
+    #[clap(long, default_value = "1")]
+    seed: u64,
+
+    /// Output occ file of control occurrences
+    #[clap(long, short)]
+    output: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct CompareArgs {
+    /// Kinetics CSV file of native DNA generated by PacBio `ipdSummary`
+    #[clap(long)]
+    native: String,
+
+    /// Kinetics CSV file of control DNA generated by PacBio `ipdSummary`
+    #[clap(long)]
+    control: String,
+
+    /// File listing positions of motif occurrences or target bases (same format as the main command)
+    #[clap(long)]
+    occ: String,
+
+    /// Length of the motif or target region including the start position
+    #[clap(long)]
+    occ_width: i64,
+
+    /// Length of an extended region for each end of a target region
+    #[clap(long)]
+    extend: i64,
+
+    /// Output CSV file in the long layout
+    #[clap(long, short)]
+    output: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct SelfTestArgs {
+    /// Keep the temporary directory of synthetic inputs and outputs
+    #[clap(long)]
+    keep: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct CompareBackendsArgs {
+    /// Kinetics CSV file generated by PacBio `ipdSummary`
+    #[clap(long, short)]
+    kinetics: String,
+
+    /// Kinetics HDF5 (.h5) file generated by PacBio `ipdSummary` for the same data
+    #[clap(long, short = 'H')]
+    kinetics_hdf5: String,
+
+    /// File listing positions of motif occurrences or target bases (same format as the main command)
+    #[clap(long)]
+    occ: String,
+
+    /// Length of the motif or target region including the start position
+    #[clap(long)]
+    occ_width: i64,
+
+    /// Length of an extended region for each end of a target region
+    #[clap(long)]
+    extend: i64,
+
+    /// Allowed relative difference between numeric fields
+    #[clap(long, default_value = "1e-6")]
+    tolerance: f64,
+
+    /// Maximum number of differing records to report
+    #[clap(long, default_value = "10")]
+    max_reported: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct SimulateArgs {
+    /// Output kinetics CSV in the format of PacBio `ipdSummary`
+    #[clap(long, short)]
+    output: String,
+
+    /// Output file listing motif occurrences with a modified base (occ format)
+    #[clap(long)]
+    occ_output: String,
+
+    /// Output FASTA of the simulated chromosome
+    #[clap(long)]
+    fasta_output: Option<String>,
+
+    #[clap(long, default_value = "chrSim")]
+    chrom_name: String,
+
+    #[clap(long, default_value = "100000")]
+    chrom_length: usize,
+
+    /// Mean coverage of each base and strand (Poisson-distributed)
+    #[clap(long, default_value = "30")]
+    coverage: f64,
+
+    /// Standard deviation of log-transformed per-read IPDs
+    #[clap(long, default_value = "0.5")]
+    ipd_sigma: f64,
+
+    /// Fold change of the mean IPD at modified bases
+    #[clap(long, default_value = "3.0")]
+    effect_size: f64,
+
+    /// Number of motif occurrences with a modified base
+    #[clap(long, default_value = "100")]
+    n_modified: usize,
+
+    /// Length of a motif occurrence
+    #[clap(long, default_value = "4")]
+    motif_width: usize,
+
+    /// 0-based offset of the modified base in a motif, relative to the motif strand
+    #[clap(long, default_value = "1")]
+    modified_offset: usize,
+
+    /// TSV of k-mers (odd length, centered at a base) and their expected IPDs without header.
+    /// If omitted, a random model is generated
+    #[clap(long)]
+    context_model: Option<String>,
+
+    /// Half width of k-mers in a generated random context model (k = 2 * half width + 1)
+    #[clap(long, default_value = "2")]
+    context_half_width: usize,
+
+    /// Seed of the random number generator
+    #[clap(long, default_value = "1")]
+    seed: u64,
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(
+        ArgGroup::new("inputs").required(true).args(&["kinetics", "kinetics-hdf5"]),
+        ))]
+struct PowerArgs {
+    /// Kinetics CSV file generated by PacBio `ipdSummary`, from which coverages are sampled
+    #[clap(long, short)]
+    kinetics: Option<String>,
+
+    /// Kinetics HDF5 (.h5) file generated by PacBio `ipdSummary`, from which coverages are sampled
+    #[clap(long, short = 'H')]
+    kinetics_hdf5: Option<String>,
+
+    /// Fold change of the mean IPD at modified bases
+    #[clap(long)]
+    effect_size: f64,
+
+    /// Standard deviation of log-transformed per-read IPDs
+    #[clap(long, default_value = "0.5")]
+    ipd_sigma: f64,
+
+    /// Number of modified motif occurrences
+    #[clap(long)]
+    n_occ: usize,
+
+    /// Minimum score for a modified base to be detected individually
+    #[clap(long, default_value = "20")]
+    score_threshold: u32,
+
+    /// Significance level of the group-level comparison against unmodified occurrences
+    #[clap(long, default_value = "0.05")]
+    alpha: f64,
+
+    /// Number of simulated data sets
+    #[clap(long, default_value = "1000")]
+    replicates: usize,
+
+    /// Maximum number of coverage values sampled from the kinetics file
+    #[clap(long, default_value = "1000000")]
+    max_coverage_samples: usize,
+
+    /// Seed of the random number generator
+    #[clap(long, default_value = "1")]
+    seed: u64,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("[ERROR] {}", e);
+        std::process::exit(e.downcast_ref::<OccError>().map_or(1, OccError::exit_code));
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = Args::parse();
+    if let Some(preset) = args.preset {
+        preset.apply(&mut args);
+    }
+    if let Some(command) = args.command.take() {
+        return match command {
+            Command::CompareBackends(a) => backend_check::compare_backends(&a.kinetics, &a.kinetics_hdf5, &a.occ, a.occ_width, a.extend, a.tolerance, a.max_reported),
+            Command::Simulate(a) => {
+                let params = simulate::SimulationParams {
+                    chrom_name: a.chrom_name,
+                    chrom_length: a.chrom_length,
+                    coverage: a.coverage,
+                    ipd_sigma: a.ipd_sigma,
+                    effect_size: a.effect_size,
+                    n_modified: a.n_modified,
+                    motif_width: a.motif_width,
+                    modified_offset: a.modified_offset,
+                    seed: a.seed,
+                };
+                simulate::simulate(&params, a.context_model.as_deref(), a.context_half_width, &a.output, &a.occ_output, a.fasta_output.as_deref())
+            },
+            Command::SelfTest(a) => self_test::self_test(a.keep),
+            Command::Compare(a) => compare::compare_kinetics(&a.native, &a.control, &a.occ, a.occ_width, a.extend, &a.output).map(|_| ()),
+            Command::ScaleRegions(a) => {
+                let params = scale::ScaleParams { bins: a.bins, extend: a.extend, value_field: a.value };
+                scale::scale_regions(&a.kinetics, &a.regions, &params, &a.output, a.profile_output.as_deref()).map(|_| ())
+            },
+            Command::ShuffleOcc(a) => {
+                let chrom_sizes = a.chrom_sizes.as_deref().map(ChromSizes::from_path).transpose()?;
+                let reference = a.reference.as_deref().map(Reference::from_path).transpose()?;
+                let params = shuffle::ShuffleParams {
+                    occ_width: a.occ_width,
+                    n_per_occ: a.n,
+                    max_distance: a.max_distance,
+                    gc_tolerance: a.gc_tolerance,
+                    gc_flank: a.gc_flank,
+                    max_attempts: a.max_attempts,
+                    seed: a.seed,
+                };
+                shuffle::shuffle_occ(&a.occ, chrom_sizes.as_ref(), reference.as_ref(), &params, &a.output).map(|_| ())
+            },
+            Command::Validate(a) => {
+                let chrom_sizes = a.chrom_sizes.as_deref().map(ChromSizes::from_path).transpose()?;
+                validate::validate(&a.occ, a.kinetics.as_deref(), a.kinetics_hdf5.as_deref(), chrom_sizes.as_ref())
+            },
+            Command::Power(a) => {
+                use rand::SeedableRng;
+                if a.n_occ == 0 || a.replicates == 0 {
+                    return Err(format!("--n-occ ({}) and --replicates ({}) must be positive", a.n_occ, a.replicates).into());
+                }
+                let mut rng = rand::rngs::StdRng::seed_from_u64(a.seed);
+                let coverages = power::coverage_distribution(a.kinetics.as_deref(), a.kinetics_hdf5.as_deref(), a.max_coverage_samples, &mut rng)?;
+                let params = power::PowerParams {
+                    effect_size: a.effect_size,
+                    ipd_sigma: a.ipd_sigma,
+                    n_occ: a.n_occ,
+                    score_threshold: a.score_threshold,
+                    alpha: a.alpha,
+                    replicates: a.replicates,
+                    seed: a.seed,
+                };
+                power::report_power(&params, &coverages);
+                Ok(())
+            },
+        };
+    }
+    // parameters of provenance, taken before fields of args are moved out
+    let parameters = provenance::parameters(&args);
+    let mut run_report = RunReport::new();
+    // the following arguments are required by clap without a subcommand
+    let windows = match (args.window, args.whole_chroms) {
+        (Some(width), _) => Some(Windows { width, step: args.step.unwrap_or(width), chromosomes: Vec::new() }),
+        (None, Some(chromosomes)) => Some(Windows::bases(chromosomes)),
+        (None, None) => None,
+    };
+    if let Some(windows) = windows.as_ref().filter(|windows| windows.width < 1 || windows.step < 1) {
+        return Err(format!("--window ({}) and --step ({}) must be positive", windows.width, windows.step).into());
+    }
+    // empty with --window or --whole-chroms
+    let occ_path = args.occ.unwrap_or_default();
+    let occ_width = windows.as_ref().map_or_else(|| args.occ_width.expect("--occ-width is required"), |windows| windows.width);
+    // an anchored target region is the anchor base
+    let (occ_width, anchor) = match args.anchor {
+        Some(anchor) => (1, Some((anchor, occ_width))),
+        None => (occ_width, None),
+    };
+    let default_extension = windows.as_ref().map(|_| 0);
+    let region_extension = args.extend_up.or(args.extend).or(default_extension).ok_or("--extend or --extend-up is required")?;
+    let region_extension_down = args.extend_down.or(args.extend).or(default_extension).ok_or("--extend or --extend-down is required")?;
+    let output_path = args.output.expect("--output is required");
+    // check if (region_extension + occ_width + region_extension_down) overflows
+    region_extension.checked_add(occ_width).ok_or(RegionOverflow::default())?.checked_add(region_extension_down).ok_or(RegionOverflow::default())?;
+    if args.fold_palindrome && region_extension != region_extension_down {
+        return Err("--fold-palindrome requires symmetric extensions".into());
+    }
+    if let Some(path) = args.multiqc_output.as_deref().filter(|path| !path.ends_with("_mqc.json") && !path.ends_with("_mqc.tsv")) {
+        return Err(format!("--multiqc-output ({}) must end with _mqc.json or _mqc.tsv to be found by MultiQC", path).into());
+    }
+    let format = args.output_format.unwrap_or_else(|| OutputFormat::from_path(&output_path));
+    let compression = args.output_compression.unwrap_or_else(|| Compression::from_path(&output_path));
+    if let Some(level) = args.compression_level {
+        compression.check_level(level)?;
+    }
+    if format == OutputFormat::Feather && compression != Compression::None {
+        return Err("Compression is not supported for Feather output".into());
+    }
+    if format == OutputFormat::Tabix && matches!(args.layout, Layout::Wide) {
+        return Err("Tabix output is only supported for the long layout".into());
+    }
+    if format == OutputFormat::Parquet && args.compression_level.is_some() {
+        return Err("--compression-level is not supported for Parquet output".into());
+    }
+    if format != OutputFormat::Csv && args.append {
+        return Err(format!("--append is not supported for {:?} output", format).into());
+    }
+    if args.split_by.is_some() && matches!(args.layout, Layout::Wide) {
+        return Err("--split-by is only supported for the long layout".into());
+    }
+    if !args.column_order.is_empty() && matches!(args.layout, Layout::Wide) {
+        return Err("--column-order is only supported for the long layout".into());
+    }
+    if args.edge_policy == Some(EdgePolicy::Clip) && matches!(args.layout, Layout::Wide) {
+        return Err("--edge-policy clip is only supported for the long layout".into());
+    }
+    if args.fill_value.is_some() && args.missing_policy != MissingPolicy::Zero {
+        return Err("--fill-value requires --missing-policy zero".into());
+    }
+    if args.missing_policy == MissingPolicy::SkipRow && matches!(args.layout, Layout::Wide) {
+        return Err("--missing-policy skip-row is only supported for the long layout".into());
+    }
+    let mut optional_columns = Vec::new();
+    if args.frac_columns {
+        optional_columns.extend(Column::FRAC);
+    }
+    if args.pw_columns {
+        optional_columns.extend(Column::PW);
+    }
+    if args.distance_column {
+        optional_columns.push(Column::Distance);
+    }
+    if args.context.is_some() {
+        optional_columns.push(Column::Context);
+    }
+    if args.occ_id {
+        optional_columns.push(Column::OccId);
+    }
+    if args.gc_content {
+        optional_columns.push(Column::GcTarget);
+    }
+    if args.gc_window {
+        optional_columns.push(Column::GcWindow);
+    }
+    if args.control_kinetics.is_some() {
+        optional_columns.push(Column::IpdRatioControl);
+    }
+    if args.zscore_background.is_some() {
+        optional_columns.push(Column::ZscoreBackground);
+    }
+    if args.unknown_strand_policy == UnknownStrand::Both {
+        optional_columns.push(Column::StrandAssumed);
+    }
+    if args.overlapping_occ == Some(OverlappingOcc::Count) {
+        optional_columns.push(Column::OverlapCount);
+    }
+    if args.edge_policy == Some(EdgePolicy::PadNa) || args.chrom_end_policy == Some(ChromEndPolicy::Clip) {
+        optional_columns.push(Column::OffContig);
+    }
+    if args.missing_column {
+        optional_columns.push(Column::IsMissing);
+    }
+    if args.log2_obs_over_model {
+        optional_columns.push(Column::Log2ObsOverModel);
+    }
+    let columns = Column::reorder(&args.column_order, &optional_columns)?;
+    if args.value.iter().enumerate().any(|(i, field)| args.value[..i].contains(field)) {
+        return Err("A field is listed more than once in --value".into());
+    }
+    if let Some(frame_rate_hz) = args.frame_rate_hz {
+        if !(frame_rate_hz.is_finite() && frame_rate_hz > 0.0) {
+            return Err(format!("--frame-rate-hz must be positive: {}", frame_rate_hz).into());
+        }
+    }
+    if let Some(p) = args.profile_quantiles.iter().find(|p| !(0.0..=1.0).contains(*p)) {
+        return Err(format!("--profile-quantiles must be between 0 and 1: {}", p).into());
+    }
+    for (name, fraction) in [("--profile-trim", args.profile_trim), ("--profile-winsorize", args.profile_winsorize)] {
+        if let Some(fraction) = fraction.filter(|f| !(0.0..0.5).contains(f)) {
+            return Err(format!("{} must be at least 0 and less than 0.5: {}", name, fraction).into());
+        }
+    }
+    if !args.annotate_track.is_empty() && matches!(args.layout, Layout::Wide) {
+        return Err("--annotate-track is only supported for the long layout".into());
+    }
+    match args.bin_size {
+        Some(_) if matches!(args.layout, Layout::Wide) => return Err("--bin-size is only supported for the long layout".into()),
+        Some(bin_size) if bin_size < 1 => return Err(format!("--bin-size must be positive: {}", bin_size).into()),
+        _ => {},
+    }
+    let annotation_tracks = args.annotate_track.iter().map(|arg| AnnotationTrack::from_arg(arg)).collect::<Result<Vec<_>, _>>()?;
+    if annotation_tracks.iter().enumerate().any(|(i, track)| annotation_tracks[..i].iter().any(|t| t.name == track.name)) {
+        return Err("An annotation track name is given more than once in --annotate-track".into());
+    }
+    let kinetics_paths = if args.kinetics.is_empty() { &args.kinetics_hdf5 } else { &args.kinetics };
+    let n_samples = if args.merge_replicates { 1 } else { kinetics_paths.len() };
+    let sample_names = match args.sample_name {
+        Some(_) if n_samples > 1 => return Err("--sample-name is for a single kinetics input; use --sample-names".into()),
+        Some(sample_name) => vec![sample_name],
+        None if n_samples > 1 && args.sample_names.is_empty() => return Err("--sample-names (or --merge-replicates) is required for multiple kinetics inputs".into()),
+        None => args.sample_names,
+    };
+    if !sample_names.is_empty() && sample_names.len() != n_samples {
+        return Err(format!("--sample-names has {} names for {} kinetics inputs", sample_names.len(), kinetics_paths.len()).into());
+    }
+    // these outputs aggregate rows without their sample
+    let pooled_outputs = [("--bedgraph-prefix", args.bedgraph_prefix.is_some()), ("--calls-bed", args.calls_bed.is_some()),
+        ("--profile-output", args.profile_output.is_some()), ("--region-summary", args.region_summary.is_some()),
+        ("--comparison-output", args.comparison_output.is_some()), ("--report", args.report.is_some())];
+    if let Some((name, _)) = pooled_outputs.iter().find(|(_, given)| *given).filter(|_| n_samples > 1) {
+        return Err(format!("{} is for a single sample; give one kinetics input or --merge-replicates", name).into());
+    }
+    let provenance = if args.provenance || args.report.is_some() || args.run_report.is_some() {
+        let mut provenance = Provenance::new();
+        let kinetics_key = if args.kinetics.is_empty() { "kinetics_hdf5" } else { "kinetics" };
+        for (i, kinetics_path) in kinetics_paths.iter().enumerate() {
+            match sample_names.get(i) {
+                _ if kinetics_paths.len() == 1 => provenance.add_input(kinetics_key, kinetics_path),
+                Some(sample_name) if n_samples > 1 => provenance.add_input(&format!("{}_{}", kinetics_key, sample_name), kinetics_path),
+                _ => provenance.add_input(&format!("{}_{}", kinetics_key, i + 1), kinetics_path),
+            }
+        }
+        if windows.is_none() {
+            provenance.add_input("occ", &occ_path);
+        }
+        for (key, path) in [("control_kinetics", &args.control_kinetics), ("control_occ", &args.control_occ), ("blacklist", &args.blacklist),
+            ("chrom_alias", &args.chrom_alias), ("chrom_sizes", &args.chrom_sizes), ("reference", &args.reference)] {
+            if let Some(path) = path {
+                provenance.add_input(key, path);
+            }
+        }
+        for arg in args.annotate_track.iter() {
+            if let Some((name, path)) = arg.split_once('=') {
+                provenance.add_input(&format!("annotation_{}", name), path);
+            }
+        }
+        for (key, value) in parameters {
+            provenance.add(&key, &value);
+        }
+        run_report.set_parameters(&provenance);
+        Some(provenance)
+    } else {
+        None
+    };
+    let output_options = OutputOptions {
+        layout: args.layout,
+        format,
+        split_by: args.split_by,
+        columns,
+        value_fields: args.value,
+        value_format: ValueFormat {
+            na_string: match args.missing_policy {
+                MissingPolicy::Na => Some(args.na_string.unwrap_or_else(|| "NA".to_string())),
+                _ => args.na_string,
+            },
+            float_precision: args.float_precision,
+        },
+        provenance: if args.provenance { provenance.clone() } else { None },
+        append: args.append,
+        sample_names,
+        relative_base: args.relative_base,
+        source_names: if args.source_columns {
+            let file_name = |path: &str| Path::new(path).file_name().map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned());
+            let kinetics_sources = kinetics_paths.iter().map(|path| file_name(path)).collect::<Vec<_>>();
+            // merged inputs are written together as a single source
+            let kinetics_sources = if args.merge_replicates { vec![kinetics_sources.join("+")] } else { kinetics_sources };
+            let occ_source = if windows.is_some() { "windows".to_string() } else { file_name(&occ_path) };
+            Some((kinetics_sources, occ_source))
+        } else {
+            None
+        },
+        compression,
+        compression_level: args.compression_level,
+        bedgraph_prefix: args.bedgraph_prefix,
+        track_value: args.track_value,
+        calls_bed: args.calls_bed,
+        call_thresholds: CallThresholds {
+            min_score: args.call_min_score,
+            min_ipd_ratio: args.call_min_ipd_ratio,
+        },
+        profile_output: args.profile_output,
+        profile_value: args.profile_value,
+        profile_quantiles: args.profile_quantiles,
+        profile_bootstrap: args.bootstrap.map(|replicates| Bootstrap { replicates, seed: args.seed }),
+        profile_weighting: args.profile_weighting,
+        profile_robust: RobustOptions { cap: args.cap_value, trim: args.profile_trim, winsorize: args.profile_winsorize },
+        comparison_output: args.comparison_output,
+        region_summary: args.region_summary,
+        background_table: args.background_n.zip(args.background_output).map(|(n, path)| BackgroundTable {
+            path,
+            n,
+            per_chromosome: args.background_per_chromosome,
+            seed: args.seed,
+        }),
+        report_output: args.report,
+        report_metadata: provenance.unwrap_or_default(),
+        multiqc_output: args.multiqc_output,
+        fasta_output: args.fasta_output,
+        annotation_names: annotation_tracks.iter().map(|track| track.name.clone()).collect(),
+        label_style: args.label_style,
+        bin_size: args.bin_size,
+        region_parts: args.region_parts,
+        extend_down: Some(region_extension_down),
+        target_strand_only: args.target_strand_only,
+        combine_strands: args.combine_strands,
+        fold_palindrome: args.fold_palindrome,
+    };
+    output_options.region_spec(occ_width, region_extension).check_parts()?;
+    if !args.force && !args.dry_run {
+        let existing = planned_outputs(&output_options, &output_path, args.manifest.as_deref())
+            .into_iter().chain(args.rejects.iter().map(PathBuf::from)).chain(args.run_report.iter().map(PathBuf::from)).filter(|p| p.exists()).map(|p| p.display().to_string()).collect::<Vec<_>>();
+        if !existing.is_empty() {
+            return Err(format!("Output files already exist (use --force to overwrite): {}", existing.join(", ")).into());
+        }
+    }
+    let mut input_options = InputOptions {
+        chrom_sizes: args.chrom_sizes.as_deref().map(ChromSizes::from_path).transpose()?,
+        chrom_end_policy: args.chrom_end_policy,
+        edge_policy: args.edge_policy,
+        chrom_filter: ChromFilter::new(args.chroms, args.exclude_chroms),
+        chrom_alias: ChromAlias::new(args.chrom_alias.as_deref().map(ChromAlias::read_aliases).transpose()?.unwrap_or_default(), args.chr_prefix),
+        blacklist: match args.blacklist.as_deref() {
+            Some(path) => {
+                let blacklist = Blacklist::from_path(path)?;
+                eprintln!("[INFO] Read {} blacklisted intervals from {}", blacklist.n_intervals(), path);
+                Some(blacklist)
+            },
+            None => None,
+        },
+        permissive: args.permissive,
+        strict: args.strict,
+        skip_bad_kinetics: args.skip_bad_kinetics_rows,
+        dedup_occ: args.dedup_occ,
+        overlapping_occ: args.overlapping_occ,
+        rejects_path: args.rejects,
+        frame_rate_hz: args.frame_rate_hz,
+        reference: args.reference.as_deref().map(Reference::from_path).transpose()?,
+        annotation_tracks,
+        audit_strand: args.audit_strand,
+        nonfinite_frac: args.nonfinite_frac,
+        unknown_strand: args.unknown_strand_policy,
+        occ_coords: args.occ_coords,
+        missing_chrom: args.missing_chrom_policy,
+        missing_policy: args.missing_policy,
+        fill_value: args.fill_value,
+        min_coverage: args.min_coverage,
+        min_score: args.min_score,
+        frac_range: (args.min_frac.is_some() || args.max_frac.is_some())
+            .then(|| (args.min_frac.unwrap_or(f32::NEG_INFINITY), args.max_frac.unwrap_or(f32::INFINITY))),
+        min_region_coverage: args.min_region_coverage.map(|min_coverage| (min_coverage, args.region_coverage_stat)),
+        context: args.context,
+        gc_content: args.gc_content || args.gc_window,
+        max_rows: args.max_rows,
+        confirmed: args.yes,
+        progress: !args.quiet && std::io::stderr().is_terminal(),
+        control_occ_path: args.control_occ,
+        merge_replicates: args.merge_replicates,
+        max_occ: args.max_occ,
+        anchor,
+        windows,
+        background: args.zscore_background.map(|n| BackgroundOptions {
+            n,
+            value_field: args.background_value,
+            by_base: args.background_by_base,
+            seed: args.seed,
+        }),
+        occ_extension_down: region_extension_down,
+        strand_relative_extension: args.strand_relative_extension,
+        ..InputOptions::new(&occ_path, occ_width, region_extension)
+    };
+    if args.dry_run {
+        return dry_run::dry_run(kinetics_paths, args.kinetics.is_empty(), &input_options, &output_options);
+    }
+    if let Some(control_kinetics) = args.control_kinetics.as_deref() {
+        input_options.control_kinetics = Some(input_options.read_control_kinetics(control_kinetics)?);
+    }
+    let mut manifest = Manifest::new();
+    if !args.kinetics.is_empty() {
+        collect_ipd_summary_in_merged_occ(&args.kinetics, &input_options, &output_options, &output_path, &mut manifest, &mut run_report)?;
+    } else {
+        collect_hdf5_ipd_summary_in_merged_occ(&args.kinetics_hdf5, &input_options, &output_options, &output_path, &mut manifest, &mut run_report)?;
+    }
+    if let Some(path) = args.run_report.as_deref() {
+        run_report.write(path, &mut manifest)?;
+    }
+    if let Some(manifest_path) = args.manifest {
+        manifest.write(manifest_path)?;
     }
     Ok(())
 }
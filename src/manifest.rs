@@ -0,0 +1,75 @@
+//! JSON manifest enumerating the files produced by a run
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::output::write_atomically;
+
+/// A produced file
+#[derive(Debug, Serialize)]
+pub(crate) struct ManifestEntry {
+    path: String,
+    /// Role of the file in this run, e.g. "kinetics"
+    kind: String,
+    /// Number of data rows (excluding header lines)
+    rows: u64,
+    /// File size in bytes
+    bytes: u64,
+    /// Hex-encoded SHA-256 of the file content as written (after compression)
+    sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Manifest {
+    tool: String,
+    version: String,
+    files: Vec<ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Manifest {
+    pub(crate) fn new() -> Self {
+        Self {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Register a produced file.
+    /// Its size and checksum are computed from the file on disk when the manifest is written.
+    pub(crate) fn add_file<P: AsRef<Path>>(&mut self, path: P, kind: &str, rows: u64) {
+        self.files.push(ManifestEntry {
+            path: path.as_ref().to_string_lossy().into_owned(),
+            kind: kind.to_string(),
+            rows,
+            bytes: 0,
+            sha256: String::new(),
+        });
+    }
+
+    fn update_checksums(&mut self) -> io::Result<()> {
+        for entry in self.files.iter_mut() {
+            let mut file = File::open(&entry.path)?;
+            let mut hasher = Sha256::new();
+            entry.bytes = io::copy(&mut file, &mut hasher)?;
+            entry.sha256 = format!("{:x}", hasher.finalize());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
+        self.update_checksums()?;
+        let json = serde_json::to_string_pretty(self)?;
+        write_atomically(path, |output| writeln!(output, "{}", json))?;
+        Ok(())
+    }
+}
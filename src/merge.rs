@@ -0,0 +1,64 @@
+//! Pooling of replicate kinetics (e.g. SMRT cells of the same sample) into one dataset per position
+
+use crate::IpdSummaryValue;
+
+/// Coverage-weighted mean of values present in some replicates; None if none has the value
+fn weighted_mean<F: Fn(&IpdSummaryValue) -> Option<f32>>(values: &[&IpdSummaryValue], weights: &[f64], field: F) -> Option<f32> {
+    let (sum, sum_weights) = values.iter().zip(weights.iter())
+        .filter_map(|(value, &weight)| field(value).map(|v| (v as f64 * weight, weight)))
+        .fold((0.0, 0.0), |(sum, sum_weights), (v, weight)| (sum + v, sum_weights + weight));
+    if sum_weights > 0.0 { Some((sum / sum_weights) as f32) } else { None }
+}
+
+/// Merge values of a position in replicates; None if no replicate has data there.
+/// coverage is summed, and tMean and the other fields are coverage-weighted means,
+/// except tErr, the standard error of the weighted mean sqrt(sum(c^2 tErr^2)) / sum(c),
+/// and ipdRatio, recomputed as tMean over modelPrediction.
+/// Replicates are weighted equally if all of them have no coverage.
+pub(crate) fn merge_values(values: &[&IpdSummaryValue]) -> Option<IpdSummaryValue> {
+    match values {
+        [] => return None,
+        [value] => return Some((*value).clone()),
+        _ => {},
+    }
+    let coverage = values.iter().map(|value| value.coverage).sum::<u32>();
+    let weights = values.iter().map(|value| if coverage > 0 { value.coverage as f64 } else { 1.0 }).collect::<Vec<_>>();
+    let sum_weights = weights.iter().sum::<f64>();
+    let mean = |field: fn(&IpdSummaryValue) -> f32| weighted_mean(values, &weights, |value| Some(field(value))).expect("positive weights");
+    let t_mean = mean(|value| value.tMean);
+    let model_prediction = mean(|value| value.modelPrediction);
+    let t_err = values.iter().zip(weights.iter()).map(|(value, weight)| (weight * value.tErr as f64).powi(2)).sum::<f64>().sqrt() / sum_weights;
+    Some(IpdSummaryValue {
+        base: values.iter().find_map(|value| value.base),
+        score: mean(|value| value.score as f32).round() as u32,
+        tMean: t_mean,
+        tErr: t_err as f32,
+        modelPrediction: model_prediction,
+        ipdRatio: if model_prediction > 0.0 { t_mean / model_prediction } else { mean(|value| value.ipdRatio) },
+        coverage,
+        frac: weighted_mean(values, &weights, |value| value.frac),
+        fracLow: weighted_mean(values, &weights, |value| value.fracLow),
+        fracUp: weighted_mean(values, &weights, |value| value.fracUp),
+        pwMean: weighted_mean(values, &weights, |value| value.pwMean),
+        pwErr: weighted_mean(values, &weights, |value| value.pwErr),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_values1() {
+        let a = IpdSummaryValue { tMean: 1.0, tErr: 0.4, modelPrediction: 1.0, coverage: 10, frac: Some(0.5), ..IpdSummaryValue::default() };
+        let b = IpdSummaryValue { tMean: 4.0, tErr: 0.2, modelPrediction: 1.0, coverage: 30, ..IpdSummaryValue::default() };
+        let merged = merge_values(&[&a, &b]).unwrap();
+        assert_eq!(merged.coverage, 40);
+        assert_eq!(merged.tMean, 3.25);
+        assert_eq!(merged.ipdRatio, 3.25);
+        assert!((merged.tErr - 0.1f32.hypot(0.15)).abs() < 1e-6);
+        assert_eq!(merged.frac, Some(0.5));
+        assert!(merged.pwMean.is_none());
+        assert!(merge_values(&[]).is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//! Summary of a run as MultiQC custom content (`*_mqc.json` or `*_mqc.tsv`), shown in MultiQC reports of pipelines
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::io::Write;
+use clap::ArgEnum;
+use serde_json::json;
+use crate::{TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+
+/// Statistics of a sample accumulated over rows of the long layout
+#[derive(Debug, Default)]
+struct SampleStats {
+    regions: HashSet<i64>,
+    /// (sum, number) of present values in target regions and in flanks
+    target: (f64, u64),
+    flank: (f64, u64),
+    rows: u64,
+    missing: u64,
+    /// coverage -> number of rows with data
+    coverages: BTreeMap<u32, u64>,
+}
+
+impl SampleStats {
+    fn mean((sum, n): (f64, u64)) -> Option<f64> {
+        if n == 0 { None } else { Some(sum / n as f64) }
+    }
+
+    fn mean_coverage(&self) -> Option<f64> {
+        Self::mean(self.coverages.iter().fold((0.0, 0), |(sum, n), (&coverage, &count)| (sum + coverage as f64 * count as f64, n + count)))
+    }
+
+    /// Lower median of coverages of rows with data
+    fn median_coverage(&self) -> Option<u32> {
+        let n = self.coverages.values().sum::<u64>();
+        let mut seen = 0;
+        self.coverages.iter().find(|(_, &count)| {
+            seen += count;
+            seen * 2 >= n
+        }).map(|(&coverage, _)| coverage)
+    }
+
+    /// Metric names and values in the order of columns
+    fn metrics(&self) -> Vec<(&'static str, Option<f64>)> {
+        let target = Self::mean(self.target);
+        let flank = Self::mean(self.flank);
+        vec![
+            ("regions", Some(self.regions.len() as f64)),
+            ("target_mean", target),
+            ("flank_mean", flank),
+            ("target_flank_ratio", target.zip(flank).filter(|(_, flank)| *flank != 0.0).map(|(target, flank)| target / flank)),
+            ("mean_coverage", self.mean_coverage()),
+            ("median_coverage", self.median_coverage().map(f64::from)),
+            ("missing_fraction", if self.rows == 0 { None } else { Some(self.missing as f64 / self.rows as f64) }),
+        ]
+    }
+}
+
+pub(crate) struct MultiQc {
+    value_field: ValueField,
+    /// Names of samples by the index of kinetics inputs
+    sample_names: Vec<String>,
+    samples: BTreeMap<usize, SampleStats>,
+}
+
+impl MultiQc {
+    pub(crate) fn new(value_field: ValueField, sample_names: Vec<String>) -> Self {
+        Self { value_field, sample_names, samples: BTreeMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, row: &TargetIpdRich) {
+        let stats = self.samples.entry(row.sample).or_default();
+        stats.regions.insert(row.src);
+        stats.rows += 1;
+        if row.missing {
+            stats.missing += 1;
+            return;
+        }
+        *stats.coverages.entry(row.coverage).or_default() += 1;
+        if let Some(value) = row.value_of(self.value_field).filter(|value| value.is_finite()) {
+            let (sum, n) = if row.region.starts_with("Target") { &mut stats.target } else { &mut stats.flank };
+            *sum += value as f64;
+            *n += 1;
+        }
+    }
+
+    fn sample_name(&self, sample: usize) -> String {
+        self.sample_names.get(sample).cloned().unwrap_or_else(|| format!("sample{}", sample + 1))
+    }
+
+    fn description(&self) -> String {
+        let value_name = self.value_field.to_possible_value().expect("no hidden field").get_name();
+        format!("Mean {} in target regions and their flanks, and coverage of bases with kinetics data", value_name)
+    }
+
+    /// Write JSON for a path ending with `_mqc.json`, and TSV with a header of comment lines otherwise
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        if path.ends_with(".json") {
+            let data = self.samples.iter().map(|(&sample, stats)| {
+                let metrics = stats.metrics().into_iter().filter_map(|(name, value)| value.map(|value| (name.to_string(), json!(value))))
+                    .collect::<serde_json::Map<_, _>>();
+                (self.sample_name(sample), serde_json::Value::from(metrics))
+            }).collect::<serde_json::Map<_, _>>();
+            let content = json!({
+                "id": "regional_kinetics",
+                "section_name": "Regional kinetics",
+                "description": self.description(),
+                "plot_type": "table",
+                "pconfig": { "id": "regional_kinetics_table", "title": "Regional kinetics" },
+                "data": data,
+            });
+            let json = serde_json::to_string_pretty(&content)?;
+            write_atomically(path, |output| writeln!(output, "{}", json))?;
+        } else {
+            write_atomically(path, |output| {
+                writeln!(output, "# id: 'regional_kinetics'")?;
+                writeln!(output, "# section_name: 'Regional kinetics'")?;
+                writeln!(output, "# description: '{}'", self.description())?;
+                writeln!(output, "# plot_type: 'table'")?;
+                let names = SampleStats::default().metrics().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+                writeln!(output, "Sample\t{}", names.join("\t"))?;
+                for (&sample, stats) in self.samples.iter() {
+                    let values = stats.metrics().into_iter().map(|(_, value)| value.map_or_else(String::new, |value| value.to_string())).collect::<Vec<_>>();
+                    writeln!(output, "{}\t{}", self.sample_name(sample), values.join("\t"))?;
+                }
+                Ok(())
+            })?;
+        }
+        manifest.add_file(path, "multiqc", self.samples.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_coverage1() {
+        let stats = SampleStats { coverages: BTreeMap::from([(5, 1), (10, 2), (20, 1)]), ..Default::default() };
+        assert_eq!(stats.median_coverage(), Some(10));
+        assert_eq!(stats.mean_coverage(), Some(11.25));
+        assert_eq!(SampleStats::default().median_coverage(), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Lenient parsing of numbers in kinetics CSV, which may be padded, in exponent format, or non-finite
+//! (`nan`, `-nan`, `inf`, `NA`, ...) depending on the version of ipdSummary and post-processing tools
+
+use std::fmt;
+use serde::Deserializer;
+use serde::de::{Error, Visitor};
+
+/// Parse a float, accepting surrounding spaces, any case of nan and inf(inity) with a sign, and NA as NaN
+fn parse_f32(field: &str) -> Option<f32> {
+    let field = field.trim();
+    field.parse::<f32>().ok().or_else(|| {
+        let unsigned = field.trim_start_matches(['+', '-']);
+        let is = |name: &str| unsigned.eq_ignore_ascii_case(name);
+        if is("nan") || is("na") {
+            Some(f32::NAN)
+        } else if is("inf") || is("infinity") {
+            Some(if field.starts_with('-') { f32::NEG_INFINITY } else { f32::INFINITY })
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse an unsigned integer, also accepting integral floats such as `12.0` or `1.2e+01`
+fn parse_u32(field: &str) -> Option<u32> {
+    let field = field.trim();
+    field.parse::<u32>().ok().or_else(|| {
+        field.parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0 && v.fract() == 0.0 && *v <= u32::MAX as f64).map(|v| v as u32)
+    })
+}
+
+/// Parse a string field with `parse`, without allocating for fields borrowed or not
+struct FieldVisitor<T> {
+    parse: fn(&str) -> Option<T>,
+    expected: &'static str,
+}
+
+impl<'de, T> Visitor<'de> for FieldVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.expected)
+    }
+
+    fn visit_str<E: Error>(self, field: &str) -> Result<T, E> {
+        (self.parse)(field).ok_or_else(|| E::custom(format!("invalid {}: {:?}", self.expected, field)))
+    }
+}
+
+pub(crate) fn f32_lenient<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    deserializer.deserialize_str(FieldVisitor { parse: parse_f32, expected: "float" })
+}
+
+/// An absent value for an empty field or NA
+pub(crate) fn opt_f32_lenient<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    let parse = |field: &str| match field.trim() {
+        "" | "NA" => Some(None),
+        _ => parse_f32(field).map(Some),
+    };
+    deserializer.deserialize_str(FieldVisitor { parse, expected: "float or an empty field" })
+}
+
+pub(crate) fn u32_lenient<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    deserializer.deserialize_str(FieldVisitor { parse: parse_u32, expected: "unsigned integer" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse1() {
+        assert!(parse_f32("nan").unwrap().is_nan());
+        assert!(parse_f32("-nan").unwrap().is_nan());
+        assert!(parse_f32("NaN").unwrap().is_nan());
+        assert!(parse_f32("NA").unwrap().is_nan());
+        assert_eq!(parse_f32("inf"), Some(f32::INFINITY));
+        assert_eq!(parse_f32("-Infinity"), Some(f32::NEG_INFINITY));
+        assert_eq!(parse_f32(" 1.5e-01 "), Some(0.15));
+        assert_eq!(parse_f32("1E3"), Some(1000.0));
+        assert_eq!(parse_f32("abc"), None);
+        assert_eq!(parse_u32("12"), Some(12));
+        assert_eq!(parse_u32("1.2e+01"), Some(12));
+        assert_eq!(parse_u32("12.0"), Some(12));
+        assert_eq!(parse_u32("12.5"), None);
+        assert_eq!(parse_u32("-1"), None);
+        assert_eq!(parse_u32("nan"), None);
+    }
+
+    #[test]
+    fn ipd_summary1() {
+        let headers = csv::StringRecord::from(vec!["refName", "tpl", "strand", "base", "score", "tMean", "tErr", "modelPrediction", "ipdRatio", "coverage", "frac", "fracLow", "fracUp"]);
+        let record = csv::StringRecord::from(vec!["chr1", "5", "0", "A", "3.0e+01", "nan", " 1.5E-01", "-inf", "Infinity", "12", "", "NA", "-nan"]);
+        let summary = record.deserialize::<crate::IpdSummary>(Some(&headers)).unwrap();
+        assert_eq!((summary.score, summary.coverage, summary.tErr), (30, 12, 0.15));
+        assert!(summary.tMean.is_nan() && summary.modelPrediction == f32::NEG_INFINITY && summary.ipdRatio == f32::INFINITY);
+        assert_eq!((summary.frac, summary.fracLow), (None, None));
+        assert!(summary.fracUp.unwrap().is_nan());
+        assert_eq!(summary.pwMean, None);
+    }
+}
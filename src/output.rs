@@ -0,0 +1,225 @@
+//! Output file writers with optional compression
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use clap::ArgEnum;
+
+/// Path of a hidden temporary file in the same directory as `path`,
+/// to be renamed to `path` after it is completely written
+pub fn temporary_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    let file_name = path.file_name().map_or_else(String::new, |f| f.to_string_lossy().into_owned());
+    path.with_file_name(format!(".{}.tmp{}", file_name, std::process::id()))
+}
+
+/// A temporary file at `temporary_path(path)`, which is removed when dropped unless it is persisted,
+/// so that failed writes do not leave temporary files behind
+pub struct TemporaryFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TemporaryFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        TemporaryFile { path: temporary_path(path), persisted: false }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rename the temporary file to `path`
+    pub fn persist<P: AsRef<Path>>(mut self, path: P) -> io::Result<()> {
+        std::fs::rename(&self.path, path)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Write a small file atomically through a temporary file
+pub fn write_atomically<P, F>(path: P, write: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let temporary = TemporaryFile::new(&path);
+    let mut output = BufWriter::new(File::create(temporary.path())?);
+    write(&mut output)?;
+    output.flush()?;
+    drop(output);
+    temporary.persist(path)
+}
+
+/// File format of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum OutputFormat {
+    /// Comma-separated values, optionally compressed
+    Csv,
+    /// Feather v2 (Arrow IPC file) with typed columns
+    Feather,
+    /// Parquet with typed columns, compressed with the codec of --output-compression
+    Parquet,
+    /// Coordinate-sorted TSV compressed with bgzip, with a tabix index; rows are sorted in memory (up to 4 GiB)
+    Tabix,
+}
+
+impl OutputFormat {
+    /// Guess an output format from the extension of an output path
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("feather") | Some("arrow") => OutputFormat::Feather,
+            Some("parquet") => OutputFormat::Parquet,
+            Some("bgz") => OutputFormat::Tabix,
+            _ => OutputFormat::Csv,
+        }
+    }
+}
+
+/// Compression format of an output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Guess a compression format from the extension of an output path
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Check if a compression level is acceptable for this format
+    pub fn check_level(&self, level: i32) -> Result<(), String> {
+        let range = match self {
+            Compression::None => return Err("Compression level is specified for uncompressed output".to_string()),
+            Compression::Gzip => 0..=9,
+            Compression::Zstd => 1..=22,
+        };
+        if range.contains(&level) {
+            Ok(())
+        } else {
+            Err(format!("Compression level ({}) is out of the range {}..={} for {:?}", level, range.start(), range.end(), self))
+        }
+    }
+}
+
+/// A buffered file writer with optional compression
+pub enum OutputWriter {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl OutputWriter {
+    /// Create a file; `level` of None means the default level of each format.
+    /// With `append`, data is appended to an existing file; concatenated gzip members
+    /// and zstd frames are still valid compressed streams.
+    pub fn create<P: AsRef<Path>>(path: P, compression: Compression, level: Option<i32>, append: bool) -> io::Result<Self> {
+        let file = if append {
+            std::fs::OpenOptions::new().create(true).append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => OutputWriter::Plain(file),
+            Compression::Gzip => {
+                let level = level.map_or_else(flate2::Compression::default, |l| flate2::Compression::new(l as u32));
+                OutputWriter::Gzip(flate2::write::GzEncoder::new(file, level))
+            },
+            // zstd level 0 means its default level
+            Compression::Zstd => OutputWriter::Zstd(zstd::Encoder::new(file, level.unwrap_or(0))?),
+        })
+    }
+
+    /// Write the trailer of a compressed stream and flush everything into the file.
+    /// Dropping an OutputWriter without calling this may leave a truncated file.
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            OutputWriter::Plain(w) => w,
+            OutputWriter::Gzip(w) => w.finish()?,
+            OutputWriter::Zstd(w) => w.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(w) => w.write(buf),
+            OutputWriter::Gzip(w) => w.write(buf),
+            OutputWriter::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush(),
+            OutputWriter::Gzip(w) => w.flush(),
+            OutputWriter::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temporary_path1() {
+        let temporary = temporary_path("dir/out.csv");
+        assert_eq!(temporary.parent(), Some(Path::new("dir")));
+        assert!(temporary.file_name().unwrap().to_string_lossy().starts_with(".out.csv.tmp"));
+    }
+
+    #[test]
+    fn write_atomically_error1() {
+        let file = crate::tests::temporary_file("atomic_error.txt");
+        let path = file.path();
+        let result = write_atomically(path, |output| {
+            writeln!(output, "partial")?;
+            Err(io::Error::new(io::ErrorKind::Other, "failed"))
+        });
+        assert!(result.is_err());
+        assert!(!path.exists());
+        assert!(!temporary_path(path).exists());
+    }
+
+    #[test]
+    fn output_format_from_path() {
+        assert_eq!(OutputFormat::from_path("out.csv.gz"), OutputFormat::Csv);
+        assert_eq!(OutputFormat::from_path("out.feather"), OutputFormat::Feather);
+        assert_eq!(OutputFormat::from_path("out.parquet"), OutputFormat::Parquet);
+        assert_eq!(OutputFormat::from_path("out.tsv.bgz"), OutputFormat::Tabix);
+    }
+
+    #[test]
+    fn compression_from_path() {
+        assert_eq!(Compression::from_path("out.csv"), Compression::None);
+        assert_eq!(Compression::from_path("out.csv.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("out.csv.zst"), Compression::Zstd);
+    }
+
+    #[test]
+    fn compression_level_range() {
+        assert!(Compression::Gzip.check_level(9).is_ok());
+        assert!(Compression::Gzip.check_level(10).is_err());
+        assert!(Compression::Zstd.check_level(19).is_ok());
+        assert!(Compression::None.check_level(1).is_err());
+    }
+}
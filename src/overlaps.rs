@@ -0,0 +1,67 @@
+//! Occurrences whose extended target regions overlap, which count the same positions more than once in aggregates
+
+use clap::ArgEnum;
+
+/// How occurrences with overlapping extended target regions are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum OverlappingOcc {
+    /// Keep all of them with the number of other overlapping occurrences in an overlap_count column
+    Count,
+    /// Keep only the left-most occurrence of each cluster of transitively overlapping ones
+    Collapse,
+}
+
+/// Indices of 0-based half-open `intervals` (chromosome, start, end) grouped by chromosome, sorted by start
+fn by_chromosome(intervals: &[(&str, i64, i64)]) -> Vec<Vec<usize>> {
+    let mut groups: std::collections::BTreeMap<&str, Vec<usize>> = std::collections::BTreeMap::new();
+    for (k, (chr, _, _)) in intervals.iter().enumerate() {
+        groups.entry(chr).or_default().push(k);
+    }
+    groups.into_values().map(|mut indices| {
+        indices.sort_by_key(|&k| (intervals[k].1, k));
+        indices
+    }).collect()
+}
+
+/// Number of the other intervals overlapping each of `intervals`
+pub(crate) fn overlap_counts(intervals: &[(&str, i64, i64)]) -> Vec<u32> {
+    let mut counts = vec![0; intervals.len()];
+    for indices in by_chromosome(intervals) {
+        let starts = indices.iter().map(|&k| intervals[k].1).collect::<Vec<_>>();
+        let mut ends = indices.iter().map(|&k| intervals[k].2).collect::<Vec<_>>();
+        ends.sort_unstable();
+        for &k in indices.iter() {
+            let (_, start, end) = intervals[k];
+            // intervals starting before `end` except those ending at or before `start`, and itself
+            let n = starts.partition_point(|&s| s < end) - ends.partition_point(|&e| e <= start) - 1;
+            counts[k] = n as u32;
+        }
+    }
+    counts
+}
+
+/// Whether each of `intervals` is the left-most one (the first one in ties) of its cluster of overlapping intervals
+pub(crate) fn cluster_representatives(intervals: &[(&str, i64, i64)]) -> Vec<bool> {
+    let mut representatives = vec![false; intervals.len()];
+    for indices in by_chromosome(intervals) {
+        let mut cluster_end = i64::MIN;
+        for k in indices {
+            let (_, start, end) = intervals[k];
+            representatives[k] = start >= cluster_end;
+            cluster_end = cluster_end.max(end);
+        }
+    }
+    representatives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_counts1() {
+        let intervals = [("chr1", 10, 20), ("chr1", 15, 25), ("chr1", 24, 30), ("chr1", 30, 40), ("chr2", 10, 20), ("chr1", 10, 20)];
+        assert_eq!(overlap_counts(&intervals), [2, 3, 1, 0, 0, 2]);
+        assert_eq!(cluster_representatives(&intervals), [true, false, false, true, true, false]);
+    }
+}
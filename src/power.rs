@@ -0,0 +1,151 @@
+//! Detection power estimation using simulated kinetics
+
+use std::error::Error;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::{open_without_bom, ChrKineticsHdf5, IpdSummary};
+use crate::chrom_filter::ChromFilter;
+use crate::simulate::{normal_upper_tail, simulate_stats};
+use crate::stats::mean_and_variance;
+
+/// Parameters of a power analysis
+pub(crate) struct PowerParams {
+    /// Fold change of the mean IPD at modified bases
+    pub(crate) effect_size: f64,
+    /// Standard deviation of log-transformed per-read IPDs
+    pub(crate) ipd_sigma: f64,
+    /// Number of modified occurrences (and of control occurrences for the group-level test)
+    pub(crate) n_occ: usize,
+    /// Minimum score for a modified base to be detected
+    pub(crate) score_threshold: u32,
+    /// Significance level of the group-level test
+    pub(crate) alpha: f64,
+    pub(crate) replicates: usize,
+    pub(crate) seed: u64,
+}
+
+/// Sample up to `max_samples` coverage values of covered bases by reservoir sampling
+fn sample_coverages<I: Iterator<Item = u32>, R: Rng>(coverages: I, max_samples: usize, rng: &mut R) -> Vec<u32> {
+    let mut samples = Vec::with_capacity(max_samples);
+    for (i, coverage) in coverages.filter(|c| *c > 0).enumerate() {
+        if samples.len() < max_samples {
+            samples.push(coverage);
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < max_samples {
+                samples[j] = coverage;
+            }
+        }
+    }
+    samples
+}
+
+/// Coverage distribution of a kinetics CSV (`kinetics_path`) or HDF5 (`kinetics_hdf5_path`) file.
+/// Records of a CSV file are sampled as they are read, without holding the whole file.
+pub(crate) fn coverage_distribution<R: Rng>(kinetics_path: Option<&str>, kinetics_hdf5_path: Option<&str>, max_samples: usize, rng: &mut R) -> Result<Vec<u32>, Box<dyn Error>> {
+    let coverages = if let Some(path) = kinetics_path {
+        let mut reader = csv::Reader::from_reader(open_without_bom(path).map_err(|e| format!("{}: {}", path, e))?);
+        let mut error = None;
+        let records = reader.deserialize::<IpdSummary>().map_while(|record| match record {
+            Ok(record) => Some(record.coverage),
+            Err(e) => {
+                error = Some(e);
+                None
+            },
+        });
+        let coverages = sample_coverages(records, max_samples, rng);
+        if let Some(e) = error {
+            return Err(format!("{}: {}", path, e).into());
+        }
+        coverages
+    } else if let Some(path) = kinetics_hdf5_path {
+        let datasets = ChrKineticsHdf5::kinetics_datasets_from_hdf5_path(path, &ChromFilter::default(), false)?;
+        sample_coverages(datasets.values().flat_map(|d| d.coverage.iter().copied()), max_samples, rng)
+    } else {
+        return Err("A kinetics CSV or HDF5 file is required".into());
+    };
+    if coverages.is_empty() {
+        return Err("No covered base in the kinetics input".into());
+    }
+    Ok(coverages)
+}
+
+/// Result of a power analysis
+pub(crate) struct PowerResult {
+    /// Probability that a single modified base reaches the score threshold
+    pub(crate) per_site_power: f64,
+    /// Probability that mean ipdRatio of modified occurrences is significantly higher than that of
+    /// the same number of unmodified occurrences (one-sided Welch test, normal approximation)
+    pub(crate) group_power: f64,
+}
+
+pub(crate) fn estimate_power(params: &PowerParams, coverages: &[u32]) -> PowerResult {
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let mut n_detected: u64 = 0;
+    let mut n_significant: u64 = 0;
+    for _ in 0..params.replicates {
+        let mut modified_ratios = Vec::with_capacity(params.n_occ);
+        let mut control_ratios = Vec::with_capacity(params.n_occ);
+        for _ in 0..params.n_occ {
+            let coverage = coverages[rng.gen_range(0..coverages.len())];
+            let modified = simulate_stats(&mut rng, 1.0, params.effect_size, params.ipd_sigma, coverage);
+            if modified.score >= params.score_threshold {
+                n_detected += 1;
+            }
+            modified_ratios.push(modified.t_mean);
+            let coverage = coverages[rng.gen_range(0..coverages.len())];
+            control_ratios.push(simulate_stats(&mut rng, 1.0, 1.0, params.ipd_sigma, coverage).t_mean);
+        }
+        let (modified_mean, modified_variance) = mean_and_variance(&modified_ratios);
+        let (control_mean, control_variance) = mean_and_variance(&control_ratios);
+        let standard_error = ((modified_variance + control_variance) / params.n_occ as f64).sqrt();
+        if standard_error > 0.0 && normal_upper_tail((modified_mean - control_mean) / standard_error) < params.alpha {
+            n_significant += 1;
+        }
+    }
+    PowerResult {
+        per_site_power: n_detected as f64 / (params.replicates * params.n_occ) as f64,
+        group_power: n_significant as f64 / params.replicates as f64,
+    }
+}
+
+/// Print a power analysis report as tab-separated key-value lines
+pub(crate) fn report_power(params: &PowerParams, coverages: &[u32]) {
+    let result = estimate_power(params, coverages);
+    let mean_coverage = coverages.iter().map(|c| *c as f64).sum::<f64>() / coverages.len() as f64;
+    println!("effect_size\t{}", params.effect_size);
+    println!("ipd_sigma\t{}", params.ipd_sigma);
+    println!("n_occ\t{}", params.n_occ);
+    println!("mean_coverage\t{:.2}", mean_coverage);
+    println!("score_threshold\t{}", params.score_threshold);
+    println!("replicates\t{}", params.replicates);
+    println!("per_site_power\t{:.4}", result.per_site_power);
+    println!("expected_detected_sites\t{:.2}", result.per_site_power * params.n_occ as f64);
+    println!("alpha\t{}", params.alpha);
+    println!("group_power\t{:.4}", result.group_power);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_increases_with_effect() {
+        let coverages = vec![30; 10];
+        let mut params = PowerParams {
+            effect_size: 1.0,
+            ipd_sigma: 0.5,
+            n_occ: 20,
+            score_threshold: 20,
+            alpha: 0.05,
+            replicates: 50,
+            seed: 1,
+        };
+        let null = estimate_power(&params, &coverages);
+        params.effect_size = 3.0;
+        let strong = estimate_power(&params, &coverages);
+        assert!(null.per_site_power < 0.1);
+        assert!(strong.per_site_power > 0.9);
+        assert!(strong.group_power > 0.9);
+    }
+}
@@ -0,0 +1,59 @@
+//! Presets of output options for downstream tools
+
+use std::path::Path;
+use clap::ArgEnum;
+use crate::{Args, Column, LabelStyle, Layout};
+use crate::track::TrackValue;
+
+/// Output options for a downstream tool.
+/// A preset only changes options left at their defaults, so options given explicitly with other values take precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum Preset {
+    /// deepTools: stranded bedGraph tracks of ipdRatio (<output stem>.plus.bedGraph and .minus.bedGraph,
+    /// to be converted into bigWig for computeMatrix) and the wide layout with signed labels
+    Deeptools,
+    /// methylKit: per-base rows led by ref_chr, ref_position, ref_strand, coverage, and frac (1-based coordinates)
+    Methylkit,
+    /// ggplot2: the long layout with signed labels and a numeric distance column
+    #[clap(name = "ggplot-long")]
+    GgplotLong,
+}
+
+impl Preset {
+    pub(crate) fn apply(&self, args: &mut Args) {
+        match self {
+            Preset::Deeptools => {
+                if args.bedgraph_prefix.is_none() {
+                    // output path without any extensions
+                    args.bedgraph_prefix = args.output.as_deref().map(|output| {
+                        let output = Path::new(output);
+                        let file_name = output.file_name().map_or_else(String::new, |f| f.to_string_lossy().into_owned());
+                        let stem = file_name.split_once('.').map_or(file_name.as_str(), |(stem, _)| stem);
+                        output.with_file_name(stem).to_string_lossy().into_owned()
+                    });
+                }
+                if matches!(args.track_value, TrackValue::TMean) {
+                    args.track_value = TrackValue::IpdRatio;
+                }
+                if matches!(args.layout, Layout::Long) {
+                    args.layout = Layout::Wide;
+                }
+                if args.label_style == LabelStyle::Part {
+                    args.label_style = LabelStyle::Signed;
+                }
+            },
+            Preset::Methylkit => {
+                if args.column_order.is_empty() {
+                    args.column_order = vec![Column::RefChr, Column::RefPosition, Column::RefStrand, Column::Coverage, Column::Frac];
+                }
+                args.frac_columns = true;
+            },
+            Preset::GgplotLong => {
+                if args.label_style == LabelStyle::Part {
+                    args.label_style = LabelStyle::Signed;
+                }
+                args.distance_column = true;
+            },
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Per-position profile of a kinetics field aggregated over all occurrences
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::Write;
+use clap::ArgEnum;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::{TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::stats::format_stat;
+
+/// Weights of values in means and standard deviations of a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum ProfileWeighting {
+    /// Every occurrence has the same weight
+    None,
+    /// Values are weighted by their coverage, so that low-coverage positions contribute less
+    Coverage,
+}
+
+/// Bootstrap of occurrences for confidence intervals of means
+#[derive(Debug, Clone)]
+pub(crate) struct Bootstrap {
+    pub(crate) replicates: usize,
+    pub(crate) seed: u64,
+}
+
+/// Robust statistics against outliers such as pausing artifacts with extremely long IPDs
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RobustOptions {
+    /// Values above this are replaced with it before aggregation
+    pub(crate) cap: Option<f32>,
+    /// Fraction of values dropped from each end for trimmed_mean
+    pub(crate) trim: Option<f64>,
+    /// Fraction of values clamped at each end for winsorized_mean
+    pub(crate) winsorize: Option<f64>,
+}
+
+/// Label and triples of an occurrence index, a value, and its weight
+type WeightedValues = (String, Vec<(usize, f32, f64)>);
+
+/// Values of each position in target regions, keyed by (relative position, relative strand)
+pub(crate) struct Profile {
+    value_field: ValueField,
+    quantiles: Vec<f64>,
+    bootstrap: Option<Bootstrap>,
+    weighting: ProfileWeighting,
+    robust: RobustOptions,
+    /// Length of target regions, whose minus-strand rows are folded onto the mirrored plus-strand positions
+    fold_length: Option<i64>,
+    /// Index of each occurrence by its src, including occurrences without kinetics data
+    occurrences: HashMap<i64, usize>,
+    values: BTreeMap<(i64, char), WeightedValues>,
+}
+
+/// Quantile of sorted values by linear interpolation between order statistics (type 7 of R's quantile)
+pub(crate) fn quantile(sorted: &[f64], p: f64) -> f64 {
+    let h = (sorted.len() - 1) as f64 * p;
+    let (lower, upper) = (h.floor() as usize, h.ceil() as usize);
+    sorted[lower] + (h - lower as f64) * (sorted[upper] - sorted[lower])
+}
+
+/// Mean of sorted values without the fraction `trim` of them at each end; None if no value remains
+fn trimmed_mean(sorted: &[f64], trim: f64) -> Option<f64> {
+    let k = (sorted.len() as f64 * trim).floor() as usize;
+    let kept = sorted.get(k..sorted.len().saturating_sub(k)).filter(|kept| !kept.is_empty())?;
+    Some(kept.iter().sum::<f64>() / kept.len() as f64)
+}
+
+/// Mean of sorted values after the fraction `winsorize` of them at each end are clamped to the nearest kept value
+fn winsorized_mean(sorted: &[f64], winsorize: f64) -> Option<f64> {
+    let n = sorted.len();
+    let k = (n as f64 * winsorize).floor() as usize;
+    if n == 0 || 2 * k >= n {
+        return None;
+    }
+    let (low, high) = (sorted[k], sorted[n - 1 - k]);
+    Some(sorted.iter().map(|v| v.clamp(low, high)).sum::<f64>() / n as f64)
+}
+
+/// Weighted mean and the standard deviation with the unbiased variance for reliability weights
+/// sum(w (x - mean)^2) / (V1 - V2 / V1), where V1 and V2 are sums of weights and squared weights
+fn weighted_mean_sd<I: Iterator<Item = (f64, f64)> + Clone>(values: I) -> (Option<f64>, Option<f64>) {
+    let (v1, v2, sum) = values.clone().fold((0.0, 0.0, 0.0), |(v1, v2, sum), (value, weight)| (v1 + weight, v2 + weight * weight, sum + weight * value));
+    if v1 <= 0.0 {
+        return (None, None);
+    }
+    let mean = sum / v1;
+    let denominator = v1 - v2 / v1;
+    let sd = if denominator > 0.0 {
+        Some((values.map(|(value, weight)| weight * (value - mean).powi(2)).sum::<f64>() / denominator).sqrt())
+    } else {
+        None
+    };
+    (Some(mean), sd)
+}
+
+impl Profile {
+    pub(crate) fn new(value_field: ValueField, quantiles: Vec<f64>, bootstrap: Option<Bootstrap>, weighting: ProfileWeighting, robust: RobustOptions) -> Self {
+        Self { value_field, quantiles, bootstrap, weighting, robust, fold_length: None, occurrences: HashMap::new(), values: BTreeMap::new() }
+    }
+
+    /// Fold positions of palindromic target regions of `length`: the row of position i on the relative minus strand
+    /// is aggregated with that of position length + 1 - i on the relative plus strand, labeled as the latter
+    pub(crate) fn folded(self, length: i64) -> Self {
+        Self { fold_length: Some(length), ..self }
+    }
+
+    /// Add a row, capping its value if requested
+    pub(crate) fn add(&mut self, row: &TargetIpdRich) {
+        let n_occurrences = self.occurrences.len();
+        let occurrence = *self.occurrences.entry(row.src).or_insert(n_occurrences);
+        let key = match self.fold_length {
+            Some(length) if row.strand == '-' => (length + 1 - row.position, '+'),
+            _ => (row.position, row.strand),
+        };
+        let (label, values) = self.values.entry(key).or_insert_with(|| (String::new(), Vec::new()));
+        // a folded position is labeled by its plus-strand row
+        if label.is_empty() && key == (row.position, row.strand) {
+            *label = row.label.clone();
+        }
+        match row.value_of(self.value_field) {
+            Some(value) if !row.missing && value.is_finite() => {
+                let weight = match self.weighting {
+                    ProfileWeighting::None => 1.0,
+                    ProfileWeighting::Coverage => row.coverage as f64,
+                };
+                let value = self.robust.cap.map_or(value, |cap| value.min(cap));
+                values.push((occurrence, value, weight));
+            },
+            _ => {},
+        }
+    }
+
+    /// Sorted (weighted) means of each position in bootstrap replicates, which resample occurrences with replacement.
+    /// A replicate without values of a position has no mean for it.
+    fn bootstrap_means(&self, bootstrap: &Bootstrap) -> Vec<Vec<f64>> {
+        let mut rng = StdRng::seed_from_u64(bootstrap.seed);
+        let n_occurrences = self.occurrences.len();
+        let mut weights = vec![0u32; n_occurrences];
+        let mut means = vec![Vec::with_capacity(bootstrap.replicates); self.values.len()];
+        for _ in 0..bootstrap.replicates {
+            weights.iter_mut().for_each(|w| *w = 0);
+            for _ in 0..n_occurrences {
+                weights[rng.gen_range(0..n_occurrences)] += 1;
+            }
+            for ((_, values), replicate_means) in self.values.values().zip(means.iter_mut()) {
+                let (sum, sum_weights) = values.iter().fold((0.0, 0.0), |(sum, sum_weights), &(i, value, weight)| {
+                    let weight = weights[i] as f64 * weight;
+                    (sum + weight * value as f64, sum_weights + weight)
+                });
+                if sum_weights > 0.0 {
+                    replicate_means.push(sum / sum_weights);
+                }
+            }
+        }
+        means.iter_mut().for_each(|m| m.sort_by(|a, b| a.partial_cmp(b).expect("finite values")));
+        means
+    }
+
+    /// Write a CSV with columns label, strand, n, mean, median, sd, and q<percent> for each quantile,
+    /// followed by trimmed_mean and winsorized_mean if requested
+    /// and mean_ci_low and mean_ci_high, the 95% percentile bootstrap interval of the mean, if bootstrapped.
+    /// mean and sd are weighted by `weighting`, where sd is the unbiased estimate for reliability weights
+    /// (the sample standard deviation without weights); the other statistics are not weighted
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        let quantile_names = self.quantiles.iter().map(|p| format!(",q{}", p * 100.0)).collect::<String>();
+        let robust_names = [(self.robust.trim, ",trimmed_mean"), (self.robust.winsorize, ",winsorized_mean")].iter()
+            .filter_map(|(fraction, name)| fraction.map(|_| *name)).collect::<String>();
+        let ci_names = if self.bootstrap.is_some() { ",mean_ci_low,mean_ci_high" } else { "" };
+        let bootstrap_means = self.bootstrap.as_ref().map(|bootstrap| self.bootstrap_means(bootstrap));
+        write_atomically(path, |output| {
+            writeln!(output, "label,strand,n,mean,median,sd{}{}{}", quantile_names, robust_names, ci_names)?;
+            for (i, ((_, strand), (label, values))) in self.values.iter().enumerate() {
+                let mut sorted = values.iter().map(|&(_, value, _)| value as f64).collect::<Vec<_>>();
+                sorted.sort_by(|a, b| a.partial_cmp(b).expect("finite values"));
+                let n = sorted.len();
+                let median = format_stat((n > 0).then(|| quantile(&sorted, 0.5)));
+                let (mean, sd) = weighted_mean_sd(values.iter().map(|&(_, value, weight)| (value as f64, weight)));
+                let (mean, sd) = (format_stat(mean), format_stat(sd));
+                let quantile_values = self.quantiles.iter()
+                    .map(|&p| format!(",{}", format_stat((n > 0).then(|| quantile(&sorted, p)))))
+                    .collect::<String>();
+                let robust_values = [self.robust.trim.map(|trim| trimmed_mean(&sorted, trim)), self.robust.winsorize.map(|w| winsorized_mean(&sorted, w))]
+                    .into_iter().flatten().map(|mean| format!(",{}", format_stat(mean)))
+                    .collect::<String>();
+                let ci = bootstrap_means.as_ref().map_or_else(String::new, |means| {
+                    let means = &means[i];
+                    let bound = |p| format_stat((!means.is_empty()).then(|| quantile(means, p)));
+                    format!(",{},{}", bound(0.025), bound(0.975))
+                });
+                writeln!(output, "{},{},{},{},{},{}{}{}{}", label, strand, n, mean, median, sd, quantile_values, robust_values, ci)?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(path, "profile", self.values.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile1() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(quantile(&sorted, 0.0), 1.0);
+        assert_eq!(quantile(&sorted, 0.5), 2.5);
+        assert_eq!(quantile(&sorted, 0.25), 1.75);
+        assert_eq!(quantile(&sorted, 1.0), 4.0);
+        assert_eq!(quantile(&[5.0], 0.9), 5.0);
+    }
+
+    #[test]
+    fn robust_means1() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 100.0];
+        assert_eq!(trimmed_mean(&sorted, 0.2), Some(3.0));
+        assert_eq!(trimmed_mean(&sorted, 0.0), Some(22.0));
+        assert_eq!(winsorized_mean(&sorted, 0.2), Some(3.0));
+        assert_eq!(trimmed_mean(&[1.0, 2.0], 0.5), None);
+        assert_eq!(winsorized_mean(&[], 0.1), None);
+    }
+
+    #[test]
+    fn weighted_mean_sd1() {
+        let (mean, sd) = weighted_mean_sd([(1.0, 1.0), (3.0, 1.0)].into_iter());
+        assert_eq!(mean, Some(2.0));
+        assert!((sd.unwrap() - 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(weighted_mean_sd([(1.0, 3.0), (5.0, 1.0)].into_iter()).0, Some(2.0));
+        assert_eq!(weighted_mean_sd([(1.0, 2.0)].into_iter()), (Some(1.0), None));
+        assert_eq!(weighted_mean_sd([(1.0, 0.0)].into_iter()), (None, None));
+    }
+
+    #[test]
+    fn folded1() {
+        use crate::{IpdSummaryValue, RegionSpec};
+        use crate::tests::alternating_strand_rows;
+        let mut profile = Profile::new(ValueField::TMean, Vec::new(), None, ProfileWeighting::None, RobustOptions::default()).folded(4);
+        for row in alternating_strand_rows(8, &RegionSpec::new(4, 0), |j| Some(IpdSummaryValue { tMean: j as f32, ..IpdSummaryValue::default() })) {
+            profile.add(&row);
+        }
+        assert_eq!(profile.values.len(), 4);
+        // 1+ with 4-
+        let (label, values) = &profile.values[&(1, '+')];
+        assert_eq!(label, "m1p");
+        assert_eq!(values.iter().map(|&(_, v, _)| v).collect::<Vec<_>>(), [0.0, 7.0]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Progress bars with ETA on stderr for long phases of genome-scale runs
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+/// Minimum interval between redraws
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+/// Number of rows read between updates of the position in a tight loop
+pub(crate) const PROGRESS_ROWS: u64 = 1 << 14;
+
+/// Unit of the position of a progress bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProgressUnit {
+    Bytes,
+    Rows,
+    Regions,
+}
+
+impl ProgressUnit {
+    fn format(&self, n: u64) -> String {
+        match self {
+            ProgressUnit::Bytes => {
+                const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+                let mut value = n as f64;
+                let mut unit = 0;
+                while value >= 1024.0 && unit + 1 < UNITS.len() {
+                    value /= 1024.0;
+                    unit += 1;
+                }
+                format!("{:.1} {}", value, UNITS[unit])
+            },
+            ProgressUnit::Rows => format!("{} rows", n),
+            ProgressUnit::Regions => format!("{} regions", n),
+        }
+    }
+}
+
+/// A progress bar of a phase, drawn only if `enabled` (stderr is a terminal and --quiet is not given)
+pub(crate) struct Progress {
+    label: String,
+    total: u64,
+    unit: ProgressUnit,
+    position: u64,
+    started: Instant,
+    last_drawn: Option<Instant>,
+    enabled: bool,
+}
+
+/// Format seconds as hh:mm:ss
+fn format_duration(seconds: u64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+impl Progress {
+    pub(crate) fn new(label: &str, total: u64, unit: ProgressUnit, enabled: bool) -> Self {
+        Self { label: label.to_string(), total, unit, position: 0, started: Instant::now(), last_drawn: None, enabled }
+    }
+
+    pub(crate) fn inc(&mut self, n: u64) {
+        self.set_position(self.position + n);
+    }
+
+    pub(crate) fn set_position(&mut self, position: u64) {
+        self.position = position;
+        if self.enabled && self.last_drawn.map_or(true, |drawn| drawn.elapsed() >= REDRAW_INTERVAL) {
+            self.draw();
+        }
+    }
+
+    /// Remaining seconds estimated from the mean rate so far
+    fn eta(&self) -> Option<u64> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if self.position == 0 || self.position >= self.total || elapsed == 0.0 {
+            return None;
+        }
+        Some(((self.total - self.position) as f64 * elapsed / self.position as f64).round() as u64)
+    }
+
+    fn line(&self) -> String {
+        let fraction = if self.total == 0 { 1.0 } else { (self.position as f64 / self.total as f64).min(1.0) };
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        format!("{} [{}{}] {:3.0}% {}/{} elapsed {} ETA {}", self.label, "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), fraction * 100.0,
+            self.unit.format(self.position), self.unit.format(self.total), format_duration(self.started.elapsed().as_secs()),
+            self.eta().map_or_else(|| "--:--:--".to_string(), format_duration))
+    }
+
+    fn draw(&mut self) {
+        self.last_drawn = Some(Instant::now());
+        // rewrite the current line of the terminal
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r{}\x1b[K", self.line());
+        let _ = stderr.flush();
+    }
+
+    /// Draw the final state and end the line
+    pub(crate) fn finish(&mut self) {
+        if self.enabled {
+            self.draw();
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line1() {
+        let mut progress = Progress::new("Reading", 2048, ProgressUnit::Bytes, false);
+        progress.set_position(1024);
+        assert!(progress.line().starts_with("Reading [###############---------------]  50% 1.0 KiB/2.0 KiB elapsed 00:00:00 ETA "));
+        assert_eq!(format_duration(3723), "01:02:03");
+    }
+}
@@ -0,0 +1,153 @@
+//! Provenance metadata of a run embedded into outputs
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::ArgEnum;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+/// Ordered key-value pairs describing how an output was produced
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    entries: Vec<(String, String)>,
+}
+
+/// Convert days since 1970-01-01 into (year, month, day) in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Current time in ISO 8601 format (UTC)
+fn timestamp_now() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+impl Provenance {
+    /// Start with the tool version, the command line, and the current time
+    pub fn new() -> Self {
+        let command_line = std::env::args().map(|arg| {
+            if arg.contains(char::is_whitespace) { format!("'{}'", arg) } else { arg }
+        }).collect::<Vec<_>>().join(" ");
+        let mut provenance = Self::default();
+        provenance.add("tool", &format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        provenance.add("command_line", &command_line);
+        provenance.add("timestamp", &timestamp_now());
+        provenance
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) {
+        self.entries.push((key.to_string(), value.to_string()));
+    }
+
+    /// Add an input file path and its size in bytes
+    pub fn add_input<P: AsRef<Path>>(&mut self, key: &str, path: P) {
+        let path = path.as_ref();
+        let size = std::fs::metadata(path).map_or_else(|_| "unknown".to_string(), |m| m.len().to_string());
+        self.add(key, &path.to_string_lossy());
+        self.add(&format!("{}_bytes", key), &size);
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// Lines starting with '#' to be prepended to text outputs
+    pub fn comment_lines(&self) -> String {
+        self.entries.iter().map(|(key, value)| format!("# {}: {}\n", key, value)).collect()
+    }
+
+    /// Key-value metadata for binary outputs
+    pub fn to_metadata(&self) -> HashMap<String, String> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+/// Serialize an `ArgEnum` value by its name on the command line
+pub(crate) fn arg_name<T: ArgEnum, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(value.to_possible_value().expect("no hidden value").get_name())
+}
+
+pub(crate) fn opt_arg_name<T: ArgEnum, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => arg_name(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn arg_names<T: ArgEnum, S: Serializer>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|value| value.to_possible_value().expect("no hidden value").get_name()))
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Fields of serialized `parameters` as key-value pairs, leaving out unset, false and empty fields;
+/// values of a list are joined with ','
+pub(crate) fn parameters<T: Serialize>(parameters: &T) -> Vec<(String, String)> {
+    // parsed back from text, where floats keep their shortest representation
+    let json = serde_json::to_string(parameters).expect("parameters are serializable");
+    let fields: Map<String, Value> = serde_json::from_str(&json).expect("parameters are serialized into an object");
+    fields.into_iter().filter_map(|(key, value)| {
+        let value = match value {
+            Value::Null | Value::Bool(false) => return None,
+            Value::Array(values) if values.is_empty() => return None,
+            Value::Array(values) => values.iter().map(format_value).collect::<Vec<_>>().join(","),
+            value => format_value(&value),
+        };
+        Some((key, value))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_from_days1() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19358), (2023, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn parameters1() {
+        #[derive(Serialize)]
+        struct Parameters {
+            #[serde(serialize_with = "arg_name")]
+            format: crate::output::OutputFormat,
+            #[serde(serialize_with = "opt_arg_name")]
+            compression: Option<crate::output::Compression>,
+            chroms: Vec<String>,
+            min_frac: Option<f32>,
+            strict: bool,
+            force: bool,
+        }
+        let parameters = parameters(&Parameters {
+            format: crate::output::OutputFormat::Parquet,
+            compression: None,
+            chroms: vec!["chr1".to_string(), "chr2".to_string()],
+            min_frac: Some(0.1),
+            strict: true,
+            force: false,
+        });
+        let expected = [("chroms", "chr1,chr2"), ("format", "parquet"), ("min_frac", "0.1"), ("strict", "true")];
+        assert_eq!(parameters, expected.map(|(key, value)| (key.to_string(), value.to_string())));
+    }
+}
@@ -0,0 +1,115 @@
+//! Reference sequences read from FASTA
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use flate2::read::MultiGzDecoder;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Reference {
+    sequences: HashMap<String, Vec<u8>>,
+}
+
+/// Complement of a base, keeping its case; other characters are kept as is
+pub(crate) fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b => b,
+    }
+}
+
+impl Reference {
+    /// Read a FASTA file, gzip-compressed if the path ends with .gz.
+    /// The name of a sequence is the first word of its header line.
+    pub(crate) fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let input: Box<dyn Read> = if path.ends_with(".gz") { Box::new(MultiGzDecoder::new(file)) } else { Box::new(file) };
+        let mut sequences = HashMap::new();
+        let mut name: Option<String> = None;
+        let mut sequence = Vec::new();
+        for line in BufReader::new(input).lines() {
+            let line = line?;
+            if let Some(header) = line.strip_prefix('>') {
+                if let Some(name) = name.take() {
+                    sequences.insert(name, std::mem::take(&mut sequence));
+                }
+                name = Some(header.split_whitespace().next().unwrap_or_default().to_string());
+            } else if name.is_some() {
+                sequence.extend_from_slice(line.trim_end().as_bytes());
+            } else if !line.trim().is_empty() {
+                return Err(format!("{}: sequence before the first header line", path).into());
+            }
+        }
+        if let Some(name) = name {
+            sequences.insert(name, sequence);
+        }
+        Ok(Self { sequences })
+    }
+
+    /// Length of a sequence, if in the reference
+    pub(crate) fn length(&self, chr: &str) -> Option<i64> {
+        self.sequences.get(chr).map(|sequence| sequence.len() as i64)
+    }
+
+    /// Length of the longest sequence
+    pub(crate) fn max_length(&self) -> Option<i64> {
+        self.sequences.values().map(|sequence| sequence.len() as i64).max()
+    }
+
+    /// Fraction of G and C among A, C, G, and T in a 0-based half-open interval [start, end) regardless of case;
+    /// None if the interval has none of them or the chromosome is not in the reference
+    pub(crate) fn gc_fraction(&self, chr: &str, start: i64, end: i64) -> Option<f32> {
+        let sequence = self.sequence(chr, start, end, '+')?;
+        let (mut n_gc, mut n_acgt) = (0, 0);
+        for base in sequence.iter().map(|b| b.to_ascii_uppercase()) {
+            match base {
+                b'G' | b'C' => {
+                    n_gc += 1;
+                    n_acgt += 1;
+                },
+                b'A' | b'T' => n_acgt += 1,
+                _ => {},
+            }
+        }
+        if n_acgt == 0 { None } else { Some(n_gc as f32 / n_acgt as f32) }
+    }
+
+    /// Sequence of a 0-based half-open interval [start, end), reverse-complemented if `strand` is '-'.
+    /// Bases outside the chromosome are N; None if the chromosome is not in the reference.
+    pub(crate) fn sequence(&self, chr: &str, start: i64, end: i64, strand: char) -> Option<Vec<u8>> {
+        let chr_sequence = self.sequences.get(chr)?;
+        let mut sequence = (start..end).map(|p| {
+            if p < 0 { b'N' } else { *chr_sequence.get(p as usize).unwrap_or(&b'N') }
+        }).collect::<Vec<_>>();
+        if strand == '-' {
+            sequence.reverse();
+            sequence.iter_mut().for_each(|b| *b = complement(*b));
+        }
+        Some(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence1() {
+        let reference = Reference { sequences: [("chrI".to_string(), b"ACGTTa".to_vec())].into_iter().collect() };
+        assert_eq!(reference.sequence("chrI", 1, 4, '+').unwrap(), b"CGT");
+        assert_eq!(reference.sequence("chrI", 1, 4, '-').unwrap(), b"ACG");
+        assert_eq!(reference.sequence("chrI", -1, 2, '+').unwrap(), b"NAC");
+        assert_eq!(reference.sequence("chrI", 4, 7, '-').unwrap(), b"NtA");
+        assert!(reference.sequence("chrII", 0, 1, '+').is_none());
+        assert_eq!(reference.gc_fraction("chrI", 0, 4), Some(0.5));
+        assert_eq!(reference.gc_fraction("chrI", 6, 8), None);
+    }
+}
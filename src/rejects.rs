@@ -0,0 +1,62 @@
+//! Input records skipped in a run, with reasons
+
+use std::error::Error;
+use std::io::Write;
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+
+/// A skipped input record
+#[derive(Debug)]
+struct Reject {
+    /// Input file kind, e.g. "occ" or "kinetics"
+    source: &'static str,
+    /// 1-based line number in the input file
+    line: u64,
+    /// Reason code
+    reason: String,
+    /// Original record
+    record: String,
+}
+
+/// Skipped input records collected over a run
+#[derive(Debug, Default)]
+pub(crate) struct Rejects {
+    rejects: Vec<Reject>,
+}
+
+impl Rejects {
+    pub(crate) fn add(&mut self, source: &'static str, line: u64, reason: &str, record: String) {
+        self.rejects.push(Reject { source, line, reason: reason.to_string(), record });
+    }
+
+    /// Numbers of skipped records of each source and reason, without details of reasons in parentheses
+    pub(crate) fn counts(&self) -> std::collections::BTreeMap<(&str, &str), u64> {
+        let mut counts = std::collections::BTreeMap::new();
+        for reject in self.rejects.iter() {
+            *counts.entry((reject.source, reject.reason.split('(').next().unwrap_or_default())).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Report the numbers of skipped records of each source and reason to stderr
+    pub(crate) fn report(&self) {
+        for ((source, reason), count) in self.counts() {
+            eprintln!("[WARNING] Skipped {} {} records: {}", count, source, reason);
+        }
+    }
+
+    /// Write a TSV with columns source, line, reason, and record
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        write_atomically(path, |output| {
+            writeln!(output, "source\tline\treason\trecord")?;
+            for reject in self.rejects.iter() {
+                // keep one reject per line even if a malformed record spans lines
+                let record = reject.record.replace(['\t', '\n', '\r'], " ");
+                writeln!(output, "{}\t{}\t{}\t{}", reject.source, reject.line, reject.reason, record)?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(path, "rejects", self.rejects.len() as u64);
+        Ok(())
+    }
+}
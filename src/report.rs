@@ -0,0 +1,204 @@
+//! Self-contained HTML report of a run for sharing without downstream tools
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write;
+use clap::ArgEnum;
+use crate::{TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::provenance::Provenance;
+use crate::stats::NA;
+
+const PLOT_WIDTH: f64 = 720.0;
+const PLOT_HEIGHT: f64 = 300.0;
+const PLOT_MARGIN: f64 = 50.0;
+const CELL_HEIGHT: f64 = 16.0;
+
+/// Counts of rows and the sum of non-missing values
+#[derive(Debug, Default)]
+struct Counts {
+    n: u64,
+    n_missing: u64,
+    sum: f64,
+}
+
+impl Counts {
+    fn add(&mut self, value: Option<f32>) {
+        self.n += 1;
+        match value {
+            Some(value) => self.sum += value as f64,
+            None => self.n_missing += 1,
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        let n_present = self.n - self.n_missing;
+        if n_present == 0 { None } else { Some(self.sum / n_present as f64) }
+    }
+
+    fn missing_fraction(&self) -> f64 {
+        if self.n == 0 { 0.0 } else { self.n_missing as f64 / self.n as f64 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChromosomeStats {
+    occurrences: HashSet<i64>,
+    counts: Counts,
+    /// (relative position, relative strand) -> counts
+    positions: BTreeMap<(i64, char), Counts>,
+}
+
+/// Statistics accumulated over rows of the long layout
+pub(crate) struct Report {
+    value_field: ValueField,
+    metadata: Provenance,
+    /// (relative position, relative strand) -> (label, counts)
+    positions: BTreeMap<(i64, char), (String, Counts)>,
+    chromosomes: BTreeMap<String, ChromosomeStats>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn format_mean(mean: Option<f64>) -> String {
+    mean.map_or_else(|| NA.to_string(), |mean| format!("{:.4}", mean))
+}
+
+impl Report {
+    pub(crate) fn new(value_field: ValueField, metadata: Provenance) -> Self {
+        Self { value_field, metadata, positions: BTreeMap::new(), chromosomes: BTreeMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, row: &TargetIpdRich) {
+        let value = row.value_of(self.value_field).filter(|value| !row.missing && value.is_finite());
+        let key = (row.position, row.strand);
+        self.positions.entry(key).or_insert_with(|| (row.label.clone(), Counts::default())).1.add(value);
+        let chromosome = self.chromosomes.entry(row.ref_chr.clone()).or_default();
+        chromosome.occurrences.insert(row.src);
+        chromosome.counts.add(value);
+        chromosome.positions.entry(key).or_default().add(value);
+    }
+
+    /// Inline SVG of the mean value per position, one line per relative strand
+    fn metaprofile_svg(&self) -> String {
+        let n_positions = self.positions.keys().map(|&(position, _)| position).max().unwrap_or(0);
+        let means = self.positions.iter().filter_map(|(&key, (_, counts))| counts.mean().map(|mean| (key, mean))).collect::<Vec<_>>();
+        let (min, max) = means.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &(_, mean)| (min.min(mean), max.max(mean)));
+        let mut svg = format!(r#"<svg width="{}" height="{}" xmlns="http://www.w3.org/2000/svg">"#, PLOT_WIDTH + 2.0 * PLOT_MARGIN, PLOT_HEIGHT + 2.0 * PLOT_MARGIN);
+        if means.is_empty() {
+            svg.push_str(r#"<text x="10" y="20">No values</text></svg>"#);
+            return svg;
+        }
+        let range = if max > min { max - min } else { 1.0 };
+        let x = |position: i64| PLOT_MARGIN + if n_positions > 1 { (position - 1) as f64 / (n_positions - 1) as f64 * PLOT_WIDTH } else { PLOT_WIDTH / 2.0 };
+        let y = |mean: f64| PLOT_MARGIN + PLOT_HEIGHT - (mean - min) / range * PLOT_HEIGHT;
+        let _ = write!(svg, r#"<rect x="{m}" y="{m}" width="{}" height="{}" fill="none" stroke="black"/>"#, PLOT_WIDTH, PLOT_HEIGHT, m = PLOT_MARGIN);
+        let _ = write!(svg, r#"<text x="5" y="{}" font-size="12">{:.3}</text>"#, PLOT_MARGIN + 4.0, max);
+        let _ = write!(svg, r#"<text x="5" y="{}" font-size="12">{:.3}</text>"#, PLOT_MARGIN + PLOT_HEIGHT, min);
+        for (strand, color) in [('+', "#1f77b4"), ('-', "#d62728")] {
+            let points = means.iter().filter(|((_, s), _)| *s == strand)
+                .map(|&((position, _), mean)| format!("{:.1},{:.1}", x(position), y(mean))).collect::<Vec<_>>().join(" ");
+            let _ = write!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#, points, color);
+        }
+        // label every position when they fit, otherwise about 20 of them
+        let step = (n_positions as usize / 20).max(1);
+        for ((position, _), (label, _)) in self.positions.iter().filter(|((position, strand), _)| *strand == '+' && (*position as usize - 1) % step == 0) {
+            let _ = write!(svg, r#"<text x="{:.1}" y="{}" font-size="10" text-anchor="end" transform="rotate(-60 {:.1} {})">{}</text>"#,
+                x(*position), PLOT_MARGIN + PLOT_HEIGHT + 12.0, x(*position), PLOT_MARGIN + PLOT_HEIGHT + 12.0, escape(label));
+        }
+        svg.push_str(r##"<text x="60" y="20" fill="#1f77b4">relative + strand</text><text x="200" y="20" fill="#d62728">relative - strand</text></svg>"##);
+        svg
+    }
+
+    /// Inline SVG of the fraction of rows without data, one row of cells per chromosome and relative strand
+    fn missing_map_svg(&self) -> String {
+        let n_positions = self.positions.keys().map(|&(position, _)| position).max().unwrap_or(0).max(1);
+        let cell_width = (PLOT_WIDTH / n_positions as f64).max(1.0);
+        let label_width = 160.0;
+        let n_rows = self.chromosomes.len() * 2;
+        let mut svg = format!(r#"<svg width="{}" height="{}" xmlns="http://www.w3.org/2000/svg">"#,
+            label_width + cell_width * n_positions as f64 + 10.0, CELL_HEIGHT * n_rows as f64 + 10.0);
+        let rows = self.chromosomes.iter().flat_map(|(chr, stats)| ['+', '-'].map(|strand| (chr, stats, strand)));
+        for (i, (chr, stats, strand)) in rows.enumerate() {
+            let top = CELL_HEIGHT * i as f64;
+            let _ = write!(svg, r#"<text x="0" y="{:.1}" font-size="11">{} ({})</text>"#, top + CELL_HEIGHT - 4.0, escape(chr), strand);
+            for (&(position, _), counts) in stats.positions.iter().filter(|((_, s), _)| *s == strand) {
+                // white: no missing rows, black: all rows missing
+                let shade = (255.0 * (1.0 - counts.missing_fraction())).round() as u8;
+                let _ = write!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{}" fill="rgb({s},{s},{s})"><title>{:.3}</title></rect>"#,
+                    label_width + (position - 1) as f64 * cell_width, top, cell_width, CELL_HEIGHT, counts.missing_fraction(), s = shade);
+            }
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    fn html(&self) -> String {
+        let value_name = self.value_field.to_possible_value().expect("no hidden field").get_name();
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Regional kinetics report</title>\n");
+        html.push_str("<style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse;margin-bottom:1em}td,th{border:1px solid #ccc;padding:2px 8px;text-align:right}td:first-child,th:first-child{text-align:left}</style>\n");
+        html.push_str("</head>\n<body>\n<h1>Regional kinetics report</h1>\n");
+
+        html.push_str("<h2>Run metadata</h2>\n<table>\n");
+        for (key, value) in self.metadata.entries() {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", escape(key), escape(value));
+        }
+        html.push_str("</table>\n");
+
+        let mut total = Counts::default();
+        let n_occurrences = self.chromosomes.values().map(|stats| stats.occurrences.len()).sum::<usize>();
+        for stats in self.chromosomes.values() {
+            total.n += stats.counts.n;
+            total.n_missing += stats.counts.n_missing;
+            total.sum += stats.counts.sum;
+        }
+        html.push_str("<h2>Quality control</h2>\n<table>\n");
+        let _ = writeln!(html, "<tr><td>Occurrences</td><td>{}</td></tr>", n_occurrences);
+        let _ = writeln!(html, "<tr><td>Rows</td><td>{}</td></tr>", total.n);
+        let _ = writeln!(html, "<tr><td>Rows without kinetics data</td><td>{} ({:.2}%)</td></tr>", total.n_missing, 100.0 * total.missing_fraction());
+        let _ = writeln!(html, "<tr><td>Mean {}</td><td>{}</td></tr>", value_name, format_mean(total.mean()));
+        html.push_str("</table>\n<table>\n<tr><th>label</th><th>strand</th><th>rows</th><th>missing</th><th>mean</th></tr>\n");
+        for ((_, strand), (label, counts)) in self.positions.iter() {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(label), strand, counts.n, counts.n_missing, format_mean(counts.mean()));
+        }
+        html.push_str("</table>\n");
+
+        let _ = writeln!(html, "<h2>Metaprofile of {}</h2>", value_name);
+        html.push_str(&self.metaprofile_svg());
+        html.push_str("\n<h2>Missing data</h2>\n<p>Fraction of rows without kinetics data per position (white: none, black: all)</p>\n");
+        html.push_str(&self.missing_map_svg());
+
+        html.push_str("\n<h2>Chromosomes</h2>\n<table>\n");
+        let _ = writeln!(html, "<tr><th>chromosome</th><th>occurrences</th><th>rows</th><th>missing</th><th>mean {}</th></tr>", value_name);
+        for (chr, stats) in self.chromosomes.iter() {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.2}%</td><td>{}</td></tr>",
+                escape(chr), stats.occurrences.len(), stats.counts.n, 100.0 * stats.counts.missing_fraction(), format_mean(stats.counts.mean()));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Write the report as a single HTML file with inline SVG plots
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        let html = self.html();
+        write_atomically(path, |output| output.write_all(html.as_bytes()))?;
+        manifest.add_file(path, "report", self.positions.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape1() {
+        assert_eq!(escape("a<b & \"c\">"), "a&lt;b &amp; &quot;c&quot;&gt;");
+    }
+}
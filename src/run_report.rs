@@ -0,0 +1,148 @@
+//! Machine-readable JSON report of a run: counts, wall-clock time per phase, peak memory, and effective parameters
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::time::Instant;
+use serde::Serialize;
+use crate::TargetIpdRich;
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::provenance::Provenance;
+use crate::rejects::Rejects;
+
+/// Rows of a chromosome and those without kinetics data
+#[derive(Debug, Default, Serialize)]
+struct MissingCounts {
+    rows: u64,
+    missing: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Phase {
+    name: &'static str,
+    seconds: f64,
+}
+
+/// Metrics accumulated over a run
+#[derive(Debug, Serialize)]
+pub(crate) struct RunReport {
+    tool: String,
+    version: String,
+    occ_processed: u64,
+    /// source -> reason -> number of skipped records
+    skipped: BTreeMap<String, BTreeMap<String, u64>>,
+    regions_written: u64,
+    /// Target regions left out without rows to write
+    regions_left_out: u64,
+    rows_written: u64,
+    /// Rows in the long layout before binning, by reference chromosome
+    missing_positions: BTreeMap<String, MissingCounts>,
+    phases: Vec<Phase>,
+    wall_clock_seconds: f64,
+    /// Peak resident set size of the process, where available
+    peak_memory_bytes: Option<u64>,
+    parameters: BTreeMap<String, String>,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    current_phase: Option<(&'static str, Instant)>,
+}
+
+/// Peak resident set size from /proc/self/status (Linux only)
+fn peak_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+impl RunReport {
+    /// Start the clock with a "setup" phase
+    pub(crate) fn new() -> Self {
+        let mut report = Self {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            occ_processed: 0,
+            skipped: BTreeMap::new(),
+            regions_written: 0,
+            regions_left_out: 0,
+            rows_written: 0,
+            missing_positions: BTreeMap::new(),
+            phases: Vec::new(),
+            wall_clock_seconds: 0.0,
+            peak_memory_bytes: None,
+            parameters: BTreeMap::new(),
+            started: Instant::now(),
+            current_phase: None,
+        };
+        report.start_phase("setup");
+        report
+    }
+
+    /// End the current phase, if any, and start the phase `name`
+    pub(crate) fn start_phase(&mut self, name: &'static str) {
+        if let Some((previous, started)) = self.current_phase.replace((name, Instant::now())) {
+            self.phases.push(Phase { name: previous, seconds: started.elapsed().as_secs_f64() });
+        }
+    }
+
+    pub(crate) fn set_parameters(&mut self, provenance: &Provenance) {
+        self.parameters = provenance.entries().iter().cloned().collect();
+    }
+
+    pub(crate) fn set_occ_processed(&mut self, n: usize) {
+        self.occ_processed = n as u64;
+    }
+
+    pub(crate) fn add_rejects(&mut self, rejects: &Rejects) {
+        for ((source, reason), count) in rejects.counts() {
+            *self.skipped.entry(source.to_string()).or_default().entry(reason.to_string()).or_default() += count;
+        }
+    }
+
+    pub(crate) fn add_rows(&mut self, rows: &[TargetIpdRich]) {
+        self.regions_written += 1;
+        for row in rows {
+            let counts = self.missing_positions.entry(row.ref_chr.clone()).or_default();
+            counts.rows += 1;
+            counts.missing += row.missing as u64;
+        }
+    }
+
+    pub(crate) fn finish(&mut self, regions_left_out: usize, rows_written: u64) {
+        self.regions_left_out = regions_left_out as u64;
+        self.rows_written = rows_written;
+    }
+
+    /// End the current phase and write the report
+    pub(crate) fn write(mut self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        if let Some((name, started)) = self.current_phase.take() {
+            self.phases.push(Phase { name, seconds: started.elapsed().as_secs_f64() });
+        }
+        self.wall_clock_seconds = self.started.elapsed().as_secs_f64();
+        self.peak_memory_bytes = peak_memory_bytes();
+        let json = serde_json::to_string_pretty(&self)?;
+        write_atomically(path, |output| writeln!(output, "{}", json))?;
+        manifest.add_file(path, "run_report", 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases1() {
+        let mut report = RunReport::new();
+        report.start_phase("read_occ");
+        report.start_phase("collect");
+        let mut rejects = Rejects::default();
+        rejects.add("occ", 3, "duplicate_occ", "chr1 10 +".to_string());
+        rejects.add("occ", 4, "duplicate_occ", "chr1 10 +".to_string());
+        report.add_rejects(&rejects);
+        assert_eq!(report.phases.iter().map(|phase| phase.name).collect::<Vec<_>>(), ["setup", "read_occ"]);
+        assert_eq!(report.skipped["occ"]["duplicate_occ"], 2);
+    }
+}
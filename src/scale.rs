@@ -0,0 +1,148 @@
+//! deepTools-style scale-regions mode: variable-length regions rescaled into a fixed number of bins with absolute flanks
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use clap::ArgEnum;
+use crate::{InputOptions, IpdSummaryKey, ValueField};
+use crate::output::write_atomically;
+use crate::rejects::Rejects;
+use crate::stats::{format_stat, mean};
+
+/// A variable-length region from BED: 0-based half-open [start, end)
+#[derive(Debug, Clone)]
+struct ScaledRegion {
+    chr: String,
+    start: i64,
+    end: i64,
+    name: String,
+    strand: char,
+}
+
+/// Parameters of scaling
+pub(crate) struct ScaleParams {
+    /// Number of bins of each region body
+    pub(crate) bins: usize,
+    /// Length of the flanks on each side, in bases
+    pub(crate) extend: i64,
+    pub(crate) value_field: ValueField,
+}
+
+/// Read regions from the first three columns and the name (4th) and strand (6th) columns of BED, if any;
+/// a region without strand or with '.' is on the plus strand
+fn read_regions(path: &str) -> Result<Vec<ScaledRegion>, Box<dyn Error>> {
+    let mut regions = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if fields.len() < 3 {
+            return Err(format!("{}:{}: expected at least 3 tab-separated BED columns", path, i + 1).into());
+        }
+        let parse = |field: &str| field.trim().parse::<i64>().map_err(|e| format!("{}:{}: invalid coordinate: {}", path, i + 1, e));
+        let (start, end) = (parse(fields[1])?, parse(fields[2])?);
+        if start < 0 || end <= start {
+            return Err(format!("{}:{}: the region must have a non-negative start and a positive length", path, i + 1).into());
+        }
+        let strand = match fields.get(5).map(|s| s.trim()) {
+            None | Some(".") | Some("+") => '+',
+            Some("-") => '-',
+            Some(s) => return Err(format!("{}:{}: unexpected strand: {}", path, i + 1, s).into()),
+        };
+        let name = fields.get(3).map_or_else(|| format!("{}:{}-{}", fields[0], start, end), |name| name.to_string());
+        regions.push(ScaledRegion { chr: fields[0].to_string(), start, end, name, strand });
+    }
+    Ok(regions)
+}
+
+/// 0-based half-open range of body offsets in bin `k` of `bins` over a body of `length` bases.
+/// A bin has at least one base, so bases are repeated (nearest-neighbor interpolation) if the body is shorter than `bins`.
+fn body_bin_range(k: usize, length: i64, bins: usize) -> (i64, i64) {
+    let start = k as i64 * length / bins as i64;
+    let end = (k as i64 + 1) * length / bins as i64;
+    (start.min(length - 1), end.max(start + 1).min(length))
+}
+
+/// Relative position, part, and 0-based reference offsets (in the orientation of the region) of each output position
+fn layout(region: &ScaledRegion, params: &ScaleParams) -> Vec<(usize, &'static str, i64, i64)> {
+    let length = region.end - region.start;
+    let upstream = (0..params.extend).map(|i| ("Upstream", i - params.extend, i - params.extend + 1));
+    let body = (0..params.bins).map(|k| {
+        let (start, end) = body_bin_range(k, length, params.bins);
+        ("Body", start, end)
+    });
+    let downstream = (0..params.extend).map(|i| ("Downstream", length + i, length + i + 1));
+    upstream.chain(body).chain(downstream).enumerate().map(|(i, (part, start, end))| (i + 1, part, start, end)).collect()
+}
+
+/// Write the long table of mean values of each position (base of flanks or bin of bodies) and relative strand of each region,
+/// and optionally the profile of their means over regions; return the number of rows of the table
+pub(crate) fn scale_regions(kinetics_path: &str, regions_path: &str, params: &ScaleParams, output_path: &str, profile_path: Option<&str>)
+    -> Result<u64, Box<dyn Error>>
+{
+    if params.bins == 0 {
+        return Err("The number of bins must be positive".into());
+    }
+    let regions = read_regions(regions_path)?;
+    let mut rejects = Rejects::default();
+    let kinetics = InputOptions::new("", 1, 0).read_kinetics_csv(kinetics_path, &mut rejects)?;
+    rejects.report();
+    let field_name = params.value_field.to_possible_value().expect("no hidden field").get_name();
+    // (position, strand) -> (part, sum of region means, number of regions with data)
+    let mut profile: BTreeMap<(usize, char), (&'static str, f64, u64)> = BTreeMap::new();
+    let mut n_rows = 0;
+    write_atomically(output_path, |output| {
+        writeln!(output, "src,name,ref_chr,region_start,region_end,region_strand,position,strand,part,{},n_bases,mean_coverage", field_name)?;
+        for (i, region) in regions.iter().enumerate() {
+            for (position, part, start, end) in layout(region, params) {
+                for (strand, data_strand) in [('+', if region.strand == '+' { 0 } else { 1 }), ('-', if region.strand == '+' { 1 } else { 0 })] {
+                    let values = (start..end).filter_map(|offset| {
+                        // offsets follow the orientation of the region
+                        let ref_start = if region.strand == '+' { region.start + offset } else { region.end - 1 - offset };
+                        let key = IpdSummaryKey::new(region.chr.clone(), ref_start + 1, data_strand);
+                        kinetics.get(&key)
+                    }).filter_map(|value| value.value_of(params.value_field).filter(|v| v.is_finite()).map(|v| (v as f64, value.coverage)))
+                        .collect::<Vec<_>>();
+                    let n = values.len();
+                    let region_mean = mean(&values.iter().map(|&(v, _)| v).collect::<Vec<_>>());
+                    if let Some(region_mean) = region_mean {
+                        let entry = profile.entry((position, strand)).or_insert((part, 0.0, 0));
+                        entry.1 += region_mean;
+                        entry.2 += 1;
+                    }
+                    let coverage = mean(&values.iter().map(|&(_, c)| c as f64).collect::<Vec<_>>());
+                    writeln!(output, "{},{},{},{},{},{},{},{},{},{},{},{}", i + 1, region.name, region.chr, region.start, region.end, region.strand,
+                        position, strand, part, format_stat(region_mean), n, format_stat(coverage))?;
+                    n_rows += 1;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    if let Some(profile_path) = profile_path {
+        write_atomically(profile_path, |output| {
+            writeln!(output, "position,strand,part,n_regions,mean_{}", field_name)?;
+            for ((position, strand), (part, sum, n)) in profile.iter() {
+                writeln!(output, "{},{},{},{},{}", position, strand, part, n, sum / *n as f64)?;
+            }
+            Ok(())
+        })?;
+    }
+    eprintln!("[INFO] Wrote {} rows of {} scaled regions", n_rows, regions.len());
+    Ok(n_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_bin_range1() {
+        assert_eq!((0..4).map(|k| body_bin_range(k, 10, 4)).collect::<Vec<_>>(), [(0, 2), (2, 5), (5, 7), (7, 10)]);
+        // a body shorter than the bins repeats its bases
+        assert_eq!((0..4).map(|k| body_bin_range(k, 2, 4)).collect::<Vec<_>>(), [(0, 1), (0, 1), (1, 2), (1, 2)]);
+    }
+}
@@ -0,0 +1,206 @@
+//! Self test of an installation on tiny synthetic inputs, including the HDF5 backend
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use hdf5::types::FixedAscii;
+use crate::{collect_ipd_summary_in_merged_occ, InputOptions, IpdSummaryKey, IpdSummaryValue, OutputOptions, RegionSpec};
+use crate::backend_check::compare_backends;
+use crate::manifest::Manifest;
+use crate::output::OutputFormat;
+use crate::rejects::Rejects;
+use crate::run_report::RunReport;
+use crate::simulate::{self, SimulationParams};
+use crate::tabix;
+
+const CHROM_NAME: &str = "chrSelfTest";
+const OCC_WIDTH: i64 = 4;
+const OCC_EXTENSION: i64 = 3;
+
+fn write_dataset<T: hdf5::H5Type>(group: &hdf5::Group, name: &str, data: &[T]) -> Result<(), Box<dyn Error>> {
+    group.new_dataset_builder().with_data(data).create(name)?;
+    Ok(())
+}
+
+/// Convert a kinetics CSV into the HDF5 layout read by the HDF5 backend
+fn write_kinetics_hdf5(kinetics_path: &Path, hdf5_path: &Path) -> Result<(), Box<dyn Error>> {
+    let kinetics = InputOptions::new("", 1, 0).read_kinetics_csv(kinetics_path, &mut Rejects::default())?;
+    write_records_hdf5(kinetics.iter(), hdf5_path)
+}
+
+/// Write kinetics records in the HDF5 layout read by the HDF5 backend:
+/// one group per chromosome, with datasets indexed by (tpl - 1) * 2 + strand
+fn write_records_hdf5<'a, I>(records: I, hdf5_path: &Path) -> Result<(), Box<dyn Error>>
+where
+    I: Iterator<Item = (&'a IpdSummaryKey, &'a IpdSummaryValue)>,
+{
+    let mut chromosomes: HashMap<&str, Vec<_>> = HashMap::new();
+    for (key, value) in records {
+        chromosomes.entry(key.refName.as_str()).or_default().push((key, value));
+    }
+    let file = hdf5::File::create(hdf5_path)?;
+    for (chr, records) in chromosomes {
+        let length = records.iter().map(|(key, _)| key.tpl as usize).max().unwrap_or(0) * 2;
+        let tpl = (0..length).map(|i| (i / 2 + 1) as u32).collect::<Vec<_>>();
+        let strand = (0..length).map(|i| (i % 2) as u8).collect::<Vec<_>>();
+        let mut base = vec![FixedAscii::<1>::from_ascii(b"N")?; length];
+        let (mut score, mut coverage) = (vec![0u32; length], vec![0u32; length]);
+        let (mut t_mean, mut t_err, mut prediction, mut ipd_ratio) = (vec![0f32; length], vec![0f32; length], vec![0f32; length], vec![0f32; length]);
+        let (mut frac, mut frac_low, mut frac_up) = (vec![f32::NAN; length], vec![f32::NAN; length], vec![f32::NAN; length]);
+        for (key, value) in records {
+            let i = (key.tpl as usize - 1) * 2 + key.strand as usize;
+            base[i] = FixedAscii::<1>::from_ascii(value.base.unwrap_or('N').to_string().as_bytes())?;
+            score[i] = value.score;
+            coverage[i] = value.coverage;
+            t_mean[i] = value.tMean;
+            t_err[i] = value.tErr;
+            prediction[i] = value.modelPrediction;
+            ipd_ratio[i] = value.ipdRatio;
+            frac[i] = value.frac.unwrap_or(f32::NAN);
+            frac_low[i] = value.fracLow.unwrap_or(f32::NAN);
+            frac_up[i] = value.fracUp.unwrap_or(f32::NAN);
+        }
+        let group = file.create_group(chr)?;
+        write_dataset(&group, "tpl", &tpl)?;
+        write_dataset(&group, "strand", &strand)?;
+        write_dataset(&group, "base", &base)?;
+        write_dataset(&group, "score", &score)?;
+        write_dataset(&group, "tMean", &t_mean)?;
+        write_dataset(&group, "tErr", &t_err)?;
+        write_dataset(&group, "modelPrediction", &prediction)?;
+        write_dataset(&group, "ipdRatio", &ipd_ratio)?;
+        write_dataset(&group, "coverage", &coverage)?;
+        write_dataset(&group, "frac", &frac)?;
+        write_dataset(&group, "fracLow", &frac_low)?;
+        write_dataset(&group, "fracUp", &frac_up)?;
+    }
+    file.close()?;
+    Ok(())
+}
+
+/// Check labels, relative strands, and reference strands of rows in a CSV output of the long layout
+fn check_geometry(output_path: &Path, occ_strands: &[char]) -> Result<(), Box<dyn Error>> {
+    let labels = RegionSpec::new(OCC_WIDTH, OCC_EXTENSION).labels();
+    let mut reader = csv::Reader::from_path(output_path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h == name).ok_or_else(|| format!("No {} column in the output", name));
+    let (src_column, strand_column, label_column, ref_strand_column) = (column("src")?, column("strand")?, column("label")?, column("ref_strand")?);
+    let mut n_rows = 0;
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        n_rows += 1;
+        let src = record[src_column].parse::<usize>()?;
+        let row_in_region = i % labels.len();
+        if record[label_column] != labels[row_in_region] {
+            return Err(format!("Row {}: label {} is not {}", i + 1, &record[label_column], labels[row_in_region]).into());
+        }
+        let strand = if row_in_region % 2 == 0 { "+" } else { "-" };
+        if record[strand_column] != *strand {
+            return Err(format!("Row {}: relative strands do not alternate", i + 1).into());
+        }
+        // a relative plus-strand row is on the strand of its occurrence
+        let occ_strand = occ_strands.get(src.wrapping_sub(1)).ok_or_else(|| format!("Row {}: unknown src {}", i + 1, src))?;
+        let expected_ref_strand = if (*occ_strand == '-') != (strand == "-") { "1" } else { "0" };
+        if record[ref_strand_column] != *expected_ref_strand {
+            return Err(format!("Row {}: ref_strand is not {} for a {} row of a {} occurrence", i + 1, expected_ref_strand, strand, occ_strand).into());
+        }
+    }
+    if n_rows != occ_strands.len() * labels.len() {
+        return Err(format!("{} rows for {} occurrences of {} rows", n_rows, occ_strands.len(), labels.len()).into());
+    }
+    Ok(())
+}
+
+fn run_checks(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let path = |name: &str| dir.join(name);
+    let path_str = |name: &str| path(name).to_string_lossy().into_owned();
+    let params = SimulationParams {
+        chrom_name: CHROM_NAME.to_string(),
+        chrom_length: 2000,
+        coverage: 20.0,
+        ipd_sigma: 0.5,
+        effect_size: 3.0,
+        n_modified: 20,
+        motif_width: OCC_WIDTH as usize,
+        modified_offset: 1,
+        seed: 1,
+    };
+    simulate::simulate(&params, None, 1, &path_str("kinetics.csv"), &path_str("occ.txt"), None)?;
+    write_kinetics_hdf5(&path("kinetics.csv"), &path("kinetics.h5"))?;
+    eprintln!("[INFO] Self test: generated synthetic inputs in {}", dir.display());
+
+    compare_backends(&path_str("kinetics.csv"), &path_str("kinetics.h5"), &path_str("occ.txt"), OCC_WIDTH, OCC_EXTENSION, 1e-6, 10)?;
+
+    let input_options = InputOptions::new(&path_str("occ.txt"), OCC_WIDTH, OCC_EXTENSION);
+    let occ_strands = std::fs::read_to_string(path("occ.txt"))?.lines()
+        .filter_map(|line| line.split(' ').nth(2).and_then(|s| s.chars().next())).collect::<Vec<_>>();
+    let expected_rows = (occ_strands.len() * RegionSpec::new(OCC_WIDTH, OCC_EXTENSION).labels().len()) as u64;
+    for (format, name) in [(OutputFormat::Csv, "out.csv"), (OutputFormat::Feather, "out.feather"), (OutputFormat::Parquet, "out.parquet"), (OutputFormat::Tabix, "out.bgz")] {
+        let output_options = OutputOptions { format, ..OutputOptions::default() };
+        let n_rows = collect_ipd_summary_in_merged_occ(&[path_str("kinetics.csv")], &input_options, &output_options, path_str(name), &mut Manifest::new(), &mut RunReport::new())?;
+        if n_rows != expected_rows {
+            return Err(format!("{:?} output has {} rows instead of {}", format, n_rows, expected_rows).into());
+        }
+        let mut written = vec![path(name)];
+        if format == OutputFormat::Tabix {
+            written.push(tabix::index_path(path(name)));
+        }
+        if let Some(empty) = written.iter().find(|p| std::fs::metadata(p).map_or(true, |m| m.len() == 0)) {
+            return Err(format!("{} is missing or empty", empty.display()).into());
+        }
+        eprintln!("[INFO] Self test: {:?} output has {} rows as expected", format, n_rows);
+    }
+    check_geometry(&path("out.csv"), &occ_strands)?;
+    eprintln!("[INFO] Self test: labels and strands of rows are consistent");
+    Ok(())
+}
+
+/// Run the self test in a temporary directory, which is removed unless `keep` is true
+pub(crate) fn self_test(keep: bool) -> Result<(), Box<dyn Error>> {
+    let dir: PathBuf = std::env::temp_dir().join(format!("collect_regional_kinetics.self_test.{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let result = run_checks(&dir);
+    if keep {
+        eprintln!("[INFO] Self test files are kept in {}", dir.display());
+    } else {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    match result {
+        Ok(()) => {
+            eprintln!("[INFO] Self test passed");
+            Ok(())
+        },
+        Err(e) => Err(format!("Self test failed: {}", e).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChrKineticsHdf5, IpdSummary};
+    use crate::chrom_filter::ChromFilter;
+    use crate::tests::temporary_file;
+
+    #[test]
+    fn nonfinite_tmean_is_missing_in_both_backends() {
+        let (kinetics_file, hdf5_file) = (temporary_file("nonfinite_tmean.kinetics.csv"), temporary_file("nonfinite_tmean.kinetics.h5"));
+        let (kinetics_path, hdf5_path) = (kinetics_file.path(), hdf5_file.path());
+        std::fs::write(kinetics_path, "refName,tpl,strand,base,score,tMean,tErr,modelPrediction,ipdRatio,coverage,frac,fracLow,fracUp\n\
+            chr1,1,0,A,10,1.5,0.1,1.0,1.5,20,,,\n\
+            chr1,2,0,C,10,nan,0.1,1.0,1.5,20,,,\n\
+            chr1,3,0,G,10,inf,0.1,1.0,1.5,20,,,\n").unwrap();
+        // the HDF5 file keeps the non-finite values, which the CSV backend drops while reading
+        let records = csv::Reader::from_path(kinetics_path).unwrap().deserialize::<IpdSummary>()
+            .map(|summary| summary.unwrap().into_pair()).collect::<Vec<_>>();
+        write_records_hdf5(records.iter().map(|(key, value)| (key, value)), hdf5_path).unwrap();
+
+        let csv_kinetics = InputOptions::new("", 1, 0).read_kinetics_csv(kinetics_path, &mut Rejects::default()).unwrap();
+        let hdf5_kinetics = ChrKineticsHdf5::kinetics_datasets_from_hdf5_path(hdf5_path, &ChromFilter::default(), false).unwrap();
+        for (key, _) in records.iter() {
+            let csv_value = csv_kinetics.get(key);
+            let hdf5_value = hdf5_kinetics["chr1"].get_ipd_summary_value(key);
+            assert_eq!(csv_value.is_some(), key.tpl == 1);
+            assert_eq!(hdf5_value.is_some(), key.tpl == 1);
+        }
+    }
+}
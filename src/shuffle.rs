@@ -0,0 +1,102 @@
+//! Control occurrence sets of random positions matched to the original occurrences
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::Write;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::InputOptions;
+use crate::chrom_sizes::ChromSizes;
+use crate::output::write_atomically;
+use crate::reference::Reference;
+use crate::rejects::Rejects;
+
+/// Parameters of shuffling
+pub(crate) struct ShuffleParams {
+    pub(crate) occ_width: i64,
+    /// Number of control occurrences drawn for each occurrence
+    pub(crate) n_per_occ: usize,
+    /// Maximum distance of a control start from the start of its original occurrence
+    pub(crate) max_distance: Option<i64>,
+    /// Maximum difference of GC fractions of a control and its original occurrence
+    pub(crate) gc_tolerance: Option<f32>,
+    /// Length added to each end of an occurrence for its GC fraction
+    pub(crate) gc_flank: i64,
+    /// Draws per control occurrence before giving up
+    pub(crate) max_attempts: usize,
+    pub(crate) seed: u64,
+}
+
+/// Range of 0-based starts of controls of an occurrence at `start` on a chromosome of `length`
+fn start_range(start: i64, length: i64, occ_width: i64, max_distance: Option<i64>) -> Option<(i64, i64)> {
+    let (mut low, mut high) = (0, length - occ_width);
+    if let Some(d) = max_distance {
+        low = low.max(start - d);
+        high = high.min(start + d);
+    }
+    if low <= high { Some((low, high)) } else { None }
+}
+
+/// Write a control occ file with `n_per_occ` random occurrences on the chromosome and strand of each occurrence,
+/// not starting at any original occurrence, optionally near it and with a similar GC fraction,
+/// and return the number of control occurrences.
+/// Chromosome lengths come from `reference` if given, or `chrom_sizes`.
+pub(crate) fn shuffle_occ(occ_path: &str, chrom_sizes: Option<&ChromSizes>, reference: Option<&Reference>, params: &ShuffleParams, output_path: &str)
+    -> Result<u64, Box<dyn Error>>
+{
+    if params.gc_tolerance.is_some() && reference.is_none() {
+        return Err("GC matching requires a reference FASTA".into());
+    }
+    let length_of = |chr: &str| reference.and_then(|reference| reference.length(chr)).or_else(|| chrom_sizes.and_then(|sizes| sizes.length(chr)));
+    let mut rejects = Rejects::default();
+    let occs = InputOptions::new(occ_path, params.occ_width, 0).read_occ(&mut rejects)?;
+    rejects.report();
+    let mut original: HashMap<&str, HashSet<i64>> = HashMap::new();
+    for (_, occ) in occs.iter() {
+        original.entry(occ.refName.as_str()).or_default().insert(occ.start);
+    }
+    let gc_of = |chr: &str, start: i64| reference.and_then(|reference| reference.gc_fraction(chr, start - params.gc_flank, start + params.occ_width + params.gc_flank));
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let (mut n_controls, mut n_missed) = (0, 0);
+    write_atomically(output_path, |output| {
+        for (_, occ) in occs.iter() {
+            let chr = occ.refName.as_str();
+            let range = length_of(chr).and_then(|length| start_range(occ.start, length, params.occ_width, params.max_distance));
+            let target_gc = params.gc_tolerance.and_then(|_| gc_of(chr, occ.start));
+            for _ in 0..params.n_per_occ {
+                let control = range.and_then(|(low, high)| (0..params.max_attempts).map(|_| rng.gen_range(low..=high)).find(|&start| {
+                    !original[chr].contains(&start) && match (params.gc_tolerance, target_gc) {
+                        (Some(tolerance), Some(target_gc)) => gc_of(chr, start).is_some_and(|gc| (gc - target_gc).abs() <= tolerance),
+                        _ => true,
+                    }
+                }));
+                match control {
+                    Some(start) => {
+                        writeln!(output, "{} {} {}", chr, start, occ.strand)?;
+                        n_controls += 1;
+                    },
+                    None => n_missed += 1,
+                }
+            }
+        }
+        Ok(())
+    })?;
+    if n_missed > 0 {
+        eprintln!("[WARNING] {} control occurrences were not found within {} draws (unknown chromosomes or no matching position)", n_missed, params.max_attempts);
+    }
+    eprintln!("[INFO] Wrote {} control occurrences for {} occurrences", n_controls, occs.len());
+    Ok(n_controls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_range1() {
+        assert_eq!(start_range(50, 100, 4, None), Some((0, 96)));
+        assert_eq!(start_range(50, 100, 4, Some(10)), Some((40, 60)));
+        assert_eq!(start_range(2, 100, 4, Some(10)), Some((0, 12)));
+        assert_eq!(start_range(0, 3, 4, None), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Synthetic kinetics under a null model with optional modified sites
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, LogNormal, Poisson};
+use crate::IpdSummary;
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b => b,
+    }
+}
+
+/// Complementary error function (Numerical Recipes `erfcc`, relative error < 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+        + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+        + t * (-0.82215223 + t * 0.17087277))))))))).exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+/// Upper-tail probability of the standard normal distribution
+pub(crate) fn normal_upper_tail(z: f64) -> f64 {
+    0.5 * erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// Expected IPD for each sequence context (a k-mer centered at the base, k = 2 * half_width + 1)
+pub(crate) struct ContextModel {
+    half_width: usize,
+    predictions: HashMap<Vec<u8>, f64>,
+}
+
+impl ContextModel {
+    /// A random model assigning a log-normally distributed expected IPD (median 1.0) to every k-mer
+    pub(crate) fn random<R: Rng>(half_width: usize, rng: &mut R) -> Self {
+        let distribution = LogNormal::new(0.0, 0.3).expect("valid log-normal parameters");
+        let mut kmers: Vec<Vec<u8>> = vec![Vec::new()];
+        for _ in 0..(2 * half_width + 1) {
+            kmers = kmers.into_iter().flat_map(|kmer| BASES.iter().map(move |b| {
+                let mut extended = kmer.clone();
+                extended.push(*b);
+                extended
+            })).collect();
+        }
+        let predictions = kmers.into_iter().map(|kmer| (kmer, distribution.sample(rng))).collect();
+        Self { half_width, predictions }
+    }
+
+    /// Read a model from a TSV file of k-mer and expected IPD without header
+    pub(crate) fn from_tsv(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut predictions = HashMap::new();
+        let mut kmer_length = None;
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (kmer, prediction) = line.split_once('\t')
+                .ok_or_else(|| format!("{}:{}: expected a k-mer and a value separated by a tab", path, i + 1))?;
+            let kmer = kmer.to_ascii_uppercase().into_bytes();
+            if kmer.len() % 2 == 0 || kmer_length.is_some_and(|l| l != kmer.len()) {
+                return Err(format!("{}:{}: k-mers must have the same odd length", path, i + 1).into());
+            }
+            kmer_length = Some(kmer.len());
+            predictions.insert(kmer, prediction.trim().parse::<f64>()?);
+        }
+        let kmer_length = kmer_length.ok_or_else(|| format!("{}: empty context model", path))?;
+        Ok(Self { half_width: kmer_length / 2, predictions })
+    }
+
+    /// Expected IPD at a 0-based position of `sequence` on a strand (0 = plus, 1 = minus).
+    /// Contexts running off the sequence or not in the model get 1.0.
+    pub(crate) fn predict(&self, sequence: &[u8], position: usize, strand: u8) -> f64 {
+        if position < self.half_width || position + self.half_width >= sequence.len() {
+            return 1.0;
+        }
+        let window = &sequence[(position - self.half_width)..=(position + self.half_width)];
+        let context = if strand == 0 {
+            window.to_vec()
+        } else {
+            window.iter().rev().map(|b| complement(*b)).collect()
+        };
+        self.predictions.get(&context).copied().unwrap_or(1.0)
+    }
+}
+
+/// ipdSummary-like statistics of one base
+pub(crate) struct SimulatedStats {
+    pub(crate) t_mean: f64,
+    pub(crate) t_err: f64,
+    /// Phred-scaled one-sided p-value of tMean exceeding the prediction
+    pub(crate) score: u32,
+}
+
+/// Draw `coverage` per-read IPDs from a log-normal distribution with mean `prediction * effect`
+/// and summarize them as ipdSummary does (mean, standard error, and a score against `prediction`)
+pub(crate) fn simulate_stats<R: Rng>(rng: &mut R, prediction: f64, effect: f64, sigma: f64, coverage: u32) -> SimulatedStats {
+    let mean = prediction * effect;
+    // E[X] = exp(mu + sigma^2 / 2) for a log-normal X
+    let distribution = LogNormal::new(mean.ln() - sigma * sigma / 2.0, sigma).expect("valid log-normal parameters");
+    let ipds = (0..coverage).map(|_| distribution.sample(rng)).collect::<Vec<f64>>();
+    let n = ipds.len() as f64;
+    let t_mean = ipds.iter().sum::<f64>() / n;
+    let t_err = if ipds.len() > 1 {
+        (ipds.iter().map(|x| (x - t_mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt() / n.sqrt()
+    } else {
+        0.0
+    };
+    let score = if t_err > 0.0 {
+        let p = normal_upper_tail((t_mean - prediction) / t_err).max(1e-100);
+        (-10.0 * p.log10()).round().min(999.0) as u32
+    } else {
+        0
+    };
+    SimulatedStats { t_mean, t_err, score }
+}
+
+/// Parameters of a simulation
+pub(crate) struct SimulationParams {
+    pub(crate) chrom_name: String,
+    pub(crate) chrom_length: usize,
+    /// Mean of Poisson-distributed coverage of each base and strand
+    pub(crate) coverage: f64,
+    /// Standard deviation of log per-read IPDs
+    pub(crate) ipd_sigma: f64,
+    /// Fold change of the mean IPD at modified bases
+    pub(crate) effect_size: f64,
+    pub(crate) n_modified: usize,
+    pub(crate) motif_width: usize,
+    /// 0-based offset of the modified base in a motif, relative to the motif strand
+    pub(crate) modified_offset: usize,
+    pub(crate) seed: u64,
+}
+
+/// Simulate a random chromosome and its kinetics.
+/// Writes an ipdSummary-like CSV, an occ file of motif occurrences with a modified base,
+/// and optionally the chromosome sequence in FASTA.
+pub(crate) fn simulate(params: &SimulationParams, context_model: Option<&str>, context_half_width: usize,
+    output: &str, occ_output: &str, fasta_output: Option<&str>) -> Result<(), Box<dyn Error>>
+{
+    if params.modified_offset >= params.motif_width || params.motif_width > params.chrom_length {
+        return Err("--modified-offset must be smaller than --motif-width, which must not exceed --chrom-length".into());
+    }
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let model = match context_model {
+        Some(path) => ContextModel::from_tsv(path)?,
+        None => ContextModel::random(context_half_width, &mut rng),
+    };
+    let sequence = (0..params.chrom_length).map(|_| BASES[rng.gen_range(0..4)]).collect::<Vec<u8>>();
+
+    // motif occurrences with a modified base; keys are (0-based position, strand) of modified bases
+    let mut occ_writer = BufWriter::new(File::create(occ_output)?);
+    let mut modified = HashSet::new();
+    for _ in 0..params.n_modified {
+        let start = rng.gen_range(0..=(params.chrom_length - params.motif_width));
+        let strand: u8 = rng.gen_range(0..2);
+        let position = if strand == 0 { start + params.modified_offset } else { start + params.motif_width - 1 - params.modified_offset };
+        modified.insert((position, strand));
+        writeln!(occ_writer, "{} {} {}", params.chrom_name, start, if strand == 0 { '+' } else { '-' })?;
+    }
+    occ_writer.flush()?;
+
+    let coverage_distribution = Poisson::new(params.coverage)?;
+    let mut kinetics_writer = csv::Writer::from_path(output)?;
+    for position in 0..params.chrom_length {
+        for strand in 0..2u8 {
+            let coverage = coverage_distribution.sample(&mut rng) as u32;
+            if coverage == 0 {
+                continue;
+            }
+            let prediction = model.predict(&sequence, position, strand);
+            let effect = if modified.contains(&(position, strand)) { params.effect_size } else { 1.0 };
+            let stats = simulate_stats(&mut rng, prediction, effect, params.ipd_sigma, coverage);
+            let base = if strand == 0 { sequence[position] } else { complement(sequence[position]) };
+            kinetics_writer.serialize(IpdSummary {
+                refName: params.chrom_name.clone(),
+                tpl: position as i64 + 1,
+                strand,
+                base: Some(base as char),
+                score: stats.score,
+                tMean: stats.t_mean as f32,
+                tErr: stats.t_err as f32,
+                modelPrediction: prediction as f32,
+                ipdRatio: (stats.t_mean / prediction) as f32,
+                coverage,
+                frac: None,
+                fracLow: None,
+                fracUp: None,
+                pwMean: None,
+                pwErr: None,
+            })?;
+        }
+    }
+    kinetics_writer.flush()?;
+
+    if let Some(fasta_output) = fasta_output {
+        let mut fasta_writer = BufWriter::new(File::create(fasta_output)?);
+        writeln!(fasta_writer, ">{}", params.chrom_name)?;
+        for line in sequence.chunks(80) {
+            fasta_writer.write_all(line)?;
+            fasta_writer.write_all(b"\n")?;
+        }
+        fasta_writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_upper_tail1() {
+        assert!((normal_upper_tail(0.0) - 0.5).abs() < 1e-6);
+        assert!((normal_upper_tail(1.959964) - 0.025).abs() < 1e-6);
+        assert!((normal_upper_tail(-1.959964) - 0.975).abs() < 1e-6);
+    }
+
+    #[test]
+    fn context_model_strands() {
+        let model = ContextModel {
+            half_width: 1,
+            predictions: [(b"ACG".to_vec(), 2.0), (b"CGT".to_vec(), 3.0)].into_iter().collect(),
+        };
+        let sequence = b"ACGT";
+        assert_eq!(model.predict(sequence, 1, 0), 2.0);
+        // reverse complement of "ACG" is "CGT"
+        assert_eq!(model.predict(sequence, 1, 1), 3.0);
+        assert_eq!(model.predict(sequence, 0, 0), 1.0);
+    }
+
+    #[test]
+    fn simulate_stats_effect() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let null = simulate_stats(&mut rng, 1.0, 1.0, 0.5, 1000);
+        let modified = simulate_stats(&mut rng, 1.0, 3.0, 0.5, 1000);
+        assert!((null.t_mean - 1.0).abs() < 0.1);
+        assert!((modified.t_mean - 3.0).abs() < 0.3);
+        assert!(modified.score > null.score);
+    }
+}
@@ -0,0 +1,115 @@
+//! Destinations of collected kinetics, receiving target regions in batches
+
+use std::error::Error;
+use std::path::Path;
+use crate::{check_existing_header, Column, IpdSummaryKey, Layout, OutputOptions, TargetIpdRich, ValueField, ValueFormat};
+use crate::output::{Compression, OutputWriter};
+
+/// Collected kinetics of a target region
+pub(crate) type TargetKinetics = (IpdSummaryKey, Vec<TargetIpdRich>);
+
+/// Number of rows of target regions buffered before a batch is passed to a sink
+pub(crate) const BATCH_ROWS: u64 = 1 << 16;
+
+/// An output of collected kinetics, implemented by `CsvSink`, `ColumnarSink` (Feather and Parquet), and `TabixSink`.
+/// A new output format is added by implementing this trait and choosing it in `TableWriter::create`.
+/// There is no DuckDB sink, as DuckDB reads the Parquet output directly, nor a callback sink, as there is no library API to call back into.
+pub(crate) trait OutputSink {
+    /// Write a batch of target regions, and return the number of written rows
+    fn write_batch(&mut self, batch: Vec<TargetKinetics>) -> Result<u64, Box<dyn Error>>;
+
+    /// Write any remaining data and close the output
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>>;
+}
+
+/// Sink writing CSV, optionally compressed
+pub(crate) struct CsvSink {
+    writer: csv::Writer<OutputWriter>,
+    layout: Layout,
+    columns: Vec<Column>,
+    value_fields: Vec<ValueField>,
+    value_format: ValueFormat,
+    /// Values of constant columns written first in each row, indexed by column and then by sample
+    constant_values: Vec<Vec<String>>,
+}
+
+impl CsvSink {
+    /// Write a header unless `append_to_existing`, in which case rows are appended to an existing output
+    pub(crate) fn create<P: AsRef<Path>>(path: P, occ_width: i64, occ_extension: i64, output_options: &OutputOptions, append_to_existing: bool) -> Result<Self, Box<dyn Error>> {
+        let header = output_options.header(occ_width, occ_extension);
+        if append_to_existing && output_options.compression == Compression::None {
+            check_existing_header(&path, &header)?;
+        }
+        let mut writer = output_options.create_writer(&path, append_to_existing)?;
+        if !append_to_existing {
+            writer.write_record(header)?;
+        }
+        Ok(Self {
+            writer,
+            layout: output_options.layout,
+            columns: output_options.columns.clone(),
+            value_fields: output_options.value_fields.clone(),
+            value_format: output_options.value_format.clone(),
+            constant_values: output_options.constant_columns().into_iter().map(|(_, value)| value).collect(),
+        })
+    }
+
+    fn write_record(&mut self, record: Vec<String>, sample: usize) -> Result<(), Box<dyn Error>> {
+        self.writer.write_record(self.constant_values.iter().map(|values| &values[sample]).chain(record.iter()))?;
+        Ok(())
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn write_batch(&mut self, batch: Vec<TargetKinetics>) -> Result<u64, Box<dyn Error>> {
+        let n_rows = self.layout.count_rows(&batch);
+        for (target_key, target_vals) in batch {
+            match self.layout {
+                Layout::Long => {
+                    for target in target_vals.iter() {
+                        let record = target.to_record(&self.columns, &self.value_fields, &self.value_format);
+                        self.write_record(record, target.sample)?;
+                    }
+                },
+                Layout::Wide => {
+                    let (src, sample) = target_vals.first().map_or((0, 0), |t| (t.src, t.sample));
+                    let mut record = vec![src.to_string(), target_key.refName, target_key.tpl.to_string(), target_key.strand.to_string()];
+                    let (value_fields, value_format) = (&self.value_fields, &self.value_format);
+                    record.extend(target_vals.iter().flat_map(|t| value_fields.iter().map(|field| t.format_value(*field, value_format))));
+                    self.write_record(record, sample)?;
+                },
+            }
+        }
+        Ok(n_rows)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        OutputOptions::finish_writer(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpdSummaryValue, RegionSpec};
+    use crate::tests::{alternating_strand_rows, temporary_file};
+
+    #[test]
+    fn wide_csv1() {
+        let file = temporary_file("wide.csv");
+        let output_options = OutputOptions { layout: Layout::Wide, value_fields: vec![ValueField::TMean, ValueField::IpdRatio], ..OutputOptions::default() };
+        let rows = alternating_strand_rows(6, &RegionSpec::new(1, 1), |j| {
+            Some(IpdSummaryValue { tMean: j as f32 + 0.5, ipdRatio: j as f32 + 1.5, coverage: 10, ..Default::default() })
+        });
+        let mut sink: Box<dyn OutputSink> = Box::new(CsvSink::create(file.path(), 1, 1, &output_options, false).unwrap());
+        assert_eq!(sink.write_batch(vec![(IpdSummaryKey::new("chr".to_string(), 101, 0), rows)]).unwrap(), 1);
+        sink.finish().unwrap();
+        // one row per occurrence with the fields of each label in the order of rows of the long layout
+        let output = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(output.lines().collect::<Vec<_>>(), [
+            "src,ref_chr,ref_position,ref_strand,s1p_tMean,s1p_ipdRatio,s1m_tMean,s1m_ipdRatio,\
+                m1p_tMean,m1p_ipdRatio,m1m_tMean,m1m_ipdRatio,e1p_tMean,e1p_ipdRatio,e1m_tMean,e1m_ipdRatio",
+            "1,chr,101,0,0.5,1.5,1.5,2.5,2.5,3.5,3.5,4.5,4.5,5.5,5.5,6.5",
+        ]);
+    }
+}
@@ -0,0 +1,25 @@
+//! Descriptive statistics shared by the per-position and per-occurrence outputs.
+//!
+//! Outputs compute statistics only from rows with kinetics data and finite values;
+//! a statistic without enough values is None and written as NA.
+
+/// Missing statistics and values in CSV outputs
+pub(crate) const NA: &str = "NA";
+
+/// Mean of values; None if there are no values
+pub(crate) fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() { None } else { Some(values.iter().sum::<f64>() / values.len() as f64) }
+}
+
+/// Mean and unbiased variance of non-empty values; the variance of a single value is 0
+pub(crate) fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = if values.len() > 1 { values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0) } else { 0.0 };
+    (mean, variance)
+}
+
+/// Format a statistic for CSV output, NA if None
+pub(crate) fn format_stat(stat: Option<f64>) -> String {
+    stat.map_or_else(|| NA.to_string(), |stat| stat.to_string())
+}
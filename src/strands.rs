@@ -0,0 +1,90 @@
+//! Collapsing of rows of both relative strands at each position into one row, e.g. for palindromic motifs and CpG sites
+
+use clap::ArgEnum;
+use crate::TargetIpdRich;
+
+/// How values of both strands are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub(crate) enum CombineStrands {
+    Mean,
+    Sum,
+    Max,
+}
+
+impl CombineStrands {
+    /// Combine present values; None if no value is present
+    fn combine<I: Iterator<Item = Option<f32>>>(&self, values: I) -> Option<f32> {
+        let values = values.flatten().collect::<Vec<_>>();
+        if values.is_empty() {
+            return None;
+        }
+        Some(match self {
+            CombineStrands::Mean => (values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64) as f32,
+            CombineStrands::Sum => values.iter().sum(),
+            CombineStrands::Max => values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        })
+    }
+}
+
+/// Combine kinetics fields of rows of a position with data into the relative plus-strand row;
+/// annotations, base, and context are those of the plus-strand row
+fn merge_strands(rows: &[&TargetIpdRich], how: CombineStrands) -> TargetIpdRich {
+    let first = rows.iter().find(|row| row.strand == '+').copied().unwrap_or(rows[0]);
+    let present = rows.iter().filter(|row| !row.missing).copied().collect::<Vec<_>>();
+    if present.is_empty() {
+        return TargetIpdRich { strand: '+', ..first.clone() };
+    }
+    let of = |field: fn(&TargetIpdRich) -> f32| how.combine(present.iter().map(|row| Some(field(row)))).expect("present rows");
+    let of_opt = |field: fn(&TargetIpdRich) -> Option<f32>| how.combine(present.iter().map(|row| field(row)));
+    TargetIpdRich {
+        strand: '+',
+        value: of(|row| row.value),
+        score: of(|row| row.score as f32).round() as u32,
+        tErr: of(|row| row.tErr),
+        modelPrediction: of(|row| row.modelPrediction),
+        ipdRatio: of(|row| row.ipdRatio),
+        coverage: of(|row| row.coverage as f32).round() as u32,
+        frac: of_opt(|row| row.frac),
+        fracLow: of_opt(|row| row.fracLow),
+        fracUp: of_opt(|row| row.fracUp),
+        pwMean: of_opt(|row| row.pwMean),
+        pwErr: of_opt(|row| row.pwErr),
+        ipdRatio_control: of_opt(|row| row.ipdRatio_control),
+        zscore_background: of_opt(|row| row.zscore_background),
+        missing: false,
+        ..first.clone()
+    }
+}
+
+/// One row per position of rows of both relative strands, in the order of `rows`.
+/// The row of a position has the label and reference position of its relative plus-strand row.
+pub(crate) fn combine_strands(rows: Vec<TargetIpdRich>, how: CombineStrands) -> Vec<TargetIpdRich> {
+    let mut positions: Vec<(i64, Vec<&TargetIpdRich>)> = Vec::new();
+    for row in rows.iter() {
+        match positions.iter_mut().rev().find(|(position, _)| *position == row.position) {
+            Some((_, members)) => members.push(row),
+            None => positions.push((row.position, vec![row])),
+        }
+    }
+    positions.iter().map(|(_, members)| merge_strands(members, how)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpdSummaryValue, RegionSpec};
+    use crate::tests::alternating_strand_rows;
+
+    #[test]
+    fn combine_strands1() {
+        let rows = alternating_strand_rows(6, &RegionSpec::new(1, 1), |j| {
+            if j == 3 { None } else { Some(IpdSummaryValue { tMean: j as f32, coverage: 10, ..IpdSummaryValue::default() }) }
+        });
+        let combined = combine_strands(rows.clone(), CombineStrands::Mean);
+        assert_eq!(combined.iter().map(|row| (row.position, row.strand, row.value)).collect::<Vec<_>>(), [(1, '+', 0.5), (2, '+', 2.0), (3, '+', 4.5)]);
+        assert_eq!(combined[0].ref_strand, 0);
+        let summed = combine_strands(rows.clone(), CombineStrands::Sum);
+        assert_eq!((summed[0].value, summed[0].coverage), (1.0, 20));
+        assert_eq!(combine_strands(rows, CombineStrands::Max)[2].value, 5.0);
+    }
+}
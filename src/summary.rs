@@ -0,0 +1,94 @@
+//! Per-occurrence summary of values in the target region and its flanks, for ranking candidate sites
+
+use std::error::Error;
+use std::io::Write;
+use crate::{IpdSummaryKey, TargetIpdRich, ValueField};
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+use crate::profile::quantile;
+use crate::stats::{format_stat, mean};
+
+const HEADER: &str = "src,occ_id,ref_chr,ref_position,ref_strand,target_mean,target_median,upstream_mean,upstream_median,\
+downstream_mean,downstream_median,enrichment,min_coverage,n_missing";
+
+/// Lines of the summary, one per occurrence in the order of targets
+pub(crate) struct RegionSummary {
+    value_field: ValueField,
+    /// Number of rows of a whole target region, to count rows left out (e.g. by --missing-policy skip-row) as missing
+    n_region_rows: Option<usize>,
+    lines: Vec<String>,
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(|a, b| a.partial_cmp(b).expect("finite values"));
+    if values.is_empty() { None } else { Some(quantile(values, 0.5)) }
+}
+
+impl RegionSummary {
+    pub(crate) fn new(value_field: ValueField, n_region_rows: Option<usize>) -> Self {
+        Self { value_field, n_region_rows, lines: Vec::new() }
+    }
+
+    /// Summarize rows of an occurrence on both relative strands.
+    /// The enrichment is the target mean over the mean of upstream and downstream values pooled.
+    pub(crate) fn add(&mut self, key: &IpdSummaryKey, rows: &[TargetIpdRich]) {
+        let first = match rows.first() {
+            Some(first) => first,
+            None => return,
+        };
+        let (mut target, mut upstream, mut downstream) = (Vec::new(), Vec::new(), Vec::new());
+        for row in rows.iter() {
+            let value = match row.value_of(self.value_field) {
+                Some(value) if !row.missing && value.is_finite() => value as f64,
+                _ => continue,
+            };
+            match row.region.as_str() {
+                "Upstream" => upstream.push(value),
+                "Downstream" => downstream.push(value),
+                _ => target.push(value),
+            }
+        }
+        let flank_mean = mean(&[upstream.as_slice(), downstream.as_slice()].concat());
+        let target_mean = mean(&target);
+        let enrichment = match (target_mean, flank_mean) {
+            (Some(target_mean), Some(flank_mean)) if flank_mean != 0.0 => Some(target_mean / flank_mean),
+            _ => None,
+        };
+        let min_coverage = rows.iter().map(|row| row.coverage).min().unwrap_or(0);
+        let n_left_out = self.n_region_rows.map_or(0, |n| n.saturating_sub(rows.len()));
+        let n_missing = rows.iter().filter(|row| row.missing).count() + n_left_out;
+        self.lines.push(format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            first.src, first.format_occ_id(), key.refName, key.tpl, if key.strand == 0 { '+' } else { '-' },
+            format_stat(target_mean), format_stat(median(&mut target)),
+            format_stat(mean(&upstream)), format_stat(median(&mut upstream)),
+            format_stat(mean(&downstream)), format_stat(median(&mut downstream)),
+            format_stat(enrichment), min_coverage, n_missing));
+    }
+
+    /// Write a CSV with one row per occurrence; statistics without values are NA
+    pub(crate) fn write(&self, path: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        write_atomically(path, |output| {
+            writeln!(output, "{}", HEADER)?;
+            for line in self.lines.iter() {
+                writeln!(output, "{}", line)?;
+            }
+            Ok(())
+        })?;
+        manifest.add_file(path, "region_summary", self.lines.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_median1() {
+        let mut values = [3.0, 1.0, 2.0, 10.0];
+        assert_eq!(mean(&values), Some(4.0));
+        assert_eq!(median(&mut values), Some(2.5));
+        assert_eq!(mean(&[]), None);
+        assert_eq!(median(&mut []), None);
+    }
+}
@@ -0,0 +1,318 @@
+//! Coordinate-sorted, bgzip-compressed TSV output with a tabix index
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+use crate::{Column, Layout, OutputOptions, ValueField, ValueFormat};
+use crate::output::Compression;
+use crate::sink::{OutputSink, TargetKinetics};
+
+/// Maximum number of uncompressed bytes in a BGZF block, as in htslib
+const MAX_BLOCK_DATA: usize = 0xff00;
+/// Size of a linear index window (16 kbp)
+const LINEAR_SHIFT: u32 = 14;
+/// Maximum memory taken by rows kept by `TabixSink` until they are sorted
+const MAX_BUFFERED_BYTES: u64 = 4 << 30;
+
+/// Writer of the BGZF format, i.e. gzip members of at most 64 KiB with the block size in an extra field
+pub(crate) struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    level: flate2::Compression,
+    /// Offset of the current block in the compressed stream
+    block_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub(crate) fn new(inner: W, level: flate2::Compression) -> Self {
+        Self { inner, buffer: Vec::with_capacity(MAX_BLOCK_DATA), level, block_offset: 0 }
+    }
+
+    /// Virtual file offset of the next byte to be written
+    pub(crate) fn virtual_offset(&self) -> u64 {
+        (self.block_offset << 16) | self.buffer.len() as u64
+    }
+
+    fn write_block(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.level);
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(data);
+        // header (18 bytes) + compressed data + CRC32 and ISIZE (8 bytes)
+        let block_size = compressed.len() + 26;
+        let mut header = vec![31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0];
+        header.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.block_offset += block_size as u64;
+        Ok(())
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let data = std::mem::take(&mut self.buffer);
+            self.write_block(&data)?;
+            self.buffer = data;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Write remaining data and the empty end-of-file block
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.flush_buffer()?;
+        self.write_block(&[])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(MAX_BLOCK_DATA - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() >= MAX_BLOCK_DATA {
+            self.flush_buffer()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+}
+
+/// Bin of the UCSC binning scheme for a 0-based half-open interval
+fn reg2bin(begin: u64, end: u64) -> u32 {
+    let end = end - 1;
+    for (shift, offset) in [(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if begin >> shift == end >> shift {
+            return (offset + (begin >> shift)) as u32;
+        }
+    }
+    0
+}
+
+/// Tabix index of one reference sequence
+#[derive(Default)]
+struct ReferenceIndex {
+    /// Chunks of virtual offsets for each bin
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    /// Smallest virtual offset of records overlapping each 16 kbp window
+    linear: Vec<Option<u64>>,
+}
+
+impl ReferenceIndex {
+    /// Add a record covering a 0-based half-open interval and stored between two virtual offsets
+    fn add(&mut self, begin: u64, end: u64, chunk_begin: u64, chunk_end: u64) {
+        let chunks = self.bins.entry(reg2bin(begin, end)).or_default();
+        match chunks.last_mut() {
+            Some(last) if last.1 == chunk_begin => last.1 = chunk_end,
+            _ => chunks.push((chunk_begin, chunk_end)),
+        }
+        let (first_window, last_window) = ((begin >> LINEAR_SHIFT) as usize, ((end - 1) >> LINEAR_SHIFT) as usize);
+        if self.linear.len() <= last_window {
+            self.linear.resize(last_window + 1, None);
+        }
+        for window in first_window..=last_window {
+            self.linear[window].get_or_insert(chunk_begin);
+        }
+    }
+}
+
+/// Serialize a tabix index (before BGZF compression) of the generic format with 1-based positions
+fn tabix_index_bytes(names: &[String], references: &[ReferenceIndex], column_sequence: i32, column_begin: i32) -> Vec<u8> {
+    let mut bytes = b"TBI\x01".to_vec();
+    let int = |bytes: &mut Vec<u8>, value: i32| bytes.extend_from_slice(&value.to_le_bytes());
+    int(&mut bytes, names.len() as i32);
+    // format: generic; sequence, begin and end columns (1-based, 0 = no end column); meta character; lines to skip
+    for value in [0, column_sequence, column_begin, 0, b'#' as i32, 0] {
+        int(&mut bytes, value);
+    }
+    let concatenated_names = names.iter().flat_map(|name| name.bytes().chain(std::iter::once(0))).collect::<Vec<u8>>();
+    int(&mut bytes, concatenated_names.len() as i32);
+    bytes.extend_from_slice(&concatenated_names);
+    for reference in references {
+        int(&mut bytes, reference.bins.len() as i32);
+        for (bin, chunks) in reference.bins.iter() {
+            bytes.extend_from_slice(&bin.to_le_bytes());
+            int(&mut bytes, chunks.len() as i32);
+            for (chunk_begin, chunk_end) in chunks {
+                bytes.extend_from_slice(&chunk_begin.to_le_bytes());
+                bytes.extend_from_slice(&chunk_end.to_le_bytes());
+            }
+        }
+        int(&mut bytes, reference.linear.len() as i32);
+        // windows without records take the offset of the previous window, as htslib does
+        let mut previous_offset = 0;
+        for offset in reference.linear.iter() {
+            previous_offset = offset.unwrap_or(previous_offset);
+            bytes.extend_from_slice(&previous_offset.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Path of the tabix index of an output
+pub(crate) fn index_path<P: AsRef<Path>>(output_path: P) -> PathBuf {
+    let mut path = output_path.as_ref().as_os_str().to_owned();
+    path.push(".tbi");
+    PathBuf::from(path)
+}
+
+/// Sink keeping all rows in memory, and writing them sorted by ref_chr and ref_position
+/// into a bgzip-compressed TSV with a tabix index.
+/// Rows of target regions are not sorted by reference coordinates (e.g. overlapping or minus-strand regions),
+/// so the whole output is held as text lines, which take roughly the size of the uncompressed TSV;
+/// an output larger than `MAX_BUFFERED_BYTES` is refused.
+pub(crate) struct TabixSink {
+    path: PathBuf,
+    index_path: PathBuf,
+    level: flate2::Compression,
+    /// Comment lines and the header line, starting with '#'
+    preamble: String,
+    /// 1-based column numbers of ref_chr and ref_position
+    coordinate_columns: (i32, i32),
+    columns: Vec<Column>,
+    value_fields: Vec<ValueField>,
+    value_format: ValueFormat,
+    /// Values of constant columns written first in each row, indexed by column and then by sample
+    constant_values: Vec<Vec<String>>,
+    /// (ref_chr, ref_position, line)
+    rows: Vec<(String, i64, String)>,
+    /// Approximate memory taken by `rows`
+    buffered_bytes: u64,
+    /// Number of rows before position 1 left out, which tabix cannot index
+    n_dropped: u64,
+}
+
+impl TabixSink {
+    /// Rows are written into `path` and its index into `index_path`
+    pub(crate) fn create<P: AsRef<Path>>(path: P, index_path: P, output_options: &OutputOptions) -> Result<Self, Box<dyn Error>> {
+        if !matches!(output_options.layout, Layout::Long) {
+            return Err("Tabix output is only supported for the long layout".into());
+        }
+        let level = match (output_options.compression, output_options.compression_level) {
+            (Compression::Zstd, _) => return Err("Tabix output is compressed only with bgzip".into()),
+            (_, Some(level)) => flate2::Compression::new(level as u32),
+            _ => flate2::Compression::default(),
+        };
+        let header = output_options.header(0, 0);
+        // 1-based column numbers
+        let column_number = |name: &str| header.iter().position(|h| h == name).map(|i| i as i32 + 1);
+        let coordinate_columns = match (column_number("ref_chr"), column_number("ref_position")) {
+            (Some(sequence), Some(begin)) => (sequence, begin),
+            _ => return Err("Tabix output requires the ref_chr and ref_position columns".into()),
+        };
+        let mut preamble = output_options.provenance.as_ref().map(|p| p.comment_lines()).unwrap_or_default();
+        preamble.push('#');
+        preamble.push_str(&header.join("\t"));
+        preamble.push('\n');
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            index_path: index_path.as_ref().to_path_buf(),
+            level,
+            preamble,
+            coordinate_columns,
+            columns: output_options.columns.clone(),
+            value_fields: output_options.value_fields.clone(),
+            value_format: output_options.value_format.clone(),
+            constant_values: output_options.constant_columns().into_iter().map(|(_, value)| value).collect(),
+            rows: Vec::new(),
+            buffered_bytes: 0,
+            n_dropped: 0,
+        })
+    }
+}
+
+impl OutputSink for TabixSink {
+    fn write_batch(&mut self, batch: Vec<TargetKinetics>) -> Result<u64, Box<dyn Error>> {
+        let mut n_rows = 0;
+        for target in batch.iter().flat_map(|(_, target_vals)| target_vals.iter()) {
+            // rows before the chromosome start, e.g. by --edge-policy pad-na, have no tabix coordinate
+            if target.ref_position < 1 {
+                self.n_dropped += 1;
+                continue;
+            }
+            let mut fields = self.constant_values.iter().map(|values| values[target.sample].clone()).collect::<Vec<_>>();
+            fields.extend(target.to_record(&self.columns, &self.value_fields, &self.value_format));
+            let line = fields.join("\t");
+            self.buffered_bytes += (std::mem::size_of::<(String, i64, String)>() + target.ref_chr.len() + line.len()) as u64;
+            if self.buffered_bytes > MAX_BUFFERED_BYTES {
+                return Err(format!("Tabix output keeps all rows in memory for sorting, and they exceed {} GiB; \
+                    write a CSV output instead and sort and index it with external tools", MAX_BUFFERED_BYTES >> 30).into());
+            }
+            self.rows.push((target.ref_chr.clone(), target.ref_position, line));
+            n_rows += 1;
+        }
+        Ok(n_rows)
+    }
+
+    /// Write the sorted rows and the index into their paths, which are persisted by the caller in this order
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        if self.n_dropped > 0 {
+            eprintln!("[WARNING] Left out {} rows before the chromosome start from the tabix output", self.n_dropped);
+        }
+        let (column_sequence, column_begin) = self.coordinate_columns;
+        // stable sort keeps the order of strands and target regions at the same position
+        self.rows.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        let mut writer = BgzfWriter::new(BufWriter::new(File::create(&self.path)?), self.level);
+        writer.write_all(self.preamble.as_bytes())?;
+        let mut names: Vec<String> = Vec::new();
+        let mut references: Vec<ReferenceIndex> = Vec::new();
+        for (chr, position, line) in self.rows.iter() {
+            if names.last() != Some(chr) {
+                names.push(chr.clone());
+                references.push(ReferenceIndex::default());
+            }
+            let chunk_begin = writer.virtual_offset();
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            let begin = *position as u64 - 1;
+            references.last_mut().expect("a reference was pushed").add(begin, begin + 1, chunk_begin, writer.virtual_offset());
+        }
+        writer.finish()?;
+
+        let index = tabix_index_bytes(&names, &references, column_sequence, column_begin);
+        let mut index_writer = BgzfWriter::new(BufWriter::new(File::create(&self.index_path)?), self.level);
+        index_writer.write_all(&index)?;
+        index_writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn reg2bin1() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(16384, 16385), 4682);
+        assert_eq!(reg2bin(16383, 16385), 585);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+    }
+
+    #[test]
+    fn bgzf_is_multi_member_gzip() {
+        let data = (0..100000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut writer = BgzfWriter::new(Vec::new(), flate2::Compression::default());
+        writer.write_all(&data).unwrap();
+        assert_eq!(writer.virtual_offset() & 0xffff, (data.len() - MAX_BLOCK_DATA) as u64);
+        let compressed = writer.finish().unwrap();
+        let mut decompressed = Vec::new();
+        flate2::read::MultiGzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+        // the empty end-of-file block
+        assert_eq!(&compressed[compressed.len() - 28..compressed.len() - 24], &[31, 139, 8, 4]);
+    }
+}
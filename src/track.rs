@@ -0,0 +1,86 @@
+//! Stranded bedGraph tracks of collected values at their reference coordinates
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use clap::ArgEnum;
+use crate::TargetIpdRich;
+use crate::manifest::Manifest;
+use crate::output::write_atomically;
+
+/// Kinetics field written into tracks
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub(crate) enum TrackValue {
+    #[clap(name = "tMean")]
+    TMean,
+    #[clap(name = "ipdRatio")]
+    IpdRatio,
+}
+
+/// Values of covered positions collected for each reference strand.
+/// A position shared by overlapping target regions is written only once.
+pub(crate) struct BedGraphTracks {
+    value: TrackValue,
+    /// (chromosome, 1-based position) -> value
+    plus: BTreeMap<(String, i64), f32>,
+    minus: BTreeMap<(String, i64), f32>,
+}
+
+impl BedGraphTracks {
+    pub(crate) fn new(value: TrackValue) -> Self {
+        Self { value, plus: BTreeMap::new(), minus: BTreeMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, row: &TargetIpdRich) {
+        if row.coverage == 0 {
+            return;
+        }
+        let value = match self.value {
+            TrackValue::TMean => row.value,
+            TrackValue::IpdRatio => row.ipdRatio,
+        };
+        let track = if row.ref_strand == 0 { &mut self.plus } else { &mut self.minus };
+        track.insert((row.ref_chr.clone(), row.ref_position), value);
+    }
+
+    /// Write `<prefix>.plus.bedGraph` and `<prefix>.minus.bedGraph` sorted by chromosome and position.
+    /// They can be converted into bigWig with UCSC `bedGraphToBigWig`.
+    pub(crate) fn write(&self, prefix: &str, manifest: &mut Manifest) -> Result<(), Box<dyn Error>> {
+        for (strand_name, track) in [("plus", &self.plus), ("minus", &self.minus)] {
+            let path = format!("{}.{}.bedGraph", prefix, strand_name);
+            write_atomically(&path, |output| {
+                for ((chr, position), value) in track {
+                    // bedGraph: 0-based start, exclusive end
+                    writeln!(output, "{}\t{}\t{}\t{}", chr, position - 1, position, value)?;
+                }
+                Ok(())
+            })?;
+            manifest.add_file(&path, "bedgraph", track.len() as u64);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpdSummaryValue, RegionSpec};
+    use crate::tests::{alternating_strand_rows, temporary_file};
+
+    #[test]
+    fn bedgraph_tracks1() {
+        let (plus, minus) = (temporary_file("tracks.plus.bedGraph"), temporary_file("tracks.minus.bedGraph"));
+        // the plus row at position 2 has no kinetics data
+        let rows = alternating_strand_rows(4, &RegionSpec::new(1, 1), |j| {
+            (j != 2).then(|| IpdSummaryValue { tMean: 1.0, ipdRatio: j as f32 + 1.5, coverage: 10, ..Default::default() })
+        });
+        let mut tracks = BedGraphTracks::new(TrackValue::IpdRatio);
+        rows.iter().for_each(|row| tracks.add(row));
+        let prefix = plus.path().to_str().unwrap().strip_suffix(".plus.bedGraph").unwrap();
+        tracks.write(prefix, &mut Manifest::new()).unwrap();
+        let read = |path: &std::path::Path| std::fs::read_to_string(path).unwrap();
+        // 0-based starts of 1-based positions chr:101 and chr:102
+        assert_eq!(read(plus.path()), "chr\t100\t101\t1.5\n");
+        assert_eq!(read(minus.path()), "chr\t100\t101\t2.5\nchr\t101\t102\t4.5\n");
+    }
+}
@@ -0,0 +1,216 @@
+//! Checks of occ and kinetics inputs before a long run, reported as JSON without writing any output
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use serde_json::{json, Value};
+use crate::{has_occ_header, open_without_bom, ChrKineticsHdf5, IpdSummary, MergedOcc};
+use crate::chrom_filter::ChromFilter;
+use crate::chrom_sizes::ChromSizes;
+
+/// Columns of kinetics CSV read by the main command
+const KINETICS_COLUMNS: [&str; 13] = ["refName", "tpl", "strand", "base", "score", "tMean", "tErr", "modelPrediction", "ipdRatio",
+    "coverage", "frac", "fracLow", "fracUp"];
+
+/// Number of line numbers reported for each kind of problem
+const MAX_LINES: usize = 5;
+
+/// Numbers of problems of each kind with the first few line numbers of them
+#[derive(Debug, Default)]
+struct Problems(BTreeMap<&'static str, (u64, Vec<u64>)>);
+
+impl Problems {
+    fn add(&mut self, kind: &'static str, line: u64) {
+        let (count, lines) = self.0.entry(kind).or_default();
+        *count += 1;
+        if lines.len() < MAX_LINES {
+            lines.push(line);
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.0.values().map(|(count, _)| count).sum()
+    }
+
+    fn to_json(&self) -> Value {
+        self.0.iter().map(|(kind, (count, lines))| (kind.to_string(), json!({ "count": count, "first_lines": lines })))
+            .collect::<serde_json::Map<_, _>>().into()
+    }
+}
+
+/// Numbers of records on each chromosome of an input and its problems
+#[derive(Debug, Default)]
+struct InputCheck {
+    n_records: u64,
+    chromosomes: BTreeMap<String, u64>,
+    problems: Problems,
+}
+
+/// Check coordinates, strands, and duplicates of occurrences
+fn check_occ(path: &str, chrom_sizes: Option<&ChromSizes>) -> Result<InputCheck, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b' ')
+        .has_headers(has_occ_header(path)?)
+        .comment(Some(b'#'))
+        .from_reader(open_without_bom(path)?);
+    let mut check = InputCheck::default();
+    let mut seen = HashSet::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => record.position().map_or(0, |p| p.line()),
+            Err(e) => {
+                check.problems.add("malformed", e.position().map_or(0, |p| p.line()));
+                continue;
+            },
+        };
+        check.n_records += 1;
+        let occ = match record.deserialize::<MergedOcc>(None) {
+            Ok(occ) => occ,
+            Err(_) => {
+                check.problems.add("malformed", line);
+                continue;
+            },
+        };
+        match occ.strand {
+            '+' | '-' => {},
+            '.' => check.problems.add("unknown_strand", line),
+            _ => check.problems.add("invalid_strand", line),
+        }
+        if occ.start < 0 {
+            check.problems.add("negative_start", line);
+        }
+        match chrom_sizes.map(|sizes| sizes.length(&occ.refName)) {
+            Some(None) => check.problems.add("unknown_chromosome", line),
+            Some(Some(length)) if occ.start >= length => check.problems.add("start_beyond_chromosome_end", line),
+            _ => {},
+        }
+        if !seen.insert((occ.refName.clone(), occ.start, occ.strand)) {
+            check.problems.add("duplicate", line);
+        }
+        *check.chromosomes.entry(occ.refName).or_default() += 1;
+    }
+    Ok(check)
+}
+
+/// Check columns, values, strands, and the ordering (contiguous chromosomes and increasing positions) of kinetics CSV;
+/// returns missing columns with the check
+fn check_kinetics_csv(path: &str) -> Result<(Vec<&'static str>, InputCheck), Box<dyn Error>> {
+    let mut reader = csv::Reader::from_reader(open_without_bom(path)?);
+    let headers = reader.headers()?.clone();
+    let missing_columns = KINETICS_COLUMNS.iter().copied().filter(|column| !headers.iter().any(|h| h == *column)).collect::<Vec<_>>();
+    let mut check = InputCheck::default();
+    // the current chromosome with its last position and strand, and the chromosomes before it
+    let mut current: Option<(String, i64, u8)> = None;
+    let mut finished = HashSet::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => record.position().map_or(0, |p| p.line()),
+            Err(e) => {
+                check.problems.add("malformed", e.position().map_or(0, |p| p.line()));
+                continue;
+            },
+        };
+        check.n_records += 1;
+        let summary = match record.deserialize::<IpdSummary>(Some(&headers)) {
+            Ok(summary) => summary,
+            Err(_) => {
+                check.problems.add("malformed", line);
+                continue;
+            },
+        };
+        if summary.strand > 1 {
+            check.problems.add("invalid_strand", line);
+        }
+        match current.as_mut() {
+            Some((chr, tpl, strand)) if *chr == summary.refName => {
+                if (summary.tpl, summary.strand) <= (*tpl, *strand) {
+                    check.problems.add("unsorted_position", line);
+                }
+                *tpl = summary.tpl;
+                *strand = summary.strand;
+            },
+            _ => {
+                if let Some((chr, _, _)) = current.take() {
+                    finished.insert(chr);
+                }
+                if finished.contains(&summary.refName) {
+                    check.problems.add("chromosome_not_contiguous", line);
+                }
+                current = Some((summary.refName.clone(), summary.tpl, summary.strand));
+            },
+        }
+        *check.chromosomes.entry(summary.refName).or_default() += 1;
+    }
+    Ok((missing_columns, check))
+}
+
+/// Numbers of rows of chromosomes in kinetics HDF5, whose schema is checked as it is loaded
+fn check_kinetics_hdf5(path: &str) -> Result<InputCheck, Box<dyn Error>> {
+    let kinetics = ChrKineticsHdf5::kinetics_datasets_from_hdf5_path(path, &ChromFilter::default(), false)?;
+    let chromosomes = kinetics.iter().map(|(chr, data)| (chr.clone(), data.coverage.len() as u64)).collect::<BTreeMap<_, _>>();
+    Ok(InputCheck { n_records: chromosomes.values().sum(), chromosomes, problems: Problems::default() })
+}
+
+/// Print a JSON report of checks of the inputs to stdout, and return an error if any problem is found
+pub(crate) fn validate(occ_path: &str, kinetics_path: Option<&str>, kinetics_hdf5_path: Option<&str>, chrom_sizes: Option<&ChromSizes>) -> Result<(), Box<dyn Error>> {
+    let occ = check_occ(occ_path, chrom_sizes)?;
+    let (format, kinetics_input, missing_columns, kinetics) = match (kinetics_path, kinetics_hdf5_path) {
+        (Some(path), _) => {
+            let (missing_columns, check) = check_kinetics_csv(path)?;
+            ("csv", path, missing_columns, check)
+        },
+        (None, Some(path)) => ("hdf5", path, Vec::new(), check_kinetics_hdf5(path)?),
+        (None, None) => return Err("Either kinetics CSV or HDF5 is required".into()),
+    };
+    let occ_only = occ.chromosomes.iter().filter(|(chr, _)| !kinetics.chromosomes.contains_key(*chr))
+        .map(|(chr, n)| (chr.clone(), Value::from(*n))).collect::<serde_json::Map<_, _>>();
+    let shared = occ.chromosomes.keys().filter(|chr| kinetics.chromosomes.contains_key(*chr)).collect::<Vec<_>>();
+    let n_problems = occ.problems.count() + kinetics.problems.count() + missing_columns.len() as u64 + occ_only.len() as u64;
+    let report = json!({
+        "occ": {
+            "path": occ_path,
+            "records": occ.n_records,
+            "chromosomes": occ.chromosomes,
+            "problems": occ.problems.to_json(),
+        },
+        "kinetics": {
+            "path": kinetics_input,
+            "format": format,
+            "records": kinetics.n_records,
+            "missing_columns": missing_columns,
+            "chromosomes": kinetics.chromosomes,
+            "problems": kinetics.problems.to_json(),
+        },
+        "chromosomes": {
+            "shared": shared,
+            // with numbers of occurrences on them
+            "occ_only": occ_only,
+        },
+        "problems": n_problems,
+    });
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if n_problems > 0 {
+        return Err(format!("Found {} problems in the inputs", n_problems).into());
+    }
+    eprintln!("[INFO] No problem found in the inputs");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_occ1() {
+        let occ_file = crate::tests::temporary_file("validate.occ");
+        std::fs::write(occ_file.path(), "chr1 10 +\nchr1 10 +\nchr1 x +\nchr2 5 *\nchr1 -1 -\n").unwrap();
+        let check = check_occ(occ_file.path().to_str().unwrap(), None).unwrap();
+        assert_eq!(check.n_records, 5);
+        assert_eq!(check.chromosomes, BTreeMap::from([("chr1".to_string(), 3), ("chr2".to_string(), 1)]));
+        let problems = check.problems.0.iter().map(|(kind, (count, lines))| (*kind, *count, lines.clone())).collect::<Vec<_>>();
+        assert_eq!(problems, [("duplicate", 1, vec![2]), ("invalid_strand", 1, vec![4]), ("malformed", 1, vec![3]), ("negative_start", 1, vec![5])]);
+    }
+}
@@ -0,0 +1,50 @@
+//! Genome-wide tiling of chromosomes into sliding windows, collected as occurrences without an occ file
+
+use crate::MergedOcc;
+
+/// Windows of `width` bases starting every `step` bases
+#[derive(Debug, Clone)]
+pub(crate) struct Windows {
+    pub(crate) width: i64,
+    pub(crate) step: i64,
+    /// Only chromosomes of these names if not empty
+    pub(crate) chromosomes: Vec<String>,
+}
+
+impl Windows {
+    /// Every base of the selected chromosomes as a window, to export their whole kinetics
+    pub(crate) fn bases(chromosomes: Vec<String>) -> Self {
+        Self { width: 1, step: 1, chromosomes }
+    }
+
+    /// Plus-strand occurrences of windows on each of `chromosomes` (names and lengths), indexed in order;
+    /// a window is tiled only if it fits in its chromosome
+    pub(crate) fn tile(&self, chromosomes: &[(String, i64)]) -> Vec<(usize, MergedOcc)> {
+        for chr in self.chromosomes.iter().filter(|chr| !chromosomes.iter().any(|(name, _)| name == *chr)) {
+            eprintln!("[WARNING] Chromosome {} has no kinetics data", chr);
+        }
+        let selected = chromosomes.iter().filter(|(chr, _)| self.chromosomes.is_empty() || self.chromosomes.contains(chr)).collect::<Vec<_>>();
+        let occs = selected.iter().flat_map(|(chr, length)| {
+            let n = if *length >= self.width { (length - self.width) / self.step + 1 } else { 0 };
+            (0..n).map(move |k| MergedOcc { refName: chr.clone(), start: k * self.step, strand: '+', strand_assumed: false, overlap_count: 0 })
+        }).enumerate().collect::<Vec<_>>();
+        eprintln!("[INFO] Tiled {} windows of {} bp every {} bp over {} chromosomes", occs.len(), self.width, self.step, selected.len());
+        occs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile1() {
+        let chromosomes = [("chr1".to_string(), 10), ("chr2".to_string(), 3)];
+        let windows = Windows { width: 4, step: 3, chromosomes: Vec::new() };
+        let occs = windows.tile(&chromosomes);
+        assert_eq!(occs.iter().map(|(i, occ)| (*i, occ.start)).collect::<Vec<_>>(), [(0, 0), (1, 3), (2, 6)]);
+        assert!(occs.iter().all(|(_, occ)| occ.refName == "chr1" && occ.strand == '+'));
+        let bases = Windows::bases(vec!["chr2".to_string()]).tile(&chromosomes);
+        assert_eq!(bases.iter().map(|(_, occ)| (occ.refName.as_str(), occ.start)).collect::<Vec<_>>(), [("chr2", 0), ("chr2", 1), ("chr2", 2)]);
+    }
+}